@@ -6,3 +6,20 @@ class MxRequest:
 
     def fetch(url: str, context: Optional[Dict[str, any]]) -> bytes:
         pass
+
+
+class MxContext:
+    """
+    Optional third argument of mx_get_urls and mx_get_book
+    """
+
+    term: str
+
+    def log(level: str, message: str) -> None:
+        """level is one of trace, debug, info, warn, error"""
+        pass
+
+    def progress(
+        current: int, total: Optional[int] = None, label: Optional[str] = None
+    ) -> None:
+        pass
@@ -0,0 +1,292 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::{DateTime, Local};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+
+use crate::{
+    core::{
+        cache::{files::FileCacheStorage, sqlite::SqliteCacheStorage, CacheStorage},
+        utils,
+    },
+    schemas::{
+        cache::{CacheEntry, Freshness},
+        config::Config,
+        watch::Interval,
+    },
+    GLOBAL_CONFIG, PLUGIN_MANAGER,
+};
+
+#[derive(Parser, Debug)]
+pub struct QueryCache {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// List cached terms with their age and whether they would be fetched again
+    List {
+        /// Only list the entries of a plugin
+        #[arg(long, short)]
+        plugin: Option<String>,
+        /// Only list the books with this tag
+        #[arg(long, short)]
+        tag: Option<String>,
+        /// Only list expired or outdated entries
+        #[arg(long)]
+        stale: bool,
+    },
+    /// Display the cached book of a term
+    Show {
+        term: String,
+        /// Plugin that fetched the term, any by default
+        #[arg(long, short)]
+        plugin: Option<String>,
+    },
+    /// Remove the entries of some terms, of a plugin or older than a given age
+    Invalidate(CacheInvalidate),
+    /// Remove expired, outdated and unreadable entries
+    Prune,
+    /// Import the JSON files of `cache.folder` into the SQLite backend
+    Migrate {
+        /// Remove each JSON file once imported
+        #[arg(long)]
+        remove_files: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("target")
+        .required(true)
+        .multiple(true)
+        .args(["terms", "plugin", "older_than"])
+))]
+pub struct CacheInvalidate {
+    pub terms: Vec<String>,
+    /// Only the entries of this plugin
+    #[arg(long, short)]
+    pub plugin: Option<String>,
+    /// Only the entries fetched before this age (e.g. 12h, 7d)
+    #[arg(long)]
+    pub older_than: Option<Interval>,
+}
+
+/// Entries selected by terms, plugin and age, every filter is optional
+struct EntryFilter {
+    terms: Vec<String>,
+    plugin: Option<String>,
+    older_than: Option<Duration>,
+    /// Signatures of `terms` for every candidate plugin, legacy entries are only known by it
+    signatures: HashSet<String>,
+}
+
+impl QueryCache {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let config = { GLOBAL_CONFIG.read().unwrap().clone() };
+        let storage = config.get_cache_storage();
+        let now = Local::now();
+
+        match &self.command {
+            CacheCommand::List { plugin, tag, stale } => {
+                let filter = EntryFilter::new(vec![], plugin.clone(), None).await;
+                let entries = match tag {
+                    Some(tag) => storage
+                        .find_by_tag(tag)?
+                        .into_iter()
+                        .map(|(signature, entry)| (signature, Ok(entry)))
+                        .collect(),
+                    None => storage.entries()?,
+                };
+                let mut count = 0;
+                for (signature, entry) in entries {
+                    match entry {
+                        Ok(entry) if filter.matches(&signature, &entry, now) => {
+                            let freshness = freshness_of(&config, &entry, now).await;
+                            if !stale || freshness != Freshness::Fresh {
+                                display_entry(&entry, freshness, now);
+                                count += 1;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) if plugin.is_none() => {
+                            println!("{signature}: unreadable, {e:#}");
+                            count += 1;
+                        }
+                        Err(_) => {}
+                    }
+                }
+                println!(
+                    "{count} cache entr{} in {}",
+                    plural(count),
+                    storage.location()
+                );
+            }
+            CacheCommand::Show { term, plugin } => {
+                let filter = EntryFilter::new(vec![term.clone()], plugin.clone(), None).await;
+                let mut found = false;
+                for (signature, entry) in storage.entries()? {
+                    let Ok(entry) = entry else {
+                        continue;
+                    };
+                    if filter.matches(&signature, &entry, now) {
+                        let freshness = freshness_of(&config, &entry, now).await;
+                        display_entry(&entry, freshness, now);
+                        println!("  signature: {signature}");
+                        println!("{}\n", entry.book.resume());
+                        found = true;
+                    }
+                }
+                if !found {
+                    anyhow::bail!("{term} is not cached");
+                }
+            }
+            CacheCommand::Invalidate(invalidate) => {
+                let filter = EntryFilter::new(
+                    invalidate.terms.clone(),
+                    invalidate.plugin.clone(),
+                    invalidate.older_than.map(|age| age.0),
+                )
+                .await;
+                let mut removed = 0;
+                for (signature, entry) in storage.entries()? {
+                    if entry.is_ok_and(|entry| filter.matches(&signature, &entry, now)) {
+                        storage.remove(&signature)?;
+                        removed += 1;
+                    }
+                }
+                println!("{removed} cache entr{} removed", plural(removed));
+            }
+            CacheCommand::Prune => {
+                let mut removed = 0;
+                for (signature, entry) in storage.entries()? {
+                    let stale = match entry {
+                        Ok(entry) => freshness_of(&config, &entry, now).await != Freshness::Fresh,
+                        Err(_) => true,
+                    };
+                    if stale {
+                        storage.remove(&signature)?;
+                        removed += 1;
+                    }
+                }
+                println!("{removed} cache entr{} removed", plural(removed));
+            }
+            CacheCommand::Migrate { remove_files } => {
+                let Some(file) = config.cache.database_file() else {
+                    anyhow::bail!("Migrating requires `cache.backend` to use sqlite");
+                };
+                let database = SqliteCacheStorage::new(file);
+                let files = FileCacheStorage::new(config.cache.folder.clone());
+                let migration = database.migrate_from(&files, *remove_files)?;
+                for (signature, e) in &migration.skipped {
+                    eprintln!("{signature}: skipped, {e:#}");
+                }
+                println!(
+                    "{} cache entr{} imported into {}, {} skipped",
+                    migration.imported,
+                    plural(migration.imported),
+                    database.location(),
+                    migration.skipped.len()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EntryFilter {
+    async fn new(terms: Vec<String>, plugin: Option<String>, older_than: Option<Duration>) -> Self {
+        let plugins = match &plugin {
+            Some(plugin) => vec![plugin.clone()],
+            None => PLUGIN_MANAGER.read().await.list_plugins(),
+        };
+        let signatures = terms
+            .iter()
+            .flat_map(|term| {
+                plugins
+                    .iter()
+                    .map(|plugin| utils::compute_query_signature(term, plugin))
+            })
+            .collect();
+        Self {
+            terms,
+            plugin,
+            older_than,
+            signatures,
+        }
+    }
+
+    fn matches(&self, signature: &str, entry: &CacheEntry, now: DateTime<Local>) -> bool {
+        let by_signature = self.signatures.contains(signature);
+        let term = self.terms.is_empty() || self.terms.contains(&entry.term) || by_signature;
+        let plugin = match &self.plugin {
+            Some(plugin) => entry.plugin == *plugin || by_signature,
+            None => true,
+        };
+        let age = self
+            .older_than
+            .is_none_or(|older_than| entry.age(now) >= older_than);
+        term && plugin && age
+    }
+}
+
+/// Same check as when fetching, with the current version of the plugin
+async fn freshness_of(config: &Config, entry: &CacheEntry, now: DateTime<Local>) -> Freshness {
+    let version = match entry.plugin_version {
+        Some(_) => {
+            PLUGIN_MANAGER
+                .read()
+                .await
+                .plugin_version(&entry.plugin)
+                .await
+        }
+        None => None,
+    };
+    entry.freshness(config.cache.ttl_for(&entry.plugin), version.as_deref(), now)
+}
+
+fn display_entry(entry: &CacheEntry, freshness: Freshness, now: DateTime<Local>) {
+    let unknown = |s: &str| match s.is_empty() {
+        true => "?".to_string(),
+        false => s.to_string(),
+    };
+    println!(
+        "{} ({} chapter(s))",
+        entry.book.title,
+        entry.book.chapters.len()
+    );
+    println!(
+        "  {} [{}{}] {freshness}, fetched {} ago ({})",
+        unknown(&entry.term),
+        unknown(&entry.plugin),
+        entry
+            .plugin_version
+            .as_ref()
+            .map(|version| format!(" {version}"))
+            .unwrap_or_default(),
+        display_age(entry.age(now)),
+        entry.fetched_at.format("%Y-%m-%d %H:%M")
+    );
+}
+
+/// Largest whole unit, e.g. `3h` or `2d`
+fn display_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    [
+        (7 * 24 * 60 * 60, "w"),
+        (24 * 60 * 60, "d"),
+        (60 * 60, "h"),
+        (60, "m"),
+    ]
+    .into_iter()
+    .find(|(unit, _)| secs >= *unit)
+    .map(|(unit, name)| format!("{}{name}", secs / unit))
+    .unwrap_or_else(|| format!("{secs}s"))
+}
+
+fn plural(count: usize) -> &'static str {
+    match count {
+        1 => "y",
+        _ => "ies",
+    }
+}
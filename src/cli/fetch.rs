@@ -4,18 +4,18 @@ use clap::{Args, Parser};
 use indexmap::{IndexMap, IndexSet};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use rand::{seq::SliceRandom, thread_rng};
-use std::{io::Write, path::PathBuf, str::FromStr};
+use std::{collections::HashMap, io::Write, path::PathBuf, str::FromStr};
 use url::Url;
 
 use crate::{
     cli::server::OneshotHttpListener,
     core::{
-        downloader::{batch_download, DownloadStatus},
+        downloader::{self, batch_download, DownloadStatus},
         http::{self, ContextProvider, FetchContext},
         utils,
     },
-    plugins::FetchResult,
-    schemas::config,
+    plugins::{FetchResult, PluginReporter},
+    schemas::{config, selection::NumberSelection},
     GLOBAL_CONFIG, PLUGIN_MANAGER,
 };
 
@@ -32,7 +32,7 @@ pub struct Auth {
     bearer: Option<String>,
 }
 
-#[derive(Args, Clone, Debug)]
+#[derive(Args, Clone, Debug, Default)]
 pub struct SharedFetchOption {
     /// Only fetch metadata
     #[arg(required = false, long, short)]
@@ -73,11 +73,27 @@ pub struct SharedFetchOption {
     /// Use the downloader associated with the plugin
     #[arg(long, short = 'd')]
     pub custom_downloader: bool,
+    /// Fix the extension of pages whose bytes or `Content-Type` tell another format
+    #[arg(required = false, long)]
+    pub fix_extensions: bool,
     #[command(flatten)]
     pub auth: Option<Auth>,
     /// Wait for cookies sent from a callback
     #[arg(long, short = 'l')]
     pub listen_cookies: bool,
+    /// Download pages as soon as the plugin yields them
+    #[arg(required = false, long)]
+    pub stream: bool,
+    /// Print which plugins claim each term and why, nothing is fetched
+    #[arg(required = false, long)]
+    pub explain: bool,
+    /// Only download these chapters by number, e.g. `1-10,15,latest:3`
+    #[arg(long, conflicts_with = "stream")]
+    pub chapters: Option<NumberSelection>,
+    /// Only download these pages of each chapter by number, same syntax as `--chapters`,
+    /// rejected for plugins that download whole chapters with the custom downloader
+    #[arg(long, conflicts_with = "stream")]
+    pub pages: Option<NumberSelection>,
 }
 
 #[derive(Parser, Debug)]
@@ -147,6 +163,16 @@ impl TermSequence {
             terms
         };
 
+        if self.flags.explain {
+            return explain_terms(&terms, self.flags.plugin.clone()).await;
+        }
+
+        if self.flags.stream && !self.flags.reflect {
+            let results = stream_terms(terms, self.flags.plugin.clone()).await?;
+            display_fetch_status(&results, self.flags.verbose);
+            return Ok(());
+        }
+
         let results = {
             match self.flags.plugin.clone() {
                 Some(name) => fetch_terms(terms, Some(name)).await,
@@ -228,6 +254,17 @@ impl FileSequence {
             terms
         };
 
+        if self.flags.explain {
+            return explain_terms(&terms, self.flags.plugin.clone()).await;
+        }
+
+        if self.flags.stream && !self.flags.reflect {
+            let mut results = stream_terms(terms, self.flags.plugin.clone()).await?;
+            results.extend(file_issues);
+            display_fetch_status(&results, self.flags.verbose);
+            return Ok(());
+        }
+
         let results = {
             let manager = PLUGIN_MANAGER.read().await;
             let mut results = match self.flags.plugin.clone() {
@@ -360,9 +397,72 @@ pub async fn fetch_terms(
     let mut results = IndexMap::new();
     let mut fetched_count = 0;
     let mut cached_count = 0;
+    let mut record = |term: &String, crawl_result: anyhow::Result<FetchResult>| {
+        local_pb.set_message(term.trim().to_string());
+        results.insert(
+            term.to_string(),
+            match crawl_result {
+                Ok(fetched) => {
+                    if fetched.cached {
+                        local_pb.set_message(format!("{} [cached]", term.to_string().trim()));
+                        cached_count += 1;
+                    } else {
+                        fetched_count += 1
+                    }
 
-    let batches = utils::batch_a_list_of(&terms, crawl_batch);
+                    status_pb.set_message(format!(
+                        "{fetched_count} fetched, {cached_count} cached, {} left",
+                        terms.len() - (fetched_count + cached_count)
+                    ));
+                    local_pb.inc(1);
+
+                    Resolution::Success(fetched.into())
+                }
+                Err(e) => Resolution::Fail(e),
+            },
+        );
+    };
+
+    // Plugins that can fetch many terms at once (e.g. gallery-dl) get them in one go
     let mut done = 0;
+    let (groups, terms_left) = {
+        let manager = PLUGIN_MANAGER.read().await;
+        manager.group_terms(&terms, plugin.as_deref()).await
+    };
+    for (plugin_name, group) in groups {
+        let group_pb = m.add(ProgressBar::new_spinner());
+        group_pb.set_style(spinner.clone());
+        group_pb.set_prefix(format!("  [{plugin_name}] {} terms", group.len()));
+        let reporter = PluginReporter::new(plugin_name.clone(), Some(group_pb));
+
+        let queries = group.iter().map(|(_, query)| query.clone()).collect();
+        let fetched = {
+            let manager = PLUGIN_MANAGER.read().await;
+            manager
+                .fetch_many(queries, plugin_name.clone(), reporter.clone())
+                .await
+        };
+        reporter.finish();
+
+        for ((term, _), crawl_result) in group.iter().zip(fetched) {
+            // Same as a single term, the next supporting plugin is given a chance
+            let crawl_result = match crawl_result {
+                Err(e) if plugin.is_none() => {
+                    let manager = PLUGIN_MANAGER.read().await;
+                    let reporter = PluginReporter::new(term.clone(), None);
+                    manager
+                        .fallthrough_fetch(term.clone(), reporter, vec![(plugin_name.clone(), e)])
+                        .await
+                }
+                crawl_result => crawl_result,
+            };
+            done += 1;
+            local_pb.set_prefix(format!("[{}/{}]", done, terms.len()));
+            record(term, crawl_result);
+        }
+    }
+
+    let batches = utils::batch_a_list_of(&terms_left, crawl_batch);
     for batch in batches {
         local_pb.set_prefix(format!("[{}/{}]", done, terms.len()));
 
@@ -370,15 +470,24 @@ pub async fn fetch_terms(
         for term in batch {
             local_pb.set_message(term.trim().to_string());
             let plugin = plugin.clone();
+
+            let term_pb = m.add(ProgressBar::new_spinner());
+            term_pb.set_style(spinner.clone());
+            term_pb.set_prefix(format!("  {}", utils::resume_text(term.trim(), Some(30))));
+            let reporter = PluginReporter::new(term.clone(), Some(term_pb));
+
             join_set.spawn(async move {
                 let manager = PLUGIN_MANAGER.read().await;
-                (
-                    term.clone(),
-                    match plugin {
-                        Some(ref name) => manager.fetch(term.to_string(), name.to_owned()).await,
-                        None => manager.auto_fetch(term.to_string()).await,
-                    },
-                )
+                let res = match plugin {
+                    Some(ref name) => {
+                        manager
+                            .fetch(term.to_string(), name.to_owned(), reporter.clone())
+                            .await
+                    }
+                    None => manager.auto_fetch(term.to_string(), reporter.clone()).await,
+                };
+                reporter.finish();
+                (term.clone(), res)
             });
         }
 
@@ -387,30 +496,65 @@ pub async fn fetch_terms(
             match res {
                 Ok((term, crawl_result)) => {
                     local_pb.set_prefix(format!("[{}/{}]", done, terms.len()));
-                    local_pb.set_message(term.trim().to_string());
+                    record(&term, crawl_result);
+                }
+                Err(join_err) => eprintln!("Task panicked: {join_err:?}"),
+            }
+        }
+    }
 
+    // Same order as the terms, whichever way they were fetched
+    let order: HashMap<&String, usize> = terms.iter().enumerate().map(|(p, t)| (t, p)).collect();
+    results.sort_by(|a, _, b, _| order.get(a).cmp(&order.get(b)));
+
+    Ok(results)
+}
+
+/// Fetch and download each term at the same time, pages are downloaded as soon as they are found
+pub async fn stream_terms(
+    terms: Vec<String>,
+    plugin: Option<String>,
+) -> anyhow::Result<IndexMap<String, Resolution>> {
+    if let Some(plugin) = &plugin {
+        PLUGIN_MANAGER.read().await.assert_exists(plugin.clone())?;
+    }
+    let crawl_batch = { GLOBAL_CONFIG.read().unwrap().max_size_init_crawl_batch };
+
+    let terms: IndexSet<String> = IndexSet::from_iter(terms.iter().cloned());
+    let terms = Vec::from_iter(terms);
+
+    let mut results = IndexMap::new();
+    let batches = utils::batch_a_list_of(&terms, crawl_batch);
+    for (p, batch) in batches.iter().enumerate() {
+        println!("Batch {}/{}", p + 1, batches.len());
+
+        let mut join_set = tokio::task::JoinSet::new();
+        for term in batch.clone() {
+            let plugin = plugin.clone();
+            join_set.spawn(async move {
+                let reporter = PluginReporter::new(term.clone(), None);
+                let res = async {
+                    let (plugin_name, query_term) = match plugin {
+                        Some(name) => (name, term.clone()),
+                        None => {
+                            let manager = PLUGIN_MANAGER.read().await;
+                            manager.find_supporting_plugin(term.clone()).await?
+                        }
+                    };
+                    downloader::stream_and_download(query_term, plugin_name, reporter).await
+                }
+                .await;
+                (term, res)
+            });
+        }
+
+        while let Some(res) = join_set.join_next().await {
+            match res {
+                Ok((term, res)) => {
                     results.insert(
-                        term.to_string(),
-                        match crawl_result {
-                            Ok(fetched) => {
-                                if fetched.cached {
-                                    local_pb.set_message(format!(
-                                        "{} [cached]",
-                                        term.to_string().trim()
-                                    ));
-                                    cached_count += 1;
-                                } else {
-                                    fetched_count += 1
-                                }
-
-                                status_pb.set_message(format!(
-                                    "{fetched_count} fetched, {cached_count} cached, {} left",
-                                    terms.len() - (fetched_count + cached_count)
-                                ));
-                                local_pb.inc(1);
-
-                                Resolution::Success(fetched.into())
-                            }
+                        term,
+                        match res {
+                            Ok(fetched) => Resolution::Success(fetched.into()),
                             Err(e) => Resolution::Fail(e),
                         },
                     );
@@ -423,7 +567,57 @@ pub async fn fetch_terms(
     Ok(results)
 }
 
-fn display_fetch_status(results: &IndexMap<String, Resolution>, verbose: bool) {
+async fn explain_terms(terms: &[String], plugin: Option<String>) -> anyhow::Result<()> {
+    let manager = PLUGIN_MANAGER.read().await;
+    let fallthrough = { GLOBAL_CONFIG.read().unwrap().plugins.fallthrough };
+
+    for term in terms {
+        println!("{term}");
+        if let Some(name) = &plugin {
+            manager.assert_exists(name.clone())?;
+            println!("  => {name} (forced with --plugin)\n");
+            continue;
+        }
+
+        let route = { GLOBAL_CONFIG.read().unwrap().route(term)? };
+        if let Some((route, rewritten)) = route {
+            println!(
+                "  => {} (routed by {}, term {rewritten:?})\n",
+                route.plugin,
+                route.describe()
+            );
+            continue;
+        }
+
+        let claims = manager.explain(term).await;
+        for (p, claim) in claims.iter().enumerate() {
+            println!("  {}. {}", p + 1, claim.explain());
+        }
+
+        let claimed = claims
+            .iter()
+            .filter(|claim| matches!(claim.verdict, Ok(true)))
+            .map(|claim| claim.plugin_name.clone())
+            .collect::<Vec<_>>();
+        match claimed.as_slice() {
+            [] => println!("  => no plugin claimed this term\n"),
+            [first] => println!("  => {first}\n"),
+            [first, others @ ..] => println!(
+                "  => {first}, ambiguous: also claimed by {}{}\n",
+                others.join(", "),
+                if fallthrough {
+                    " (tried in that order on failure)"
+                } else {
+                    " (fallthrough disabled)"
+                }
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn display_fetch_status(results: &IndexMap<String, Resolution>, verbose: bool) {
     let fail_messages = results
         .iter()
         .filter_map(|(term, res)| match res {
@@ -448,7 +642,7 @@ fn display_fetch_status(results: &IndexMap<String, Resolution>, verbose: bool) {
     }
 }
 
-fn display_download_status(fetched_books: &[Box<FetchResult>], results: &[DownloadStatus]) {
+pub fn display_download_status(fetched_books: &[Box<FetchResult>], results: &[DownloadStatus]) {
     assert!(fetched_books.len() == results.len(), "Size preserved");
 
     let mut fail_messages = vec![];
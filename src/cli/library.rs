@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use chrono::Local;
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    core::library,
+    schemas::library::{DownloadState, LibraryBook, LibraryIndex, LibraryQuery, TitleFilter},
+    GLOBAL_CONFIG,
+};
+
+#[derive(Parser, Debug)]
+pub struct Library {
+    #[command(subcommand)]
+    pub command: LibraryCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LibraryCommand {
+    /// Scan the metadata and download folders into the library index
+    Index,
+    /// Search the indexed books and report whether they are fully downloaded
+    Search(LibrarySearch),
+}
+
+#[derive(Args, Debug)]
+pub struct LibrarySearch {
+    /// Books with this tag, can be repeated
+    #[arg(long, short)]
+    pub tag: Vec<String>,
+    /// Part of the name of an author
+    #[arg(long, short)]
+    pub author: Option<String>,
+    #[arg(long, short)]
+    pub plugin: Option<String>,
+    /// Part of the title, or a regex prefixed with `~` (e.g. `~^One .+ Vol\.`)
+    #[arg(long)]
+    pub title: Option<TitleFilter>,
+    /// complete, partial or missing
+    #[arg(long, short)]
+    pub state: Option<DownloadState>,
+    /// Scan the folders again before searching
+    #[arg(long)]
+    pub reindex: bool,
+}
+
+impl Library {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let index_file = { GLOBAL_CONFIG.read().unwrap().library.index.clone() };
+        match &self.command {
+            LibraryCommand::Index => {
+                let index = reindex(&index_file)?;
+                println!(
+                    "{} book(s) indexed in {}",
+                    index.books.len(),
+                    index_file.display()
+                );
+                Ok(())
+            }
+            LibraryCommand::Search(search) => search.search(&index_file),
+        }
+    }
+}
+
+impl LibrarySearch {
+    fn search(&self, index_file: &Path) -> anyhow::Result<()> {
+        let index = match LibraryIndex::load(index_file)? {
+            Some(index) if !self.reindex => index,
+            _ => reindex(index_file)?,
+        };
+        let query = LibraryQuery {
+            tags: self.tag.clone(),
+            author: self.author.clone(),
+            plugin: self.plugin.clone(),
+            title: self.title.clone(),
+            state: self.state,
+        };
+
+        let mut count = 0;
+        for book in index.search(&query) {
+            display_book(book);
+            count += 1;
+        }
+        println!(
+            "{count} of {} book(s), indexed {}",
+            index.books.len(),
+            index.indexed_at.format("%Y-%m-%d %H:%M")
+        );
+        if (Local::now() - index.indexed_at).num_days() > 0 {
+            println!("The index may be outdated, see `library index` or `--reindex`");
+        }
+        Ok(())
+    }
+}
+
+fn reindex(index_file: &Path) -> anyhow::Result<LibraryIndex> {
+    let (metadata, download, sidecars) = {
+        let config = GLOBAL_CONFIG.read().unwrap();
+        (
+            config.download_folder.metadata.clone(),
+            config.download_folder.download.clone(),
+            config.sidecars.clone(),
+        )
+    };
+    let (index, errors) = library::scan(&metadata, &download, &sidecars);
+    for (path, e) in &errors {
+        eprintln!("{}: skipped, {e:#}", path.display());
+    }
+    index.save(index_file)?;
+    Ok(index)
+}
+
+fn display_book(book: &LibraryBook) {
+    println!("{} [{}]", book.title, book.plugin);
+    let selection = match &book.selection {
+        Some(selection) => {
+            let parts = [
+                selection.chapters.as_ref().map(|c| format!("chapters {c}")),
+                selection.pages.as_ref().map(|p| format!("pages {p}")),
+            ];
+            format!(
+                ", selected {}",
+                parts.into_iter().flatten().collect::<Vec<_>>().join(" ")
+            )
+        }
+        None => String::new(),
+    };
+    println!(
+        "  {}: {}/{} chapter(s), {}/{} page(s){selection}",
+        book.state, book.downloaded_chapters, book.chapters, book.downloaded_pages, book.pages
+    );
+    if !book.authors.is_empty() {
+        println!("  by {}", book.authors.join(", "));
+    }
+    if !book.tags.is_empty() {
+        println!("  tags: {}", book.tags.join(", "));
+    }
+    match book.state {
+        DownloadState::Missing => println!("  metadata: {}", book.metadata.display()),
+        _ => println!("  folder: {}", book.folder.display()),
+    }
+}
@@ -1,11 +1,21 @@
+use cache::QueryCache;
 use clap::{Parser, Subcommand};
 use fetch::{FileSequence, TermSequence, UrlTerm};
 use infos::Infos;
+use library::Library;
 use server::ApiServer;
+use sync::SyncSequence;
+use verify::Verify;
+use watch::Watch;
 
+pub mod cache;
 pub mod fetch;
 pub mod infos;
+pub mod library;
 pub mod server;
+pub mod sync;
+pub mod verify;
+pub mod watch;
 
 #[derive(Parser, Debug)]
 #[command(author = "futureg-lab", about = "mx-scraper engine")]
@@ -20,6 +30,18 @@ pub enum Commands {
     Fetch(TermSequence),
     /// Fetch a sequence of terms from a collection of files
     FetchFiles(FileSequence),
+    /// Fetch terms again and only download their new or changed chapters,
+    /// `--meta-only` reports them without downloading
+    Sync(SyncSequence),
+    /// Manage terms checked periodically for new chapters
+    Watch(Watch),
+    /// Inspect and invalidate the query cache
+    Cache(QueryCache),
+    /// Index and search the books saved so far
+    Library(Library),
+    /// Check the downloaded pages against the saved metadata,
+    /// `--redownload` fetches the broken ones again
+    Verify(Verify),
     /// Request a url
     Request(UrlTerm),
     /// Display various informations
@@ -33,6 +55,11 @@ impl Commands {
         match self {
             Commands::Fetch(terms) => terms.fetch().await,
             Commands::FetchFiles(files) => files.fetch().await,
+            Commands::Sync(terms) => terms.sync().await,
+            Commands::Watch(watch) => watch.run().await,
+            Commands::Cache(cache) => cache.run().await,
+            Commands::Library(library) => library.run().await,
+            Commands::Verify(verify) => verify.run().await,
             Commands::Request(url_term) => url_term.fetch().await,
             Commands::Infos(infos) => infos.display().await,
             Commands::Server(server) => server.spawn().await,
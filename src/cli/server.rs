@@ -11,7 +11,7 @@ pub struct ApiServer {
     port: Option<u16>,
 }
 
-use crate::{plugins::FetchResult, GLOBAL_CONFIG, PLUGIN_MANAGER};
+use crate::{plugins::FetchResult, schemas::selection::Selection, GLOBAL_CONFIG, PLUGIN_MANAGER};
 use async_graphql::{
     http::GraphiQLSource, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject, Union,
 };
@@ -41,6 +41,7 @@ struct Query;
 
 #[Object]
 impl Query {
+    #[allow(clippy::too_many_arguments)]
     async fn crawl(
         &self,
         terms: Vec<String>,
@@ -49,7 +50,14 @@ impl Query {
         cookies_path: Option<String>,
         auth: Option<Auth>,
         max_parallel_fetch: Option<usize>,
+        #[graphql(desc = "Only keep these chapters by number, e.g. `1-10,15,latest:3`")]
+        chapters: Option<String>,
+        #[graphql(desc = "Only keep these pages of each chapter by number")] pages: Option<String>,
     ) -> anyhow::Result<Vec<CrawlOuput>> {
+        let selection = Selection {
+            chapters: chapters.as_deref().map(str::parse).transpose()?,
+            pages: pages.as_deref().map(str::parse).transpose()?,
+        };
         let flags = SharedFetchOption {
             plugin: plugin.clone(),
             no_cache: no_cache.unwrap_or(false),
@@ -58,6 +66,7 @@ impl Query {
             verbose: true,
             meta_only: true,          // no effect
             custom_downloader: false, // no effect
+            fix_extensions: false,    // no effect
             rand: false,
             asc: false,
             reflect: false,
@@ -66,6 +75,10 @@ impl Query {
             mini_batch_size: None,
             batch_size: None,
             listen_cookies: false,
+            stream: false,
+            explain: false,
+            chapters: selection.chapters.clone(),
+            pages: selection.pages.clone(),
         };
 
         {
@@ -81,7 +94,9 @@ impl Query {
                 term: term.to_owned(),
                 result: match res {
                     Resolution::Success(fetch_result) => {
-                        CrawlResult::Resolved(*fetch_result.clone())
+                        let mut fetch_result = *fetch_result.clone();
+                        selection.apply(&mut fetch_result.book);
+                        CrawlResult::Resolved(fetch_result)
                     }
                     Resolution::Fail(error) => CrawlResult::Failed(FetchError {
                         error: error.to_string(),
@@ -0,0 +1,186 @@
+use clap::Parser;
+
+use crate::{
+    cli::server::OneshotHttpListener,
+    core::{
+        downloader::{self, batch_download, DownloadStatus},
+        http::{self, FetchContext},
+        sync::{self, BookDiff, ChapterState},
+    },
+    plugins::FetchResult,
+    schemas::book::Chapter,
+    GLOBAL_CONFIG,
+};
+
+use super::fetch::{
+    display_download_status, display_fetch_status, fetch_terms, Resolution, SharedFetchOption,
+};
+
+#[derive(Parser, Debug)]
+pub struct SyncSequence {
+    /// A sequence of terms that were already fetched
+    #[arg(required = true)]
+    pub terms: Vec<String>,
+    #[command(flatten)]
+    pub flags: SharedFetchOption,
+}
+
+/// A book waiting for its new chapters
+pub struct PendingSync {
+    pub fetched: Box<FetchResult>,
+    /// Chapters of the last saved metadata
+    pub previous: Vec<Chapter>,
+    /// Nothing was saved for this book yet
+    pub first_sync: bool,
+    pub diff: BookDiff,
+}
+
+impl PendingSync {
+    /// Diff of a fetched book against the chapters of its last saved metadata
+    pub fn prepare(term: &str, fetched: &FetchResult) -> anyhow::Result<Self> {
+        let saved = sync::load_saved(&fetched.book, term, &fetched.plugin_name)?;
+        let first_sync = saved.is_none();
+        let previous = saved.map(|saved| saved.book.chapters).unwrap_or_default();
+        // Saved pages are named after the page template
+        let mut templated = fetched.book.clone();
+        templated.apply_page_template(&fetched.plugin_name);
+        let diff = BookDiff::new(&previous, &templated.chapters);
+        Ok(Self {
+            fetched: Box::new(fetched.clone()),
+            previous,
+            first_sync,
+            diff,
+        })
+    }
+}
+
+impl SyncSequence {
+    pub async fn sync(&self) -> anyhow::Result<()> {
+        {
+            if let Some(max_fetch) = self.flags.max_parallel_fetch {
+                http::update_fetch_semaphore_count(max_fetch).await;
+            }
+        }
+
+        {
+            if self.flags.listen_cookies {
+                let listener = OneshotHttpListener { port: 5678 };
+                let context = listener.block_and_listen::<FetchContext>("context").await?;
+                let mut config = GLOBAL_CONFIG.write().unwrap();
+                tracing::debug!("New fetch context will be injected: {context:?}");
+                config.__known_fetch_context = Some(context);
+            }
+        }
+
+        let batch_size = {
+            let mut config = GLOBAL_CONFIG.write().unwrap();
+            config.adapt_override(self.flags.clone())?;
+            // Metadata is always fetched again, the cache is refreshed along the way
+            config.cache.enable = false;
+            config.max_size_batch
+        };
+
+        let results = fetch_terms(self.terms.clone(), self.flags.plugin.clone()).await?;
+        display_fetch_status(&results, self.flags.verbose);
+
+        let mut pending = vec![];
+        for (term, res) in &results {
+            let Resolution::Success(fetched) = res else {
+                continue;
+            };
+            let sync = match PendingSync::prepare(term, fetched) {
+                Ok(sync) => sync,
+                Err(e) => {
+                    eprintln!("{term}: skipped, {e:?}");
+                    continue;
+                }
+            };
+            print_report(fetched, sync.first_sync, &sync.diff);
+
+            if !sync.diff.is_up_to_date() {
+                pending.push(sync);
+            }
+        }
+
+        if self.flags.meta_only || pending.is_empty() {
+            return Ok(());
+        }
+
+        let partial_books = pending
+            .iter()
+            .map(|sync| {
+                Box::new(FetchResult {
+                    book: sync.diff.pending(&sync.fetched.book),
+                    ..*sync.fetched.clone()
+                })
+            })
+            .collect::<Vec<_>>();
+        let status = batch_download(&partial_books, batch_size).await;
+        display_download_status(&partial_books, &status);
+
+        for (sync, status) in pending.iter().zip(status) {
+            if let DownloadStatus::Success = status {
+                record_sync(sync)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn print_report(fetched: &FetchResult, first_sync: bool, diff: &BookDiff) {
+    let summary = match (first_sync, diff.is_up_to_date()) {
+        (true, _) => "nothing saved yet".to_string(),
+        (false, true) => "up to date".to_string(),
+        (false, false) => format!(
+            "{} new, {} changed",
+            diff.count(ChapterState::New),
+            diff.count(ChapterState::Changed)
+        ),
+    };
+    println!(
+        "{} [{}]: {summary}",
+        fetched.book.title, fetched.plugin_name
+    );
+    if first_sync {
+        return;
+    }
+
+    for (chapter, chapter_diff) in fetched.book.chapters.iter().zip(&diff.chapters) {
+        match chapter_diff.state {
+            ChapterState::New => println!("  + {}", chapter.title),
+            ChapterState::Changed => println!("  ~ {}", chapter.title),
+            ChapterState::Unchanged => {}
+        }
+    }
+    if !diff.removed.is_empty() {
+        println!("  {} saved chapter(s) no longer listed", diff.removed.len());
+    }
+}
+
+/// Record every saved chapter, the metadata written by the download only has the new ones
+pub fn record_sync(sync: &PendingSync) -> anyhow::Result<()> {
+    let FetchResult {
+        query_term,
+        book,
+        plugin_name,
+        ..
+    } = sync.fetched.as_ref();
+
+    // Written by the download, it only lists the chapters that were just saved
+    let download_path = book.get_metadata_download_path(query_term, plugin_name);
+    let saved = match download_path.exists() {
+        true => sync::read_metadata(&download_path)?.book.chapters,
+        false => vec![],
+    };
+
+    let mut merged = book.clone();
+    merged.chapters = sync.diff.merge(&sync.previous, &book.chapters, &saved);
+    for path in [
+        book.get_metadata_dest_path(query_term, plugin_name),
+        download_path,
+    ] {
+        downloader::create_metadata_file(&path, plugin_name, &merged)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Parser;
+use indexmap::IndexMap;
+
+use crate::{
+    core::{
+        downloader, library, sync,
+        verify::{self, BrokenFile, Problem},
+    },
+    schemas::{book::CacheFile, library::DownloadState},
+    GLOBAL_CONFIG, PLUGIN_MANAGER,
+};
+
+#[derive(Parser, Debug)]
+pub struct Verify {
+    /// Download folder to verify, the one of the config by default
+    pub path: Option<PathBuf>,
+    /// Remove the broken pages and download them again
+    #[arg(long)]
+    pub redownload: bool,
+}
+
+impl Verify {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let (metadata, download, temp, custom_downloader, sidecars, book_depth) = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            (
+                config.download_folder.metadata.clone(),
+                config.download_folder.download.clone(),
+                config.download_folder.temp.clone(),
+                config.custom_downloader,
+                config.sidecars.clone(),
+                config.paths.book_depth(),
+            )
+        };
+        let download = self.path.clone().unwrap_or(download);
+
+        let (index, errors) = library::scan(&metadata, &download, &sidecars);
+        for (path, e) in &errors {
+            eprintln!("{}: skipped, {e:#}", path.display());
+        }
+
+        let mut verified = 0;
+        let mut damaged = 0;
+        for book in &index.books {
+            // Nothing on disk to compare against
+            if book.state == DownloadState::Missing {
+                continue;
+            }
+            let saved = match sync::read_metadata(&book.metadata) {
+                Ok(saved) => saved,
+                Err(e) => {
+                    eprintln!("{}: skipped, {e:#}", book.metadata.display());
+                    continue;
+                }
+            };
+
+            verified += 1;
+            let broken = verify::verify_book(&saved, &book.plugin, &book.folder);
+            if broken.is_empty() {
+                continue;
+            }
+
+            damaged += 1;
+            // Such chapters are saved under the names the plugin gave, not page by page
+            let by_plugin = custom_downloader
+                && PLUGIN_MANAGER
+                    .read()
+                    .await
+                    .can_download_chapter(&book.plugin);
+            println!(
+                "{} [{}]: {} broken file(s)",
+                book.title,
+                book.plugin,
+                broken.len()
+            );
+            for file in &broken {
+                let path = file.path.strip_prefix(&book.folder).unwrap_or(&file.path);
+                match by_plugin && file.problem == Problem::Missing {
+                    true => println!(
+                        "  {}: {} (saved by the plugin itself)",
+                        path.display(),
+                        file.problem
+                    ),
+                    false => println!("  {}: {}", path.display(), file.problem),
+                }
+            }
+
+            if !self.redownload {
+                continue;
+            }
+            match by_plugin {
+                true => println!(
+                    "  {} page(s) saved by the plugin itself, fetch the book again",
+                    broken.len()
+                ),
+                false => {
+                    redownload(&saved, &book.plugin, &book.folder, &book.metadata, &broken).await?
+                }
+            }
+        }
+
+        let leftovers = verify::temp_leftovers(&temp, book_depth);
+        for (folder, files) in &leftovers {
+            println!(
+                "Leftover temp folder: {} ({files} file(s))",
+                folder.display()
+            );
+        }
+
+        println!(
+            "{verified} book(s) verified, {damaged} with broken files, {} leftover temp folder(s)",
+            leftovers.len()
+        );
+        Ok(())
+    }
+}
+
+/// Pages that are downloaded again under another extension are renamed in the metadata
+async fn redownload(
+    saved: &CacheFile,
+    plugin_name: &str,
+    folder: &Path,
+    metadata: &Path,
+    broken: &[BrokenFile],
+) -> anyhow::Result<()> {
+    // chapter => pages
+    let mut pending: IndexMap<usize, Vec<usize>> = IndexMap::new();
+    let mut unlisted = 0;
+    for file in broken {
+        let Some(page) = file.page else {
+            unlisted += 1;
+            continue;
+        };
+        if file.problem != Problem::Missing {
+            std::fs::remove_file(&file.path)?;
+        }
+        pending.entry(file.chapter).or_default().push(page);
+    }
+    if unlisted > 0 {
+        println!("  {unlisted} file(s) of chapters without listed pages, fetch the book again");
+    }
+
+    // (chapter, filename, renamed to)
+    let mut renamed_pages = vec![];
+    for (c, pages) in pending {
+        let chapter = &saved.book.chapters[c];
+        let chapter_folder = folder.join(saved.book.get_chapter_folder(plugin_name, chapter));
+        std::fs::create_dir_all(&chapter_folder)?;
+        let pages = pages
+            .into_iter()
+            .map(|p| chapter.pages[p].clone())
+            .collect::<Vec<_>>();
+
+        let (renamed, failed) =
+            downloader::redownload_pages(plugin_name, &chapter_folder, &pages).await;
+        println!(
+            "  {}: {}/{} page(s) downloaded again",
+            chapter.title,
+            pages.len() - failed.len(),
+            pages.len()
+        );
+        for (page, err) in failed {
+            eprintln!("    {}: {err}", page.filename);
+        }
+        renamed_pages.extend(renamed.into_iter().map(|(old, new)| (c, old, new)));
+    }
+
+    if !renamed_pages.is_empty() {
+        let mut saved = saved.clone();
+        downloader::rename_pages(&mut saved.book, renamed_pages);
+        let text = serde_json::to_string_pretty(&saved)?;
+        std::fs::write(metadata, text)
+            .with_context(|| format!("Writing {}", metadata.display()))?;
+    }
+    Ok(())
+}
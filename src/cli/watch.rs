@@ -0,0 +1,321 @@
+use std::{path::Path, time::Duration};
+
+use chrono::{DateTime, Local};
+use clap::{Args, Parser, Subcommand};
+use indexmap::IndexMap;
+
+use crate::{
+    core::downloader::{batch_download, DownloadStatus},
+    plugins::FetchResult,
+    schemas::{
+        book::Book,
+        watch::{Interval, WatchEntry, Watchlist},
+    },
+    GLOBAL_CONFIG,
+};
+
+use super::{
+    fetch::{display_fetch_status, fetch_terms, Resolution, SharedFetchOption},
+    sync::{record_sync, PendingSync},
+};
+
+/// Shortest pause of the daemon between two rounds
+const MIN_WAIT: Duration = Duration::from_secs(30);
+/// Pause of the daemon when nothing is watched
+const IDLE_WAIT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Parser, Debug)]
+pub struct Watch {
+    #[command(subcommand)]
+    pub command: WatchCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WatchCommand {
+    /// Add terms to the watchlist, or update their plugin and interval
+    Add(WatchAdd),
+    /// Remove terms from the watchlist
+    Remove {
+        #[arg(required = true)]
+        terms: Vec<String>,
+    },
+    /// List watched terms and when they are checked next
+    List,
+    /// Check due terms and download their new chapters
+    Run(WatchRun),
+}
+
+#[derive(Args, Debug)]
+pub struct WatchAdd {
+    #[arg(required = true)]
+    pub terms: Vec<String>,
+    /// Plugin used to fetch the terms, auto-detected otherwise
+    #[arg(long, short)]
+    pub plugin: Option<String>,
+    /// Time between two checks (e.g. 30m, 6h, 1d), `watch.interval` of the config by default
+    #[arg(long, short)]
+    pub interval: Option<Interval>,
+    /// Fetch the terms now, only chapters released afterwards are downloaded
+    #[arg(long)]
+    pub skip_existing: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchRun {
+    /// Keep running and check terms as they become due
+    #[arg(long)]
+    pub daemon: bool,
+    /// Check every term regardless of its interval
+    #[arg(long)]
+    pub all: bool,
+    /// Verbose mode
+    #[arg(long, short)]
+    pub verbose: bool,
+}
+
+impl Watch {
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let (file, default_interval) = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            (config.watch.file.clone(), config.watch.interval)
+        };
+
+        match &self.command {
+            WatchCommand::Add(add) => add.add(&file, default_interval).await,
+            WatchCommand::Remove { terms } => {
+                let mut watchlist = Watchlist::load(&file)?;
+                for term in terms {
+                    if !watchlist.remove(term) {
+                        eprintln!("{term} is not watched");
+                    }
+                }
+                watchlist.save(&file)
+            }
+            WatchCommand::List => {
+                let watchlist = Watchlist::load(&file)?;
+                display_watchlist(&watchlist, Local::now());
+                Ok(())
+            }
+            WatchCommand::Run(run) => run.run(&file).await,
+        }
+    }
+}
+
+impl WatchAdd {
+    async fn add(&self, file: &Path, default_interval: Interval) -> anyhow::Result<()> {
+        let interval = self.interval.unwrap_or(default_interval);
+        let mut watchlist = Watchlist::load(file)?;
+        for term in &self.terms {
+            watchlist.upsert(term, self.plugin.clone(), interval);
+        }
+
+        if self.skip_existing {
+            adapt_config(false)?;
+            let results = fetch_terms(self.terms.clone(), self.plugin.clone()).await?;
+            display_fetch_status(&results, true);
+
+            let now = Local::now();
+            for (term, res) in &results {
+                if let (Resolution::Success(fetched), Some(entry)) = (res, watchlist.find_mut(term))
+                {
+                    entry.record(&fetched.book, now);
+                    println!(
+                        "{}: {} existing chapter(s) skipped",
+                        fetched.book.title,
+                        entry.chapters.len()
+                    );
+                }
+            }
+        }
+
+        watchlist.save(file)?;
+        println!("Watching {} term(s) every {interval}", self.terms.len());
+        Ok(())
+    }
+}
+
+impl WatchRun {
+    async fn run(&self, file: &Path) -> anyhow::Result<()> {
+        let batch_size = adapt_config(self.verbose)?;
+        if !self.daemon {
+            return self.check_due(file, batch_size, self.all).await;
+        }
+
+        let mut all = self.all;
+        loop {
+            if let Err(e) = self.check_due(file, batch_size, all).await {
+                tracing::error!("Watch round failed: {e:?}");
+            }
+            all = false;
+
+            let wait = Watchlist::load(file)?
+                .next_due_in(Local::now())
+                .unwrap_or(IDLE_WAIT)
+                .clamp(MIN_WAIT, IDLE_WAIT);
+            tracing::info!("Next check in {}s", wait.as_secs());
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = tokio::signal::ctrl_c() => {
+                    tracing::info!("Stopping");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// One round: fetch every due term, download the chapters that were never seen
+    async fn check_due(&self, file: &Path, batch_size: usize, all: bool) -> anyhow::Result<()> {
+        let now = Local::now();
+        let due = Watchlist::load(file)?
+            .entries
+            .into_iter()
+            .filter(|entry| all || entry.is_due(now))
+            .collect::<Vec<_>>();
+        if due.is_empty() {
+            tracing::info!("No watched term is due");
+            return Ok(());
+        }
+
+        // Terms forced to the same plugin are fetched together
+        let mut groups: IndexMap<Option<String>, Vec<String>> = IndexMap::new();
+        for entry in &due {
+            groups
+                .entry(entry.plugin.clone())
+                .or_default()
+                .push(entry.term.clone());
+        }
+        let mut results = IndexMap::new();
+        for (plugin, terms) in groups {
+            results.extend(fetch_terms(terms, plugin).await?);
+        }
+        display_fetch_status(&results, self.verbose);
+
+        let mut checked = vec![];
+        let mut pending = vec![];
+        for mut entry in due {
+            let fetched = match results.get(&entry.term) {
+                Some(Resolution::Success(fetched)) => fetched,
+                Some(Resolution::Fail(e)) => {
+                    entry.record_error(e, now);
+                    checked.push(entry);
+                    continue;
+                }
+                None => continue,
+            };
+
+            let unknown = entry.unknown_chapters(&fetched.book);
+            if unknown.is_empty() {
+                tracing::info!("{}: no new chapter", fetched.book.title);
+                entry.record(&fetched.book, now);
+                checked.push(entry);
+                continue;
+            }
+            tracing::info!(
+                "{}: {} new chapter(s): {}",
+                fetched.book.title,
+                unknown.len(),
+                unknown
+                    .iter()
+                    .map(|c| fetched.book.chapters[*c].title.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+
+            let sync = match PendingSync::prepare(&entry.term, fetched) {
+                Ok(sync) => sync,
+                Err(e) => {
+                    entry.record_error(&e, now);
+                    checked.push(entry);
+                    continue;
+                }
+            };
+            let partial = Box::new(FetchResult {
+                book: Book {
+                    chapters: unknown
+                        .iter()
+                        .map(|c| fetched.book.chapters[*c].clone())
+                        .collect(),
+                    ..fetched.book.clone()
+                },
+                ..*fetched.clone()
+            });
+            pending.push((entry, sync, partial));
+        }
+
+        let partial_books = pending
+            .iter()
+            .map(|(_, _, partial)| partial.clone())
+            .collect::<Vec<_>>();
+        let status = batch_download(&partial_books, batch_size).await;
+        for ((mut entry, sync, _), status) in pending.into_iter().zip(status) {
+            let recorded = match status {
+                DownloadStatus::Success => record_sync(&sync),
+                DownloadStatus::Fail(failure) => Err(anyhow::anyhow!("{failure:?}")),
+            };
+            match recorded {
+                Ok(_) => entry.record(&sync.fetched.book, now),
+                Err(e) => {
+                    tracing::warn!("{}: {e:?}", sync.fetched.book.title);
+                    entry.record_error(&e, now);
+                }
+            }
+            checked.push(entry);
+        }
+
+        save_checked(file, checked)
+    }
+}
+
+/// Terms are fetched again each time, without the download options of `fetch`
+fn adapt_config(verbose: bool) -> anyhow::Result<usize> {
+    let mut config = GLOBAL_CONFIG.write().unwrap();
+    config.adapt_override(SharedFetchOption {
+        no_cache: true,
+        verbose,
+        ..Default::default()
+    })?;
+    Ok(config.max_size_batch)
+}
+
+/// Only the state of checked entries is written back, the watchlist may have been edited meanwhile
+fn save_checked(file: &Path, checked: Vec<WatchEntry>) -> anyhow::Result<()> {
+    let mut watchlist = Watchlist::load(file)?;
+    for checked in checked {
+        if let Some(entry) = watchlist.find_mut(&checked.term) {
+            entry.title = checked.title;
+            entry.last_check = checked.last_check;
+            entry.last_error = checked.last_error;
+            entry.chapters = checked.chapters;
+        }
+    }
+    watchlist.save(file)
+}
+
+fn display_watchlist(watchlist: &Watchlist, now: DateTime<Local>) {
+    if watchlist.entries.is_empty() {
+        println!("No watched term, see `watch add`");
+        return;
+    }
+
+    for entry in &watchlist.entries {
+        println!("{}", entry.title.as_deref().unwrap_or(&entry.term));
+        println!(
+            "  {} [{}] every {}, {} chapter(s) known",
+            entry.term,
+            entry.plugin.as_deref().unwrap_or("auto"),
+            entry.interval,
+            entry.chapters.len()
+        );
+        let next = match entry.next_check() {
+            Some(next) if next > now => format!("next check {}", next.format("%Y-%m-%d %H:%M")),
+            _ => "due".to_string(),
+        };
+        match entry.last_check {
+            Some(last) => println!("  last check {}, {next}", last.format("%Y-%m-%d %H:%M")),
+            None => println!("  never checked, {next}"),
+        }
+        if let Some(error) = &entry.last_error {
+            println!("  last error: {error}");
+        }
+    }
+}
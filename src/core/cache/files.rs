@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+
+use super::CacheStorage;
+use crate::schemas::{book::Book, cache::CacheEntry};
+
+/// Read a cache file
+///
+/// Files written before entries were stamped only hold the book, they are stamped with
+/// their modification time and an unknown term, plugin and version.
+pub fn read_entry(path: &Path) -> anyhow::Result<CacheEntry> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading cache {:?}", path.display()))?;
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Deserializing cache {:?}", path.display()))?;
+
+    if value.get("fetched_at").is_some() {
+        return serde_json::from_value(value)
+            .with_context(|| format!("Deserializing cache {:?}", path.display()));
+    }
+
+    let book: Book = serde_json::from_value(value)
+        .with_context(|| format!("Deserializing legacy cache {:?}", path.display()))?;
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(CacheEntry {
+        term: String::new(),
+        plugin: String::new(),
+        plugin_version: None,
+        fetched_at: DateTime::<Local>::from(modified),
+        book,
+    })
+}
+
+pub fn write_entry(path: &Path, entry: &CacheEntry) -> anyhow::Result<()> {
+    let content = serde_json::to_string_pretty(entry)?;
+    std::fs::write(path, content).with_context(|| format!("Writing cache {:?}", path.display()))
+}
+
+/// One pretty-printed `<signature>.json` per entry in a flat folder
+#[derive(Debug, Clone)]
+pub struct FileCacheStorage {
+    pub folder: PathBuf,
+}
+
+impl FileCacheStorage {
+    pub fn new(folder: PathBuf) -> Self {
+        Self { folder }
+    }
+
+    pub fn path_of(&self, signature: &str) -> PathBuf {
+        self.folder.join(format!("{signature}.json"))
+    }
+}
+
+impl CacheStorage for FileCacheStorage {
+    fn get(&self, signature: &str) -> anyhow::Result<Option<CacheEntry>> {
+        let path = self.path_of(signature);
+        if !path.exists() {
+            return Ok(None);
+        }
+        read_entry(&path).map(Some)
+    }
+
+    fn put(&self, signature: &str, entry: &CacheEntry) -> anyhow::Result<()> {
+        write_entry(&self.path_of(signature), entry)
+    }
+
+    fn remove(&self, signature: &str) -> anyhow::Result<bool> {
+        let path = self.path_of(signature);
+        if !path.exists() {
+            return Ok(false);
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Removing cache {:?}", path.display()))?;
+        Ok(true)
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<(String, anyhow::Result<CacheEntry>)>> {
+        if !self.folder.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = std::fs::read_dir(&self.folder)
+            .with_context(|| format!("Reading cache folder {:?}", self.folder.display()))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let signature = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                (signature, read_entry(&path))
+            })
+            .collect())
+    }
+
+    fn location(&self) -> String {
+        self.folder.display().to_string()
+    }
+}
@@ -0,0 +1,34 @@
+use crate::schemas::cache::CacheEntry;
+
+pub mod files;
+pub mod sqlite;
+
+/// Where fetched books are cached, entries are keyed by query signature
+/// (see `utils::compute_query_signature`)
+pub trait CacheStorage: Send + Sync {
+    fn get(&self, signature: &str) -> anyhow::Result<Option<CacheEntry>>;
+    fn put(&self, signature: &str, entry: &CacheEntry) -> anyhow::Result<()>;
+    /// Whether there was an entry to remove
+    fn remove(&self, signature: &str) -> anyhow::Result<bool>;
+    /// Every entry along with its signature, unreadable ones included
+    fn entries(&self) -> anyhow::Result<Vec<(String, anyhow::Result<CacheEntry>)>>;
+    /// Entries of the books tagged with `tag` (case insensitive), scans every entry by default
+    fn find_by_tag(&self, tag: &str) -> anyhow::Result<Vec<(String, CacheEntry)>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter_map(|(signature, entry)| Some((signature, entry.ok()?)))
+            .filter(|(_, entry)| has_tag(entry, tag))
+            .collect())
+    }
+    /// Folder or database file, for display
+    fn location(&self) -> String;
+}
+
+pub fn has_tag(entry: &CacheEntry, tag: &str) -> bool {
+    entry
+        .book
+        .tags
+        .iter()
+        .any(|t| t.name.eq_ignore_ascii_case(tag))
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use super::{files::FileCacheStorage, CacheStorage};
+use crate::schemas::cache::CacheEntry;
+
+lazy_static! {
+    /// `database file => connection`, shared by every storage of the same file
+    static ref CONNECTIONS: Mutex<HashMap<PathBuf, Arc<Mutex<Connection>>>> =
+        Mutex::new(HashMap::new());
+}
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS entries (
+    signature TEXT PRIMARY KEY,
+    term TEXT NOT NULL,
+    plugin TEXT NOT NULL,
+    plugin_version TEXT,
+    fetched_at TEXT NOT NULL,
+    title TEXT NOT NULL,
+    book TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_by_plugin ON entries (plugin);
+CREATE TABLE IF NOT EXISTS tags (
+    signature TEXT NOT NULL,
+    tag TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (signature, tag)
+);
+CREATE INDEX IF NOT EXISTS tags_by_name ON tags (tag);
+"#;
+
+const COLUMNS: &str = "signature, term, plugin, plugin_version, fetched_at, book";
+
+/// Entries in an embedded SQLite database, books are indexed by tag
+#[derive(Debug, Clone)]
+pub struct SqliteCacheStorage {
+    pub file: PathBuf,
+}
+
+/// Outcome of importing a folder of JSON entries
+#[derive(Debug, Default)]
+pub struct Migration {
+    pub imported: usize,
+    /// Unreadable files, left in place
+    pub skipped: Vec<(String, anyhow::Error)>,
+}
+
+impl SqliteCacheStorage {
+    pub fn new(file: PathBuf) -> Self {
+        Self { file }
+    }
+
+    /// Opened once, the schema is created along the way
+    fn connection(&self) -> anyhow::Result<Arc<Mutex<Connection>>> {
+        let mut connections = CONNECTIONS.lock().unwrap();
+        if let Some(connection) = connections.get(&self.file) {
+            return Ok(connection.clone());
+        }
+
+        if let Some(parent) = self.file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(&self.file)
+            .with_context(|| format!("Opening cache database {:?}", self.file.display()))?;
+        // Other processes may be writing to the same database
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Creating cache schema in {:?}", self.file.display()))?;
+
+        let connection = Arc::new(Mutex::new(connection));
+        connections.insert(self.file.clone(), connection.clone());
+        Ok(connection)
+    }
+
+    fn with_connection<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut Connection) -> anyhow::Result<T>,
+    {
+        let connection = self.connection()?;
+        let mut connection = connection.lock().unwrap();
+        f(&mut connection).with_context(|| format!("Cache database {:?}", self.file.display()))
+    }
+
+    /// Import every readable entry of `files`, replacing entries with the same signature
+    ///
+    /// JSON files are removed once imported if `remove_files` is set.
+    pub fn migrate_from(
+        &self,
+        files: &FileCacheStorage,
+        remove_files: bool,
+    ) -> anyhow::Result<Migration> {
+        let mut migration = Migration::default();
+        for (signature, entry) in files.entries()? {
+            match entry {
+                Ok(entry) => {
+                    self.put(&signature, &entry)?;
+                    if remove_files {
+                        files.remove(&signature)?;
+                    }
+                    migration.imported += 1;
+                }
+                Err(e) => migration.skipped.push((signature, e)),
+            }
+        }
+        Ok(migration)
+    }
+}
+
+impl CacheStorage for SqliteCacheStorage {
+    fn get(&self, signature: &str) -> anyhow::Result<Option<CacheEntry>> {
+        self.with_connection(|connection| {
+            let row = connection
+                .query_row(
+                    &format!("SELECT {COLUMNS} FROM entries WHERE signature = ?1"),
+                    params![signature],
+                    read_row,
+                )
+                .optional()?;
+            row.map(|(_, entry)| entry).transpose()
+        })
+    }
+
+    fn put(&self, signature: &str, entry: &CacheEntry) -> anyhow::Result<()> {
+        let book = serde_json::to_string(&entry.book)?;
+        self.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO entries
+                    (signature, term, plugin, plugin_version, fetched_at, title, book)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    signature,
+                    entry.term,
+                    entry.plugin,
+                    entry.plugin_version,
+                    entry.fetched_at.to_rfc3339(),
+                    entry.book.title,
+                    book
+                ],
+            )?;
+            transaction.execute("DELETE FROM tags WHERE signature = ?1", params![signature])?;
+            for tag in &entry.book.tags {
+                transaction.execute(
+                    "INSERT OR IGNORE INTO tags (signature, tag) VALUES (?1, ?2)",
+                    params![signature, tag.name.trim()],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+    }
+
+    fn remove(&self, signature: &str) -> anyhow::Result<bool> {
+        self.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute("DELETE FROM tags WHERE signature = ?1", params![signature])?;
+            let removed = transaction.execute(
+                "DELETE FROM entries WHERE signature = ?1",
+                params![signature],
+            )?;
+            transaction.commit()?;
+            Ok(removed > 0)
+        })
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<(String, anyhow::Result<CacheEntry>)>> {
+        self.with_connection(|connection| {
+            let mut statement =
+                connection.prepare(&format!("SELECT {COLUMNS} FROM entries ORDER BY signature"))?;
+            let rows = statement.query_map([], read_row)?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+    }
+
+    fn find_by_tag(&self, tag: &str) -> anyhow::Result<Vec<(String, CacheEntry)>> {
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {COLUMNS} FROM entries
+                 WHERE signature IN (SELECT signature FROM tags WHERE tag = ?1)
+                 ORDER BY signature"
+            ))?;
+            let rows = statement.query_map(params![tag.trim()], read_row)?;
+            let mut entries = vec![];
+            for row in rows {
+                let (signature, entry) = row?;
+                // Unreadable entries are only reported by `entries`
+                if let Ok(entry) = entry {
+                    entries.push((signature, entry));
+                }
+            }
+            Ok(entries)
+        })
+    }
+
+    fn location(&self) -> String {
+        self.file.display().to_string()
+    }
+}
+
+/// Columns are read as is, a malformed date or book only fails this entry
+fn read_row(row: &Row) -> rusqlite::Result<(String, anyhow::Result<CacheEntry>)> {
+    let signature: String = row.get(0)?;
+    let term: String = row.get(1)?;
+    let plugin: String = row.get(2)?;
+    let plugin_version: Option<String> = row.get(3)?;
+    let fetched_at: String = row.get(4)?;
+    let book: String = row.get(5)?;
+
+    let entry = (|| {
+        let fetched_at = DateTime::parse_from_rfc3339(&fetched_at)
+            .with_context(|| format!("Invalid fetch date {fetched_at:?} of {signature}"))?
+            .with_timezone(&Local);
+        let book = serde_json::from_str(&book)
+            .with_context(|| format!("Deserializing cached book {signature}"))?;
+        Ok(CacheEntry {
+            term,
+            plugin,
+            plugin_version,
+            fetched_at,
+            book,
+        })
+    })();
+    Ok((signature, entry))
+}
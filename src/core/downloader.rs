@@ -1,8 +1,11 @@
-use super::utils;
+use super::{postprocess, sidecar, utils};
 use crate::{
     core::http::{ContextProvider, MxScraperHttpClient},
-    plugins::FetchResult,
-    schemas::book::{Book, CacheFile, Page},
+    plugins::{DownloadEvent, FetchResult, PluginReporter, STREAM_BUFFER},
+    schemas::{
+        book::{Book, BookAssembler, BookChunk, CacheFile, Chapter, LazyChapter, Page},
+        config::{DownloadFolder, PostProcess},
+    },
     GLOBAL_CONFIG, PLUGIN_MANAGER,
 };
 use anyhow::Context;
@@ -10,9 +13,19 @@ use chrono::Local;
 use futures::future::join_all;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
-use scraper::{Html, Selector};
-use std::{error::Error, io::Write, path::Path, str::FromStr, sync::Arc, time::Duration};
-use tokio::task;
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc::Receiver, Semaphore},
+    task,
+};
 use url::Url;
 
 lazy_static! {
@@ -27,6 +40,9 @@ pub enum Failure {
     Some(anyhow::Error),
 }
 
+/// `(chapter, download result, page)` of a streamed page, see `download_page`
+type PageResult = (usize, anyhow::Result<Option<String>>, Page);
+
 pub enum DownloadStatus {
     Success,
     Fail(Failure),
@@ -84,11 +100,21 @@ pub async fn download(fetched_book: &[Box<FetchResult>]) -> Vec<DownloadStatus>
 pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()> {
     let FetchResult {
         query_term,
-        book,
+        mut book,
         plugin_name,
         cached,
     } = *fetch_result;
-    let (meta_only, delay, verbose, custom_downloader, max_size_mini_batch, downloader) = {
+    let (
+        meta_only,
+        delay,
+        verbose,
+        custom_downloader,
+        fix_extensions,
+        postprocess,
+        max_size_mini_batch,
+        downloader,
+        selection,
+    ) = {
         // TODO: refactor
         let config = GLOBAL_CONFIG.read().unwrap();
         (
@@ -96,20 +122,44 @@ pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()>
             config.delay.clone(),
             config.verbose,
             config.custom_downloader,
+            config.fix_extensions,
+            config.get_postprocess(&plugin_name),
             config.max_size_mini_batch,
             Arc::new(config.get_http_client()),
+            config.__options.selection.clone(),
         )
     };
+    // Pages renamed by a previous run are not downloaded again
+    let find_renamed = fix_extensions || postprocess.as_ref().is_some_and(|p| p.format.is_some());
+
+    // Such plugins save every page of a chapter
+    if selection.pages.is_some()
+        && custom_downloader
+        && PLUGIN_MANAGER
+            .read()
+            .await
+            .can_download_chapter(&plugin_name)
+    {
+        anyhow::bail!(
+            "{plugin_name} downloads whole chapters, --pages requires the custom downloader to be disabled"
+        );
+    }
+
+    // The metadata file only lists the chapters that are saved
+    selection.apply(&mut book);
+    // Chapters saved by the plugin itself have the filenames it gave
+    let plugin_chapters = custom_downloader.then(|| book.chapters.clone());
+    book.apply_page_template(&plugin_name);
 
     let folders = book.get_download_folders(&query_term, &plugin_name);
 
     if meta_only {
         let down_meta_path = book.get_metadata_dest_path(&query_term, &plugin_name);
-        create_metadata_file(&down_meta_path, &book)?;
+        create_metadata_file(&down_meta_path, &plugin_name, &book)?;
         return Ok(());
     } else {
         let meta_path = book.get_metadata_path(&query_term, &plugin_name);
-        create_metadata_file(&meta_path, &book)?;
+        create_metadata_file(&meta_path, &plugin_name, &book)?;
     }
 
     let total_pages = book
@@ -117,13 +167,10 @@ pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()>
         .iter()
         .fold(0, |acc, chapter| acc + chapter.pages.len());
 
-    let pb = MULTI_PROGRESS.add(ProgressBar::new(total_pages as u64));
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:40.green}] {pos:>5}/{len:6} {eta} | {msg}")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
+    let pb = create_book_progress_bar(total_pages as u64);
+    let mut expanded_chapters = vec![];
+    // (chapter, filename, renamed to)
+    let mut renamed_pages = vec![];
 
     for (c, chapter) in book.chapters.iter().enumerate() {
         if verbose {
@@ -144,7 +191,7 @@ pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()>
             ));
         }
 
-        let chunk_title_path = utils::sanitize_string_as_path(&chapter.title, None);
+        let chunk_title_path = book.get_chapter_folder(&plugin_name, chapter);
         let down_dir = folders.download.join(&chunk_title_path);
         let temp_dir = folders.temp.join(&chunk_title_path);
 
@@ -153,53 +200,110 @@ pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()>
                 .with_context(|| format!("Creating chapter {}", temp_dir.display()))?;
         }
 
-        // TODO: refactor with futures::stream + buffered(max_size_mini_batch)
+        let by_plugin = match custom_downloader {
+            true => download_chapter_by_plugin(&plugin_name, chapter, &temp_dir, &pb).await,
+            false => None,
+        };
         let failed_pages = Arc::new(tokio::sync::RwLock::new(Vec::new()));
-        let batches = utils::batch_a_list_of(&chapter.pages, max_size_mini_batch);
-        for batch in batches.into_iter() {
-            let mut join_set = tokio::task::JoinSet::new();
-            for page in batch {
-                let plugin_name = plugin_name.clone();
-                let temp_dir = temp_dir.clone();
-                let down_dir = down_dir.clone();
-                let downloader = downloader.clone();
-
-                join_set.spawn(async move {
-                    let page_clone = page.clone();
-                    let res = download_page(
-                        custom_downloader,
-                        downloader.clone(),
-                        &plugin_name,
-                        &page,
-                        &temp_dir,
-                        &down_dir,
-                    )
-                    .await;
-
-                    tokio::time::sleep(Duration::from_millis(delay.download as u64)).await;
-                    (res, page_clone)
-                });
+        let mut failed_chapter = None;
+        // Both reach the postprocess once their files are in the temp folder
+        let chapter = match by_plugin {
+            Some(res) => {
+                // Files saved before the failure are kept, like the pages that did not fail
+                failed_chapter = res.err();
+                if let Some(plugin_chapters) = &plugin_chapters {
+                    let renamed = adopt_plugin_files(&temp_dir, &plugin_chapters[c], chapter)?;
+                    renamed_pages.extend(renamed.into_iter().map(|(old, new)| (c, old, new)));
+                }
+                chapter
             }
+            None => {
+                let expanded = expand_chapter(downloader.clone(), &plugin_name, chapter)
+                    .await
+                    .with_context(|| format!("Resolving the pages of {:?}", chapter.title))?;
+                let chapter = match expanded {
+                    Some(mut expanded) => {
+                        selection.apply_to_chapter(&mut expanded);
+                        book.apply_page_template_to(&plugin_name, &mut expanded);
+                        pb.inc_length(expanded.pages.len() as u64);
+                        expanded_chapters.push((c, expanded));
+                        &expanded_chapters.last().unwrap().1
+                    }
+                    None => chapter,
+                };
+
+                // Listed once, pages look for their renamed file in it
+                let saved = find_renamed.then(|| Arc::new(list_saved(&temp_dir, &down_dir)));
+
+                // TODO: refactor with futures::stream + buffered(max_size_mini_batch)
+                let batches = utils::batch_a_list_of(&chapter.pages, max_size_mini_batch);
+                for batch in batches.into_iter() {
+                    let mut join_set = tokio::task::JoinSet::new();
+                    for page in batch {
+                        let plugin_name = plugin_name.clone();
+                        let temp_dir = temp_dir.clone();
+                        let down_dir = down_dir.clone();
+                        let downloader = downloader.clone();
+                        let saved = saved.clone();
+
+                        join_set.spawn(async move {
+                            let page_clone = page.clone();
+                            let res = download_page(
+                                custom_downloader,
+                                fix_extensions,
+                                saved.as_deref().map(Vec::as_slice),
+                                downloader.clone(),
+                                &plugin_name,
+                                &page,
+                                &temp_dir,
+                                &down_dir,
+                            )
+                            .await;
 
-            while let Some(res) = join_set.join_next().await {
-                match res {
-                    Ok((download_result, page)) => {
-                        if let Err(err) = download_result {
-                            failed_pages.write().await.push((page, err));
-                        } else {
-                            pb.inc(1);
+                            tokio::time::sleep(Duration::from_millis(delay.download as u64)).await;
+                            (res, page_clone)
+                        });
+                    }
+
+                    while let Some(res) = join_set.join_next().await {
+                        match res {
+                            Ok((download_result, page)) => match download_result {
+                                Err(err) => failed_pages.write().await.push((page, err)),
+                                Ok(renamed) => {
+                                    if let Some(renamed) = renamed {
+                                        renamed_pages.push((c, page.filename, renamed));
+                                    }
+                                    pb.inc(1);
+                                }
+                            },
+                            Err(join_err) => eprintln!("Task panicked: {join_err:?}"),
                         }
                     }
-                    Err(join_err) => eprintln!("Task panicked: {join_err:?}"),
                 }
+                chapter
             }
+        };
+
+        if let Some(postprocess) = &postprocess {
+            let filenames = chapter
+                .pages
+                .iter()
+                .map(|page| current_filename(&renamed_pages, c, &page.filename))
+                .collect();
+            let (renamed, failures) =
+                postprocess_chapter(postprocess.clone(), temp_dir.clone(), filenames).await;
+            for failure in failures {
+                pb.suspend(|| tracing::warn!("{failure}"));
+            }
+            renamed_pages.extend(renamed.into_iter().map(|(old, new)| (c, old, new)));
         }
 
         let failed_pages = failed_pages.read().await;
-        if !failed_pages.is_empty() {
+        if !failed_pages.is_empty() || failed_chapter.is_some() {
             let combined = failed_pages
                 .iter()
                 .map(|(p, err)| format!("{}: {}", p.filename, err))
+                .chain(failed_chapter.map(|err| format!("{}: {err}", chapter.title)))
                 .collect::<Vec<_>>()
                 .join("\n");
 
@@ -207,7 +311,360 @@ pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()>
         }
     }
 
-    // move book
+    // Record the pages that were actually downloaded, under their final name
+    if !expanded_chapters.is_empty() || !renamed_pages.is_empty() {
+        for (c, chapter) in expanded_chapters {
+            book.chapters[c] = chapter;
+        }
+        rename_pages(&mut book, renamed_pages);
+        create_metadata_file(
+            &book.get_metadata_path(&query_term, &plugin_name),
+            &plugin_name,
+            &book,
+        )?;
+    }
+
+    move_to_download_folder(&folders)?;
+    // Pages skipped because they were already saved get theirs too
+    write_sidecars(&plugin_name, &book, &folders.download)
+}
+
+/// Pages of a lazy chapter, `None` if the chapter already has them
+async fn expand_chapter(
+    client: Arc<MxScraperHttpClient>,
+    plugin_name: &str,
+    chapter: &Chapter,
+) -> anyhow::Result<Option<Chapter>> {
+    let pages = match &chapter.lazy {
+        None => return Ok(None),
+        Some(LazyChapter::Plugin) => {
+            PLUGIN_MANAGER
+                .read()
+                .await
+                .expand_chapter(plugin_name, chapter)
+                .await?
+        }
+        Some(LazyChapter::Hint(hint)) => {
+            let url = Url::from_str(&chapter.url)?;
+            let mut context = {
+                let config = GLOBAL_CONFIG.read().unwrap();
+                config.gen_fetch_context_for(Some(&plugin_name.to_string()))
+            };
+            let bytes = client
+                .download(url.clone(), ContextProvider::Concrete(context.clone()))
+                .await?;
+            let body = String::from_utf8(bytes)?;
+            context
+                .headers
+                .insert("Referer".to_string(), url.to_string());
+            hint.evaluate_all(&body, &url)?
+                .iter()
+                .enumerate()
+                .map(|(p, page_url)| Page {
+                    fetch_context: Some(context.clone()),
+                    ..Page::from_url(p as u32 + 1, page_url)
+                })
+                .collect()
+        }
+    };
+
+    Ok(Some(Chapter {
+        pages,
+        lazy: None,
+        ..chapter.clone()
+    }))
+}
+
+/// Download pages as soon as the plugin yields them,
+/// pages that were already saved are kept in the temp folder if the plugin fails midway
+pub async fn stream_and_download(
+    query_term: String,
+    plugin_name: String,
+    reporter: PluginReporter,
+) -> anyhow::Result<FetchResult> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+    let manager = PLUGIN_MANAGER.read().await;
+    let (produced, consumed) = tokio::join!(
+        manager.stream(query_term.clone(), plugin_name.clone(), reporter, sender),
+        consume_book_stream(&query_term, &plugin_name, receiver)
+    );
+    let (book, failed_pages) = consumed?;
+
+    let meta_only = { GLOBAL_CONFIG.read().unwrap().plugins.meta_only };
+    let saved_pages = book.chapters.iter().map(|c| c.pages.len()).sum::<usize>();
+    let fetch_result = FetchResult {
+        query_term: query_term.clone(),
+        book,
+        plugin_name: plugin_name.clone(),
+        cached: false,
+    };
+    let book = &fetch_result.book;
+
+    if meta_only {
+        produced?;
+        let down_meta_path = book.get_metadata_dest_path(&query_term, &plugin_name);
+        create_metadata_file(&down_meta_path, &plugin_name, book)?;
+        return Ok(fetch_result);
+    }
+
+    // Always keep track of what was retrieved so far
+    let meta_path = book.get_metadata_path(&query_term, &plugin_name);
+    create_metadata_file(&meta_path, &plugin_name, book)?;
+
+    if let Err(e) = produced {
+        anyhow::bail!(
+            "{e}\nPartial book kept at {} ({saved_pages} pages received)",
+            book.get_download_folders(&query_term, &plugin_name)
+                .temp
+                .display()
+        );
+    }
+
+    if !failed_pages.is_empty() {
+        let combined = failed_pages
+            .iter()
+            .map(|(p, err)| format!("{}: {}", p.filename, err))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        anyhow::bail!(combined)
+    }
+
+    let folders = book.get_download_folders(&query_term, &plugin_name);
+    move_to_download_folder(&folders)?;
+    write_sidecars(&plugin_name, book, &folders.download)?;
+    Ok(fetch_result)
+}
+
+async fn consume_book_stream(
+    query_term: &str,
+    plugin_name: &str,
+    mut receiver: Receiver<BookChunk>,
+) -> anyhow::Result<(Book, Vec<(Page, anyhow::Error)>)> {
+    let (
+        meta_only,
+        delay,
+        custom_downloader,
+        fix_extensions,
+        postprocess,
+        max_size_mini_batch,
+        downloader,
+    ) = {
+        let config = GLOBAL_CONFIG.read().unwrap();
+        (
+            config.plugins.meta_only,
+            config.delay.clone(),
+            config.custom_downloader,
+            config.fix_extensions,
+            config.get_postprocess(plugin_name),
+            config.max_size_mini_batch,
+            Arc::new(config.get_http_client()),
+        )
+    };
+    let find_renamed = fix_extensions || postprocess.as_ref().is_some_and(|p| p.format.is_some());
+
+    let mut assembler = BookAssembler::new(query_term.to_string());
+    // (chapter, filename, temp folder it was saved in)
+    let mut saved_to = vec![];
+    let mut failed_pages = vec![];
+    let mut renamed_pages = vec![];
+    // Pages renamed after the page template before being downloaded
+    let mut templated_pages = vec![];
+    // temp folder of a chapter => files of its temp and download folders
+    let mut listings: HashMap<PathBuf, Arc<Vec<String>>> = HashMap::new();
+
+    let pb = create_book_progress_bar(0);
+    pb.set_message(format!(
+        "[stream] :: {}",
+        utils::resume_text(query_term, Some(20))
+    ));
+
+    let limit = Arc::new(Semaphore::new(max_size_mini_batch));
+    let mut join_set = tokio::task::JoinSet::new();
+    let mut collect = |res: Result<PageResult, task::JoinError>| match res {
+        Ok((_, Err(err), page)) => failed_pages.push((page, err)),
+        Ok((c, Ok(renamed), page)) => {
+            if let Some(renamed) = renamed {
+                renamed_pages.push((c, page.filename, renamed));
+            }
+            pb.inc(1)
+        }
+        Err(join_err) => eprintln!("Task panicked: {join_err:?}"),
+    };
+
+    while let Some(chunk) = receiver.recv().await {
+        let added = assembler.push(chunk);
+        if meta_only || added.is_empty() {
+            continue;
+        }
+
+        pb.inc_length(added.len() as u64);
+        // Pages may come before the header, they are moved once the book is complete
+        let book = assembler.book();
+        let folders = book.get_download_folders(query_term, plugin_name);
+
+        for (c, page) in added {
+            let chapter = &book.chapters[c];
+            let filename = book.get_page_filename(plugin_name, chapter, &page);
+            let page = match filename != page.filename {
+                true => {
+                    templated_pages.push((c, page.filename.clone(), filename.clone()));
+                    Page { filename, ..page }
+                }
+                false => page,
+            };
+            let chunk_title_path = book.get_chapter_folder(plugin_name, chapter);
+            let down_dir = folders.download.join(&chunk_title_path);
+            let temp_dir = folders.temp.join(&chunk_title_path);
+
+            if !temp_dir.exists() {
+                std::fs::create_dir_all(&temp_dir)
+                    .with_context(|| format!("Creating chapter {}", temp_dir.display()))?;
+            }
+            saved_to.push((c, page.filename.clone(), temp_dir.clone()));
+            let saved = find_renamed.then(|| {
+                listings
+                    .entry(temp_dir.clone())
+                    .or_insert_with(|| Arc::new(list_saved(&temp_dir, &down_dir)))
+                    .clone()
+            });
+
+            let plugin_name = plugin_name.to_string();
+            let downloader = downloader.clone();
+            let limit = limit.clone();
+            let delay = delay.clone();
+            join_set.spawn(async move {
+                let _permit = limit.acquire_owned().await;
+                let res = download_page(
+                    custom_downloader,
+                    fix_extensions,
+                    saved.as_deref().map(Vec::as_slice),
+                    downloader,
+                    &plugin_name,
+                    &page,
+                    &temp_dir,
+                    &down_dir,
+                )
+                .await;
+
+                tokio::time::sleep(Duration::from_millis(delay.download as u64)).await;
+                (c, res, page)
+            });
+        }
+
+        while let Some(res) = join_set.try_join_next() {
+            collect(res);
+        }
+    }
+
+    while let Some(res) = join_set.join_next().await {
+        collect(res);
+    }
+    pb.finish();
+
+    let mut book = assembler.finish();
+    rename_pages(&mut book, templated_pages);
+    let folders = match saved_to.is_empty() {
+        true => None,
+        false => Some(book.get_download_folders(query_term, plugin_name)),
+    };
+    if let Some(folders) = &folders {
+        relocate_pages(&book, plugin_name, folders, &saved_to, &renamed_pages)?;
+    }
+    if let (Some(postprocess), Some(folders)) = (&postprocess, &folders) {
+        for (c, chapter) in book.chapters.iter().enumerate() {
+            let temp_dir = folders
+                .temp
+                .join(book.get_chapter_folder(plugin_name, chapter));
+            let filenames = chapter
+                .pages
+                .iter()
+                .map(|page| current_filename(&renamed_pages, c, &page.filename))
+                .collect();
+            let (renamed, failures) =
+                postprocess_chapter(postprocess.clone(), temp_dir, filenames).await;
+            for failure in failures {
+                tracing::warn!("{failure}");
+            }
+            renamed_pages.extend(renamed.into_iter().map(|(old, new)| (c, old, new)));
+        }
+    }
+    rename_pages(&mut book, renamed_pages);
+    let lazy = book.chapters.iter().filter(|c| c.lazy.is_some()).count();
+    if lazy > 0 && !meta_only {
+        pb.suspend(|| {
+            tracing::warn!("{query_term}: {lazy} lazy chapter(s) are not downloaded when streaming")
+        });
+    }
+
+    Ok((book, failed_pages))
+}
+
+/// Move the pages saved before the folders of the book were final, e.g. before the header
+/// giving its title, to the folders of the complete book
+fn relocate_pages(
+    book: &Book,
+    plugin_name: &str,
+    folders: &DownloadFolder,
+    saved_to: &[(usize, String, PathBuf)],
+    renamed_pages: &[(usize, String, String)],
+) -> anyhow::Result<()> {
+    let mut left = BTreeSet::new();
+    for (c, filename, dir) in saved_to {
+        let expected = folders
+            .temp
+            .join(book.get_chapter_folder(plugin_name, &book.chapters[*c]));
+        if *dir == expected {
+            continue;
+        }
+
+        let filename = current_filename(renamed_pages, *c, filename);
+        let origin = dir.join(&filename);
+        if origin.exists() {
+            std::fs::create_dir_all(&expected)
+                .with_context(|| format!("Creating chapter {}", expected.display()))?;
+            let dest = expected.join(&filename);
+            std::fs::rename(&origin, &dest).with_context(|| {
+                format!("Moving {:?} ==> {:?}", origin.display(), dest.display())
+            })?;
+        }
+        left.insert(dir.clone());
+    }
+
+    let temp = { GLOBAL_CONFIG.read().unwrap().download_folder.temp.clone() };
+    for dir in left {
+        // Up to the temp folder, as long as they are empty
+        for folder in dir
+            .ancestors()
+            .take_while(|folder| folder.starts_with(&temp) && *folder != temp)
+        {
+            if std::fs::remove_dir(folder).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Metadata files of the other tools, written with the pages before they are moved
+fn write_sidecars(plugin_name: &str, book: &Book, folder: &Path) -> anyhow::Result<()> {
+    let formats = { GLOBAL_CONFIG.read().unwrap().sidecars.clone() };
+    sidecar::write_sidecars(&formats, plugin_name, book, folder)
+        .with_context(|| format!("Writing the sidecars of {:?}", book.title))
+}
+
+fn create_book_progress_bar(total_pages: u64) -> ProgressBar {
+    let pb = MULTI_PROGRESS.add(ProgressBar::new(total_pages));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40.green}] {pos:>5}/{len:6} {eta} | {msg}")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb
+}
+
+fn move_to_download_folder(folders: &DownloadFolder) -> anyhow::Result<()> {
     if !folders.download.exists() {
         std::fs::create_dir_all(folders.download.parent().unwrap())?;
         if folders.temp.exists() {
@@ -217,46 +674,217 @@ pub async fn download_book(fetch_result: Box<FetchResult>) -> anyhow::Result<()>
                 format!("Moving {:?} ==> {:?}", origin.display(), dest.display())
             })?;
         }
+    } else if folders.temp.exists() {
+        // Chapters added to a book that was already downloaded
+        merge_into_folder(&folders.temp, &folders.download)?;
+        std::fs::remove_dir_all(&folders.temp)
+            .with_context(|| format!("Removing {:?}", folders.temp.display()))?;
     }
 
     Ok(())
 }
 
+/// Move the content of `origin` into `dest`, files of `origin` replace existing ones
+fn merge_into_folder(origin: &Path, dest: &Path) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(origin)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() && target.is_dir() {
+            merge_into_folder(&entry.path(), &target)?;
+        } else {
+            std::fs::rename(entry.path(), &target).with_context(|| {
+                format!(
+                    "Moving {:?} ==> {:?}",
+                    entry.path().display(),
+                    target.display()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Download pages again straight into the folder of a chapter that was already saved,
+/// files still present are kept
+/// * pages are saved under the extension of their content, `(filename, renamed to)` are returned
+///   along with the failures so that a mismatch is not downloaded again as is
+pub async fn redownload_pages(
+    plugin_name: &str,
+    chapter_folder: &Path,
+    pages: &[Page],
+) -> (Vec<(String, String)>, Vec<(Page, anyhow::Error)>) {
+    let (delay, custom_downloader, downloader) = {
+        let config = GLOBAL_CONFIG.read().unwrap();
+        (
+            config.delay.clone(),
+            config.custom_downloader,
+            Arc::new(config.get_http_client()),
+        )
+    };
+
+    let mut renamed_pages = vec![];
+    let mut failed_pages = vec![];
+    for page in pages {
+        let res = download_page(
+            custom_downloader,
+            true,
+            None,
+            downloader.clone(),
+            plugin_name,
+            page,
+            chapter_folder,
+            chapter_folder,
+        )
+        .await;
+        match res {
+            Ok(Some(renamed)) => renamed_pages.push((page.filename.clone(), renamed)),
+            Ok(None) => {}
+            Err(err) => failed_pages.push((page.clone(), err)),
+        }
+        tokio::time::sleep(Duration::from_millis(delay.download as u64)).await;
+    }
+    (renamed_pages, failed_pages)
+}
+
+/// Let the plugin download the whole chapter, `None` if it can only download url by url
+async fn download_chapter_by_plugin(
+    plugin_name: &str,
+    chapter: &Chapter,
+    temp_dir: &Path,
+    pb: &ProgressBar,
+) -> Option<anyhow::Result<()>> {
+    let manager = PLUGIN_MANAGER.read().await;
+    if !manager.can_download_chapter(plugin_name) {
+        return None;
+    }
+
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut failures = vec![];
+    let track = async {
+        while let Some(event) = receiver.recv().await {
+            match event {
+                DownloadEvent::Progress { filename } => {
+                    tracing::trace!("{plugin_name}: saved {filename}");
+                    // Plugins may produce more files than expected (e.g. postprocessors)
+                    if pb.position() >= pb.length().unwrap_or(0) {
+                        pb.inc_length(1);
+                    }
+                    pb.inc(1);
+                }
+                DownloadEvent::Failed { filename, error } => {
+                    failures.push(format!("{filename}: {error}"));
+                }
+                DownloadEvent::Message(message) => {
+                    pb.suspend(|| tracing::debug!("{plugin_name}: {message}"));
+                }
+            }
+        }
+    };
+
+    let (res, _) = tokio::join!(
+        manager.download_chapter(plugin_name, chapter, temp_dir, sender),
+        track
+    );
+
+    Some(res?.and_then(|_| match failures.is_empty() {
+        true => Ok(()),
+        false => anyhow::bail!(failures.join("\n")),
+    }))
+}
+
+/// Files of a chapter saved by the plugin are named after its own filenames (`plugin_chapter`),
+/// they are renamed to the ones of `chapter` (e.g. from the page template)
+/// * files saved under another name (e.g. by a postprocessor) are returned as
+///   `(filename, renamed to)` so that the metadata lists them
+fn adopt_plugin_files(
+    temp_dir: &Path,
+    plugin_chapter: &Chapter,
+    chapter: &Chapter,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let mut unmatched = vec![];
+    for (plugin_page, page) in plugin_chapter.pages.iter().zip(&chapter.pages) {
+        let dest = temp_dir.join(&page.filename);
+        if dest.exists() {
+            continue;
+        }
+        let origin = temp_dir.join(&plugin_page.filename);
+        if origin.exists() {
+            std::fs::rename(&origin, &dest).with_context(|| {
+                format!("Moving {:?} ==> {:?}", origin.display(), dest.display())
+            })?;
+        } else {
+            unmatched.push((plugin_page, page));
+        }
+    }
+    if unmatched.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let filenames = utils::list_filenames(temp_dir);
+    let renamed = unmatched
+        .into_iter()
+        .filter_map(|(plugin_page, page)| {
+            utils::find_renamed(&filenames, &plugin_page.filename)
+                .map(|found| (page.filename.clone(), found))
+        })
+        .collect();
+    Ok(renamed)
+}
+
+/// Download a page into `tmp_dir` unless it was already saved,
+/// the new filename is returned when its extension is fixed
+/// * `saved` lists the files of both folders (see `list_saved`) to also look for the page
+///   under another extension
+#[allow(clippy::too_many_arguments)]
 async fn download_page(
     use_custom_downloader: bool,
+    fix_extensions: bool,
+    saved: Option<&[String]>,
     downloader: Arc<MxScraperHttpClient>,
     plugin_name: &str,
     original_page: &Page,
     tmp_dir: &Path,
     down_dir: &Path,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Option<String>> {
     // let filename = utils::sanitize_string_as_path(&page.filename);
     let filename = &original_page.filename;
     let tmp_filepath = tmp_dir.join(filename);
     let down_filepath = down_dir.join(filename);
 
     if tmp_filepath.exists() || down_filepath.exists() {
-        return Ok(());
+        return Ok(None);
+    }
+    // Saved by a previous run under its fixed name
+    if let Some(renamed) = saved.and_then(|saved| utils::find_renamed(saved, filename)) {
+        return Ok(Some(renamed));
     }
 
     let page = evaluate_lazy_ops(downloader.clone(), original_page.clone()).await?;
     let url = Url::from_str(&page.url)?;
 
-    if use_custom_downloader {
+    let detected = if use_custom_downloader {
         match PLUGIN_MANAGER
             .read()
             .await
             .download_url(plugin_name, &tmp_filepath, &url)
+            .await
         {
             None => anyhow::bail!(
                 "No custom downloader available for {plugin_name}, please disable it."
             ),
             Some(res) => res?,
         }
+        match fix_extensions {
+            true => infer::get_from_path(&tmp_filepath)
+                .ok()
+                .flatten()
+                .map(|kind| kind.extension().to_string()),
+            false => None,
+        }
     } else {
-        let bytes = {
+        let response = {
             downloader
-                .download(
+                .download_response(
                     url.clone(),
                     match &page.fetch_context {
                         Some(fctx) => ContextProvider::Concrete(fctx.clone()),
@@ -266,6 +894,7 @@ async fn download_page(
                 .await
         }
         .map_err(|e| anyhow::anyhow!("{e}: {original_page:?}"))?;
+        let bytes = response.body;
 
         let mut file = std::fs::File::create(&tmp_filepath).with_context(|| {
             format!(
@@ -276,13 +905,97 @@ async fn download_page(
         })?;
         file.write(&bytes)
             .with_context(|| format!("Downloading page: {url}"))?;
+
+        match fix_extensions {
+            true => utils::detect_extension(
+                &bytes,
+                response.headers.get("content-type").map(String::as_str),
+            ),
+            false => None,
+        }
+    };
+
+    let Some(renamed) = detected.and_then(|ext| utils::fix_extension(filename, &ext)) else {
+        return Ok(None);
+    };
+    let renamed_filepath = tmp_dir.join(&renamed);
+    std::fs::rename(&tmp_filepath, &renamed_filepath).with_context(|| {
+        format!(
+            "Moving {:?} ==> {:?}",
+            tmp_filepath.display(),
+            renamed_filepath.display()
+        )
+    })?;
+    Ok(Some(renamed))
+}
+
+/// Convert the pages of a chapter saved in `temp_dir`, pages that cannot be converted are kept,
+/// `(filename, renamed to)` of the pages whose extension changed along with the failures
+async fn postprocess_chapter(
+    options: PostProcess,
+    temp_dir: PathBuf,
+    filenames: Vec<String>,
+) -> (Vec<(String, String)>, Vec<String>) {
+    let res = task::spawn_blocking(move || {
+        let mut renamed = vec![];
+        let mut failures = vec![];
+        for filename in filenames {
+            // Already moved to the download folder
+            if !temp_dir.join(&filename).exists() {
+                continue;
+            }
+            match postprocess::process_page(&temp_dir, &filename, &options) {
+                Ok(Some(new)) => renamed.push((filename, new)),
+                Ok(None) => {}
+                Err(e) => failures.push(format!("Post-processing {filename}: {e:#}")),
+            }
+        }
+        (renamed, failures)
+    })
+    .await;
+
+    res.unwrap_or_else(|e| (vec![], vec![format!("Post-processing panicked: {e:?}")]))
+}
+
+/// Current filename of a page, renames are applied in order
+fn current_filename(renamed_pages: &[(usize, String, String)], c: usize, filename: &str) -> String {
+    renamed_pages
+        .iter()
+        .fold(filename.to_string(), |filename, (rc, old, new)| {
+            match *rc == c && *old == filename {
+                true => new.clone(),
+                false => filename,
+            }
+        })
+}
+
+/// Record the fixed filenames, `(chapter, filename, renamed to)`
+pub fn rename_pages(book: &mut Book, renamed_pages: Vec<(usize, String, String)>) {
+    for (c, filename, renamed) in renamed_pages {
+        let Some(chapter) = book.chapters.get_mut(c) else {
+            continue;
+        };
+        if let Some(page) = chapter.pages.iter_mut().find(|p| p.filename == filename) {
+            page.filename = renamed;
+        }
     }
-    Ok(())
 }
 
+/// Files of the temp folder of a chapter followed by the ones of its download folder
+fn list_saved(temp_dir: &Path, down_dir: &Path) -> Vec<String> {
+    let mut filenames = utils::list_filenames(temp_dir);
+    filenames.extend(utils::list_filenames(down_dir));
+    filenames
+}
+
+/// Follow the intermediate link hints of a page, one request per hop
 async fn evaluate_lazy_ops(client: Arc<MxScraperHttpClient>, page: Page) -> anyhow::Result<Page> {
-    if let Some(hint) = &page.intermediate_link_hint {
-        let url = Url::from_str(&page.url)?;
+    if page.intermediate_link_hint.is_empty() {
+        return Ok(page);
+    }
+
+    let mut url = Url::from_str(&page.url)?;
+    for (hop, hint) in page.intermediate_link_hint.iter().enumerate() {
         let bytes = {
             client
                 .download(
@@ -294,42 +1007,31 @@ async fn evaluate_lazy_ops(client: Arc<MxScraperHttpClient>, page: Page) -> anyh
                 )
                 .await
         }?;
-        let html = String::from_utf8(bytes)?;
-        let document = Html::parse_document(&html);
-        let selector =
-            Selector::parse(&hint.selector).map_err(|e| anyhow::anyhow!("Bad selector: {e}"))?;
-
-        let evaluated_url = match document.select(&selector).next() {
-            Some(value) => value.attr(&hint.attribute).with_context(|| {
-                format!(
-                    "Retrieving attribute {:?} of {:?} using {page:?}",
-                    hint.attribute,
-                    value.text()
-                )
-            })?,
-            None => anyhow::bail!("Could not find evaluate {:?} at {}", hint, page.url),
-        };
-
-        let url = Url::from_str(evaluated_url)?;
-
-        return Ok(Page {
-            url: url.to_string(),
-            // filename: utils::extract_filename(&url).unwrap_or(page.filename),
-            intermediate_link_hint: None,
-            ..page
-        });
+        let body = String::from_utf8(bytes)?;
+        url = hint
+            .evaluate(&body, &url)
+            .with_context(|| format!("Hop {} of {}", hop + 1, page.url))?;
     }
 
-    Ok(page)
+    Ok(Page {
+        url: url.to_string(),
+        // filename: utils::extract_filename(&url).unwrap_or(page.filename),
+        intermediate_link_hint: vec![],
+        ..page
+    })
 }
 
-fn create_metadata_file(file: &Path, book: &Book) -> anyhow::Result<()> {
+pub fn create_metadata_file(file: &Path, plugin_name: &str, book: &Book) -> anyhow::Result<()> {
     let version = env!("CARGO_PKG_VERSION").to_owned();
     let time = Local::now().to_string();
 
+    let selection = { GLOBAL_CONFIG.read().unwrap().__options.selection.clone() };
+
     let cache_file = CacheFile {
         engine: format!("mx-scraper {version}"),
         date: time,
+        selection: Some(selection).filter(|selection| !selection.is_empty()),
+        plugin: Some(plugin_name.to_string()),
         book: book.clone(),
     };
 
@@ -1,12 +1,14 @@
 use crate::{
-    core::http::{ContextProvider, MxScraperHttpResolver},
+    core::http::{ContextProvider, HttpResponse, MxScraperHttpResolver},
     schemas::config::AuthKind,
 };
 use reqwest::{
     blocking::{self},
+    header::HeaderMap,
     redirect::Policy,
-    Client,
+    Client, StatusCode,
 };
+use std::collections::HashMap;
 use url::Url;
 
 #[derive(Clone)]
@@ -19,10 +21,21 @@ impl MxScraperHttpResolver for BasicRequestResolver {
     }
 
     fn get(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>> {
+        self.get_response(url, context)
+            .map(|response| response.body)
+    }
+
+    async fn get_async(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>> {
+        self.get_response_async(url, context)
+            .await
+            .map(|response| response.body)
+    }
+
+    fn get_response(&self, url: Url, context: ContextProvider) -> anyhow::Result<HttpResponse> {
         let context = context.get();
         let req_headers = context.to_headermap()?;
 
-        let bytes = std::thread::spawn(move || {
+        std::thread::spawn(move || {
             // FIXME:
             // reqwest::blocking acting sus
             // "Cannot drop a runtime in a context where blocking is not allowed"
@@ -39,19 +52,25 @@ impl MxScraperHttpResolver for BasicRequestResolver {
                 };
             }
             let response = builder.send()?;
-            if !response.status().is_success() {
-                anyhow::bail!(format!("{}: {}", response.status(), url));
-            }
+            check_status(response.status(), &url)?;
 
-            Ok(response.bytes())
+            let status = response.status().as_u16();
+            let headers = collect_headers(response.headers());
+            Ok(HttpResponse {
+                status,
+                headers,
+                body: response.bytes()?.into(),
+            })
         })
         .join()
-        .unwrap();
-
-        Ok(bytes??.into())
+        .unwrap()
     }
 
-    async fn get_async(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>> {
+    async fn get_response_async(
+        &self,
+        url: Url,
+        context: ContextProvider,
+    ) -> anyhow::Result<HttpResponse> {
         let context = context.get();
         let req_headers = context.to_headermap()?;
 
@@ -64,10 +83,29 @@ impl MxScraperHttpResolver for BasicRequestResolver {
             };
         }
         let response = builder.send().await?;
-        if !response.status().is_success() {
-            anyhow::bail!(format!("{}: {}", response.status(), url));
-        }
+        check_status(response.status(), &url)?;
 
-        Ok(response.bytes().await?.into())
+        let status = response.status().as_u16();
+        let headers = collect_headers(response.headers());
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: response.bytes().await?.into(),
+        })
     }
 }
+
+/// `304 Not Modified` only answers conditional requests, it is left to the caller
+fn check_status(status: StatusCode, url: &Url) -> anyhow::Result<()> {
+    if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+        anyhow::bail!(format!("{}: {}", status, url));
+    }
+    Ok(())
+}
+
+fn collect_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
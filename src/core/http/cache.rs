@@ -0,0 +1,254 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::schemas::{config::HttpCacheOptions, rfc3339};
+
+use super::{FetchContext, HttpResponse};
+
+/// Response headers kept along with the body
+const STORED_HEADERS: [&str; 6] = [
+    "cache-control",
+    "content-type",
+    "date",
+    "etag",
+    "expires",
+    "last-modified",
+];
+
+/// On-disk cache of GET responses
+/// * `<key>.json` holds the status, headers and storage date, `<key>.body` the raw body
+/// * the key is a digest of the method, url and `vary` headers of the request
+/// * `Cache-Control` (`no-store`, `no-cache`, `max-age`) and `Expires` decide how long a
+///   response is fresh, stale responses are revalidated with `ETag`/`Last-Modified`
+/// * `dev_mode` keeps everything and never revalidates
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    options: HttpCacheOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredResponse {
+    pub url: String,
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    #[serde(with = "rfc3339")]
+    pub stored_at: DateTime<Local>,
+}
+
+/// A request looked up in the cache, see `HttpCache::complete`
+#[derive(Debug)]
+pub struct CachedRequest {
+    key: String,
+    url: Url,
+    stored: Option<StoredResponse>,
+    fresh: bool,
+}
+
+/// Subset of `Cache-Control`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub max_age: Option<Duration>,
+}
+
+impl HttpCache {
+    pub fn new(options: HttpCacheOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn key(&self, url: &Url, context: &FetchContext) -> String {
+        let mut data = format!("GET {url}");
+        for name in &self.options.vary {
+            let value = match name.eq_ignore_ascii_case("authorization") {
+                true => context.auth.as_ref().map(|auth| auth.stringify()),
+                false => context
+                    .headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone()),
+            };
+            if let Some(value) = value {
+                data.push_str(&format!("\n{}: {value}", name.to_lowercase()));
+            }
+        }
+        hex::encode(Sha256::digest(data))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.options.folder.join(format!("{key}.json"))
+    }
+
+    fn body_path(&self, key: &str) -> PathBuf {
+        self.options.folder.join(format!("{key}.body"))
+    }
+
+    pub fn lookup(&self, url: &Url, context: &FetchContext) -> CachedRequest {
+        self.lookup_at(url, context, Local::now())
+    }
+
+    pub fn lookup_at(
+        &self,
+        url: &Url,
+        context: &FetchContext,
+        now: DateTime<Local>,
+    ) -> CachedRequest {
+        let key = self.key(url, context);
+        // A broken entry is a miss, it is overwritten by the next response
+        let stored = std::fs::read_to_string(self.meta_path(&key))
+            .ok()
+            .and_then(|content| serde_json::from_str::<StoredResponse>(&content).ok())
+            .filter(|_| self.body_path(&key).exists());
+        let fresh = stored
+            .as_ref()
+            .is_some_and(|stored| self.options.dev_mode || stored.is_fresh(now));
+        CachedRequest {
+            key,
+            url: url.clone(),
+            stored,
+            fresh,
+        }
+    }
+
+    /// Body of a fresh response, no request is needed
+    pub fn fresh_body(&self, request: &CachedRequest) -> Option<Vec<u8>> {
+        if !request.fresh {
+            return None;
+        }
+        tracing::debug!("HTTP cache hit for {}", request.url);
+        std::fs::read(self.body_path(&request.key)).ok()
+    }
+
+    /// Store `response` or, on `304 Not Modified`, refresh the stored one
+    pub fn complete(
+        &self,
+        request: CachedRequest,
+        response: HttpResponse,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.complete_at(request, response, Local::now())
+    }
+
+    pub fn complete_at(
+        &self,
+        request: CachedRequest,
+        response: HttpResponse,
+        now: DateTime<Local>,
+    ) -> anyhow::Result<Vec<u8>> {
+        if response.status == 304 {
+            let Some(mut stored) = request.stored else {
+                anyhow::bail!("304: {} without a cached response", request.url);
+            };
+            tracing::debug!("HTTP cache revalidated {}", request.url);
+            stored.headers.extend(keep_headers(&response.headers));
+            stored.stored_at = now;
+            self.write_meta(&request.key, &stored)?;
+            return std::fs::read(self.body_path(&request.key))
+                .with_context(|| format!("Reading cached body of {}", request.url));
+        }
+
+        if self.options.dev_mode || is_storable(&response.headers) {
+            let stored = StoredResponse {
+                url: request.url.to_string(),
+                status: response.status,
+                headers: keep_headers(&response.headers),
+                stored_at: now,
+            };
+            std::fs::create_dir_all(&self.options.folder)?;
+            std::fs::write(self.body_path(&request.key), &response.body)
+                .with_context(|| format!("Writing cached body of {}", request.url))?;
+            self.write_meta(&request.key, &stored)?;
+        }
+        Ok(response.body)
+    }
+
+    fn write_meta(&self, key: &str, stored: &StoredResponse) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(stored)?;
+        std::fs::write(self.meta_path(key), content)
+            .with_context(|| format!("Writing cached response of {}", stored.url))
+    }
+}
+
+impl CachedRequest {
+    /// `context` with the validators of the stored response
+    pub fn conditional(&self, mut context: FetchContext) -> FetchContext {
+        let Some(stored) = &self.stored else {
+            return context;
+        };
+        if let Some(etag) = stored.headers.get("etag") {
+            context
+                .headers
+                .insert("If-None-Match".to_string(), etag.clone());
+        }
+        if let Some(last_modified) = stored.headers.get("last-modified") {
+            context
+                .headers
+                .insert("If-Modified-Since".to_string(), last_modified.clone());
+        }
+        context
+    }
+}
+
+impl StoredResponse {
+    pub fn is_fresh(&self, now: DateTime<Local>) -> bool {
+        let cache_control = CacheControl::parse(self.headers.get("cache-control"));
+        if cache_control.no_cache || cache_control.no_store {
+            return false;
+        }
+        let fresh_until = match cache_control.max_age {
+            Some(max_age) => TimeDelta::from_std(max_age)
+                .ok()
+                .and_then(|max_age| self.stored_at.checked_add_signed(max_age)),
+            None => self
+                .headers
+                .get("expires")
+                .and_then(|expires| DateTime::parse_from_rfc2822(expires).ok())
+                .map(|expires| expires.with_timezone(&Local)),
+        };
+        fresh_until.is_some_and(|fresh_until| now < fresh_until)
+    }
+}
+
+impl CacheControl {
+    pub fn parse(header: Option<&String>) -> Self {
+        let mut cache_control = Self::default();
+        for directive in header.into_iter().flat_map(|header| header.split(',')) {
+            let (name, value) = directive
+                .split_once('=')
+                .map_or((directive, None), |(name, value)| (name, Some(value)));
+            match name.trim().to_lowercase().as_str() {
+                "no-store" => cache_control.no_store = true,
+                "no-cache" => cache_control.no_cache = true,
+                "max-age" => {
+                    cache_control.max_age = value
+                        .and_then(|value| value.trim().trim_matches('"').parse().ok())
+                        .map(Duration::from_secs)
+                }
+                _ => {}
+            }
+        }
+        cache_control
+    }
+}
+
+/// Worth keeping: not forbidden, and either fresh for a while or revalidatable
+fn is_storable(headers: &HashMap<String, String>) -> bool {
+    let cache_control = CacheControl::parse(headers.get("cache-control"));
+    !cache_control.no_store
+        && (cache_control.max_age.is_some()
+            || ["expires", "etag", "last-modified"]
+                .iter()
+                .any(|name| headers.contains_key(*name)))
+}
+
+fn keep_headers(headers: &HashMap<String, String>) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| STORED_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
@@ -1,4 +1,6 @@
-use crate::core::http::{basic::BasicRequestResolver, ContextProvider, MxScraperHttpResolver};
+use crate::core::http::{
+    basic::BasicRequestResolver, ContextProvider, HttpResponse, MxScraperHttpResolver,
+};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -31,4 +33,18 @@ impl MxScraperHttpResolver for CloudflareWorkerResolver {
             .get_async(self.actual_url(url), context)
             .await
     }
+
+    fn get_response(&self, url: Url, context: ContextProvider) -> anyhow::Result<HttpResponse> {
+        BasicRequestResolver.get_response(self.actual_url(url), context)
+    }
+
+    async fn get_response_async(
+        &self,
+        url: Url,
+        context: ContextProvider,
+    ) -> anyhow::Result<HttpResponse> {
+        BasicRequestResolver
+            .get_response_async(self.actual_url(url), context)
+            .await
+    }
 }
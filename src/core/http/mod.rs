@@ -1,5 +1,5 @@
 use crate::{
-    core::http::basic::BasicRequestResolver,
+    core::http::{basic::BasicRequestResolver, cache::HttpCache},
     schemas::{config::AuthKind, cookies::NetscapeCookie},
     FETCH_SEMAPHORE,
 };
@@ -10,6 +10,7 @@ use tokio::sync::Semaphore;
 use url::Url;
 
 pub mod basic;
+pub mod cache;
 pub mod cf_worker;
 pub mod flaresolverr;
 
@@ -72,15 +73,50 @@ pub async fn update_fetch_semaphore_count(new_count: usize) {
     *rw = Arc::new(Semaphore::new(new_count));
 }
 
+/// A successful response, or `304 Not Modified` to a conditional request
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// Lowercase names
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Body of a resolver that does not expose headers
+    pub fn from_body(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            headers: HashMap::new(),
+            body,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait MxScraperHttpResolver: Sync + Send {
     fn can_download(&self) -> bool;
     fn get(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>>;
     async fn get_async(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>>;
+    /// Status and headers along with the body, only the body is known by default
+    fn get_response(&self, url: Url, context: ContextProvider) -> anyhow::Result<HttpResponse> {
+        self.get(url, context).map(HttpResponse::from_body)
+    }
+    async fn get_response_async(
+        &self,
+        url: Url,
+        context: ContextProvider,
+    ) -> anyhow::Result<HttpResponse> {
+        self.get_async(url, context)
+            .await
+            .map(HttpResponse::from_body)
+    }
 }
 
 pub struct MxScraperHttpClient {
     resolver: Arc<dyn MxScraperHttpResolver>,
+    /// Page requests only, downloads are never cached
+    cache: Option<HttpCache>,
 }
 
 #[derive(Debug)]
@@ -101,30 +137,74 @@ impl ContextProvider {
 
 impl MxScraperHttpClient {
     pub fn new(resolver: Arc<dyn MxScraperHttpResolver>) -> Self {
-        Self { resolver }
+        Self {
+            resolver,
+            cache: None,
+        }
+    }
+
+    pub fn with_cache(mut self, cache: Option<HttpCache>) -> Self {
+        self.cache = cache;
+        self
     }
 }
 
 impl MxScraperHttpClient {
     pub fn get(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>> {
-        self.resolver.get(url, context)
+        let Some(cache) = &self.cache else {
+            return self.resolver.get(url, context);
+        };
+
+        let context = context.get();
+        let request = cache.lookup(&url, &context);
+        if let Some(body) = cache.fresh_body(&request) {
+            return Ok(body);
+        }
+        let context = ContextProvider::Concrete(request.conditional(context));
+        let response = self.resolver.get_response(url, context)?;
+        cache.complete(request, response)
     }
 
     pub async fn get_async(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>> {
-        let rw = FETCH_SEMAPHORE.read().await;
-        let _permit = rw.acquire().await;
+        let Some(cache) = &self.cache else {
+            let rw = FETCH_SEMAPHORE.read().await;
+            let _permit = rw.acquire().await;
+            return self.resolver.get_async(url, context).await;
+        };
 
-        self.resolver.get_async(url, context).await
+        let context = context.get();
+        let request = cache.lookup(&url, &context);
+        if let Some(body) = cache.fresh_body(&request) {
+            return Ok(body);
+        }
+        let context = ContextProvider::Concrete(request.conditional(context));
+        let response = {
+            let rw = FETCH_SEMAPHORE.read().await;
+            let _permit = rw.acquire().await;
+            self.resolver.get_response_async(url, context).await?
+        };
+        cache.complete(request, response)
     }
 
     pub async fn download(&self, url: Url, context: ContextProvider) -> anyhow::Result<Vec<u8>> {
+        self.download_response(url, context)
+            .await
+            .map(|response| response.body)
+    }
+
+    /// Same as `download`, along with the headers when the resolver exposes them
+    pub async fn download_response(
+        &self,
+        url: Url,
+        context: ContextProvider,
+    ) -> anyhow::Result<HttpResponse> {
         let rw = FETCH_SEMAPHORE.read().await;
         let _permit = rw.acquire().await;
 
         if self.resolver.can_download() {
-            return self.resolver.get_async(url, context).await;
+            return self.resolver.get_response_async(url, context).await;
         }
 
-        BasicRequestResolver.get_async(url, context).await
+        BasicRequestResolver.get_response_async(url, context).await
     }
 }
@@ -0,0 +1,195 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+    core::{sidecar, sync},
+    schemas::{
+        book::{Book, CacheFile, Chapter},
+        config::SidecarFormat,
+        library::{DownloadState, LibraryBook, LibraryIndex},
+    },
+};
+
+/// Metadata files that could not be read
+pub type ScanErrors = Vec<(PathBuf, anyhow::Error)>;
+
+/// Index every book saved under the `metadata` and `download` folders
+/// * metadata files live in `<metadata>/<book folder>/<metadata file>`, by default
+///   `<metadata>/<plugin>/<title folder>/<source id>.json` (see the `paths` templates)
+/// * a copy is moved along with the chapters to `<download>/<book folder>/`
+/// * the most recent of the two is used when a book has both
+/// * files written by the enabled `sidecars` formats are not counted as pages
+pub fn scan(
+    metadata: &Path,
+    download: &Path,
+    sidecars: &[SidecarFormat],
+) -> (LibraryIndex, ScanErrors) {
+    // (book folder, file name) => (path, modified)
+    let mut candidates: IndexMap<(PathBuf, String), (PathBuf, SystemTime)> = IndexMap::new();
+    for root in [metadata, download] {
+        for (book_folder, path) in metadata_files(root) {
+            let modified = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let key = (book_folder, file_name);
+            match candidates.get(&key) {
+                Some((_, newest)) if *newest >= modified => {}
+                _ => {
+                    candidates.insert(key, (path, modified));
+                }
+            }
+        }
+    }
+
+    let mut books = vec![];
+    let mut errors = vec![];
+    for ((book_folder, _), (path, _)) in candidates {
+        match sync::read_metadata(&path) {
+            Ok(saved) => {
+                // Older metadata files only have the plugin as their first folder
+                let plugin = saved.plugin.clone().unwrap_or_else(|| {
+                    book_folder
+                        .components()
+                        .next()
+                        .map(|c| c.as_os_str().to_string_lossy().to_string())
+                        .unwrap_or_default()
+                });
+                let folder = download.join(&book_folder);
+                books.push(index_book(saved, plugin, path, folder, sidecars));
+            }
+            Err(e) => errors.push((path, e)),
+        }
+    }
+    books.sort_by(|a, b| (&a.plugin, &a.title).cmp(&(&b.plugin, &b.title)));
+
+    (LibraryIndex::new(books), errors)
+}
+
+/// `(book folder relative to root, path)` of the JSON files below `root`,
+/// the first folder of a branch holding one is a book folder and is not searched further
+fn metadata_files(root: &Path) -> Vec<(PathBuf, PathBuf)> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<(PathBuf, PathBuf)>) {
+        let mut entries = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        let json = entries
+            .iter()
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<_>>();
+        if dir != root && !json.is_empty() {
+            let relative = dir.strip_prefix(root).unwrap_or(dir).to_path_buf();
+            files.extend(
+                json.into_iter()
+                    .map(|path| (relative.clone(), path.clone())),
+            );
+            return;
+        }
+        for subfolder in entries.iter().filter(|path| path.is_dir()) {
+            walk(root, subfolder, files);
+        }
+    }
+
+    let mut files = vec![];
+    walk(root, root, &mut files);
+    files
+}
+
+fn index_book(
+    saved: CacheFile,
+    plugin: String,
+    metadata: PathBuf,
+    folder: PathBuf,
+    sidecars: &[SidecarFormat],
+) -> LibraryBook {
+    let book = saved.book;
+    let mut downloaded_chapters = 0;
+    let mut pages = 0;
+    let mut downloaded_pages = 0;
+    for chapter in &book.chapters {
+        let (expected, found) = chapter_progress(&folder, &book, &plugin, chapter, sidecars);
+        pages += expected;
+        downloaded_pages += found;
+        let complete = match expected {
+            // Lazy chapter or custom downloader, pages are only known on disk
+            0 => found > 0,
+            expected => found >= expected,
+        };
+        if complete {
+            downloaded_chapters += 1;
+        }
+    }
+
+    let state = if !book.chapters.is_empty() && downloaded_chapters == book.chapters.len() {
+        DownloadState::Complete
+    } else if downloaded_chapters > 0 || downloaded_pages > 0 {
+        DownloadState::Partial
+    } else {
+        DownloadState::Missing
+    };
+
+    LibraryBook {
+        title: book.title,
+        plugin,
+        url: book.url,
+        source_id: book.source_id,
+        authors: book.authors.into_iter().map(|a| a.name).collect(),
+        tags: book.tags.into_iter().map(|t| t.name).collect(),
+        saved_at: saved.date,
+        metadata,
+        folder,
+        state,
+        chapters: book.chapters.len(),
+        downloaded_chapters,
+        pages,
+        downloaded_pages,
+        selection: saved.selection,
+    }
+}
+
+/// `(listed pages, pages on disk)`, every file of the folder but the sidecars counts when no page
+/// is listed
+fn chapter_progress(
+    folder: &Path,
+    book: &Book,
+    plugin: &str,
+    chapter: &Chapter,
+    sidecars: &[SidecarFormat],
+) -> (usize, usize) {
+    let chapter_folder = folder.join(book.get_chapter_folder(plugin, chapter));
+    if !chapter_folder.is_dir() {
+        return (chapter.pages.len(), 0);
+    }
+    if chapter.pages.is_empty() {
+        let files = std::fs::read_dir(&chapter_folder)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter(|entry| {
+                        let filename = entry.file_name().to_string_lossy().to_string();
+                        !sidecar::is_sidecar(sidecars, chapter, &filename)
+                    })
+                    .count()
+            })
+            .unwrap_or(0);
+        return (0, files);
+    }
+    let found = chapter
+        .pages
+        .iter()
+        .filter(|page| chapter_folder.join(&page.filename).exists())
+        .count();
+    (chapter.pages.len(), found)
+}
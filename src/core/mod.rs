@@ -1,3 +1,9 @@
+pub mod cache;
 pub mod downloader;
 pub mod http;
+pub mod library;
+pub mod postprocess;
+pub mod sidecar;
+pub mod sync;
 pub mod utils;
+pub mod verify;
@@ -0,0 +1,282 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageReader, Rgba,
+};
+
+use crate::{
+    core::utils,
+    schemas::config::{PageFormat, PostProcess},
+};
+
+/// Formats converted when `from` is empty, animated GIFs have to be listed explicitly
+const STILL_FORMATS: [&str; 3] = ["jpg", "png", "webp"];
+
+/// Formats that can be listed in `from`, the others cannot be decoded (e.g. avif)
+pub const DECODED_FORMATS: [&str; 4] = ["jpg", "png", "webp", "gif"];
+
+/// Flags of the `VP8X` chunk of a WebP file telling that it has EXIF or XMP chunks
+const VP8X_EXIF_XMP: u8 = 0x08 | 0x04;
+
+/// Convert, downsize or strip the EXIF of a page of `dir`,
+/// the new filename is returned when its extension changes
+/// * pages in other formats than `from` are left untouched
+/// * JPEG, PNG and WebP pages are stripped without being encoded again, unless they have to be
+///   rotated
+/// * WebP is only encoded lossless, downsized lossy WebP pages become JPEG ones
+pub fn process_page(
+    dir: &Path,
+    filename: &str,
+    options: &PostProcess,
+) -> anyhow::Result<Option<String>> {
+    let path = dir.join(filename);
+    let Some(kind) = infer::get_from_path(&path).with_context(|| format!("Reading {path:?}"))?
+    else {
+        return Ok(None);
+    };
+    let current = kind.extension();
+    let selected = match options.from.is_empty() {
+        true => STILL_FORMATS.iter().any(|f| utils::same_format(f, current)),
+        false => options.from.iter().any(|f| utils::same_format(f, current)),
+    };
+    if !selected {
+        return Ok(None);
+    }
+
+    let target = options
+        .format
+        .filter(|format| !utils::same_format(format.extension(), current));
+    let oversized = match options.max_dimension {
+        Some(max) => {
+            let (width, height) = image::image_dimensions(&path)
+                .with_context(|| format!("Reading the dimensions of {path:?}"))?;
+            width > max || height > max
+        }
+        None => false,
+    };
+    if target.is_none() && !oversized {
+        if !options.strip_exif {
+            return Ok(None);
+        }
+        let stripped = match current {
+            "jpg" if orientation(&path)? == Orientation::NoTransforms => strip_jpeg(&path)?,
+            "png" => strip_png(&path)?,
+            "webp" if orientation(&path)? == Orientation::NoTransforms => strip_webp(&path)?,
+            // Rotated pages and other formats lose their metadata when encoded again
+            _ => false,
+        };
+        if stripped {
+            return Ok(None);
+        }
+    }
+
+    // Downsized pages keep their format when possible
+    let format = target.unwrap_or(match current {
+        "jpg" => PageFormat::Jpg,
+        "webp" if is_lossless_webp(&path)? => PageFormat::Webp,
+        // Several times larger once encoded lossless
+        "webp" => PageFormat::Jpg,
+        _ => PageFormat::Png,
+    });
+    let mut decoder = ImageReader::open(&path)?
+        .with_guessed_format()?
+        .into_decoder()
+        .with_context(|| format!("Decoding {path:?}"))?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    if let Some(max) = options.max_dimension {
+        if image.width() > max || image.height() > max {
+            image = image.resize(max, max, FilterType::Lanczos3);
+        }
+    }
+
+    let renamed = utils::fix_extension(filename, format.extension());
+    let dest = dir.join(renamed.as_deref().unwrap_or(filename));
+    let part = dir.join(format!("{filename}.part"));
+    encode(&image, format, options.quality, &part)
+        .with_context(|| format!("Encoding {dest:?} as {}", format.extension()))?;
+    std::fs::rename(&part, &dest)?;
+    if renamed.is_some() {
+        std::fs::remove_file(&path)?;
+    }
+    Ok(renamed)
+}
+
+fn orientation(path: &Path) -> anyhow::Result<Orientation> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    Ok(decoder.orientation()?)
+}
+
+fn encode(
+    image: &DynamicImage,
+    format: PageFormat,
+    quality: u8,
+    dest: &Path,
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(dest)?);
+    match format {
+        PageFormat::Jpg => flatten_alpha(image)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, quality))?,
+        PageFormat::Png => image.write_with_encoder(PngEncoder::new(&mut writer))?,
+        PageFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut writer))?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Transparent pixels over a white background, JPEG has no alpha channel
+fn flatten_alpha(image: &DynamicImage) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+    let mut rgba = image.to_rgba8();
+    for Rgba([r, g, b, a]) in rgba.pixels_mut() {
+        let alpha = *a as u32;
+        for channel in [r, g, b] {
+            *channel = ((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+        *a = 255;
+    }
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8())
+}
+
+/// Remove the APP1 EXIF segments, `false` if the file is not a valid JPEG
+fn strip_jpeg(path: &Path) -> anyhow::Result<bool> {
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Ok(false);
+    }
+
+    let mut stripped = bytes[..2].to_vec();
+    let mut pos = 2;
+    let mut removed = false;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return Ok(false);
+        }
+        let marker = bytes[pos + 1];
+        // Start of scan, the entropy coded data follows
+        if marker == 0xDA {
+            break;
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return Ok(false);
+        }
+        let is_exif = marker == 0xE1 && bytes[pos + 4..end].starts_with(b"Exif\0\0");
+        match is_exif {
+            true => removed = true,
+            false => stripped.extend_from_slice(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+
+    if removed {
+        stripped.extend_from_slice(&bytes[pos..]);
+        std::fs::write(path, stripped)?;
+    }
+    Ok(true)
+}
+
+/// Remove the `eXIf` chunks, `false` if the file is not a valid PNG
+fn strip_png(path: &Path) -> anyhow::Result<bool> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(SIGNATURE) {
+        return Ok(false);
+    }
+
+    let mut stripped = SIGNATURE.to_vec();
+    let mut pos = SIGNATURE.len();
+    let mut removed = false;
+    while pos + 8 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        // length, type, data and CRC
+        let end = pos + 12 + len;
+        if end > bytes.len() {
+            return Ok(false);
+        }
+        match &bytes[pos + 4..pos + 8] == b"eXIf" {
+            true => removed = true,
+            false => stripped.extend_from_slice(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+
+    if removed {
+        std::fs::write(path, stripped)?;
+    }
+    Ok(true)
+}
+
+/// `(fourcc, start, end)` of the chunks of a WebP file, `None` if it is malformed
+fn webp_chunks(bytes: &[u8]) -> Option<Vec<([u8; 4], usize, usize)>> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut chunks = vec![];
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let fourcc = bytes[pos..pos + 4].try_into().unwrap();
+        let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        // fourcc, size, data and the padding to an even size
+        let end = pos + 8 + len + len % 2;
+        if end > bytes.len() {
+            return None;
+        }
+        chunks.push((fourcc, pos, end));
+        pos = end;
+    }
+    Some(chunks)
+}
+
+fn is_lossless_webp(path: &Path) -> anyhow::Result<bool> {
+    let bytes = std::fs::read(path)?;
+    Ok(webp_chunks(&bytes)
+        .unwrap_or_default()
+        .iter()
+        .any(|(fourcc, _, _)| fourcc == b"VP8L"))
+}
+
+/// Drop the `EXIF` and `XMP ` chunks, `false` if the file could not be parsed
+fn strip_webp(path: &Path) -> anyhow::Result<bool> {
+    let bytes = std::fs::read(path)?;
+    let Some(chunks) = webp_chunks(&bytes) else {
+        return Ok(false);
+    };
+
+    // The RIFF size is set once the chunks are known
+    let mut stripped = b"RIFF\0\0\0\0WEBP".to_vec();
+    let mut removed = false;
+    for (fourcc, start, end) in chunks {
+        match &fourcc {
+            b"EXIF" | b"XMP " => removed = true,
+            b"VP8X" if end - start >= 9 => {
+                stripped.extend_from_slice(&bytes[start..end]);
+                let flags = stripped.len() - (end - start) + 8;
+                stripped[flags] &= !VP8X_EXIF_XMP;
+            }
+            _ => stripped.extend_from_slice(&bytes[start..end]),
+        }
+    }
+
+    if removed {
+        let size = (stripped.len() - 8) as u32;
+        stripped[4..8].copy_from_slice(&size.to_le_bytes());
+        std::fs::write(path, stripped)?;
+    }
+    Ok(true)
+}
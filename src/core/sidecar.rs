@@ -0,0 +1,416 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::schemas::{
+    book::{Book, Chapter, Metadata, Page},
+    config::SidecarFormat,
+};
+
+/// A metadata file derived from a book, one per book, chapter or page depending on the format
+/// * `(file name, content)` are written in the book folder, the chapter folder or next to the page
+/// * chapters and pages that are not on disk get no file
+pub trait SidecarWriter {
+    fn book(&self, _book: &Book, _plugin_name: &str) -> Option<(String, String)> {
+        None
+    }
+
+    fn chapter(
+        &self,
+        _book: &Book,
+        _plugin_name: &str,
+        _chapter: &Chapter,
+    ) -> Option<(String, String)> {
+        None
+    }
+
+    fn page(
+        &self,
+        _book: &Book,
+        _plugin_name: &str,
+        _chapter: &Chapter,
+        _page: &Page,
+    ) -> Option<(String, String)> {
+        None
+    }
+}
+
+pub fn writer(format: SidecarFormat) -> Box<dyn SidecarWriter> {
+    match format {
+        SidecarFormat::ComicInfo => Box::new(ComicInfo),
+        SidecarFormat::Opf => Box::new(Opf),
+        SidecarFormat::Hydrus => Box::new(HydrusTags),
+        SidecarFormat::JsonLd => Box::new(JsonLd),
+    }
+}
+
+/// Whether a file of the `chapter` folder was written by one of the enabled `formats` rather than
+/// downloaded, `ComicInfo.xml` and the Hydrus `<page>.txt` of the pages listed in the chapter
+pub fn is_sidecar(formats: &[SidecarFormat], chapter: &Chapter, filename: &str) -> bool {
+    formats.iter().any(|format| match format {
+        SidecarFormat::ComicInfo => filename == "ComicInfo.xml",
+        SidecarFormat::Hydrus => filename
+            .strip_suffix(".txt")
+            .is_some_and(|page| chapter.pages.iter().any(|listed| listed.filename == page)),
+        SidecarFormat::Opf | SidecarFormat::JsonLd => false,
+    })
+}
+
+/// Write the sidecars of `book` in its `folder`, files of a previous run are replaced
+pub fn write_sidecars(
+    formats: &[SidecarFormat],
+    plugin_name: &str,
+    book: &Book,
+    folder: &Path,
+) -> anyhow::Result<()> {
+    let write = |path: &Path, content: &str| {
+        std::fs::write(path, content).with_context(|| format!("Writing {}", path.display()))
+    };
+
+    for format in formats {
+        let writer = writer(*format);
+        if let Some((name, content)) = writer.book(book, plugin_name) {
+            if folder.is_dir() {
+                write(&folder.join(name), &content)?;
+            }
+        }
+
+        for chapter in &book.chapters {
+            let chapter_folder = folder.join(book.get_chapter_folder(plugin_name, chapter));
+            if !chapter_folder.is_dir() {
+                continue;
+            }
+            if let Some((name, content)) = writer.chapter(book, plugin_name, chapter) {
+                write(&chapter_folder.join(name), &content)?;
+            }
+            for page in &chapter.pages {
+                if !chapter_folder.join(&page.filename).is_file() {
+                    continue;
+                }
+                if let Some((name, content)) = writer.page(book, plugin_name, chapter, page) {
+                    write(&chapter_folder.join(name), &content)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ComicInfo 2.0, as read by Komga, Kavita, ComicRack and most comic readers
+pub struct ComicInfo;
+
+impl SidecarWriter for ComicInfo {
+    fn chapter(
+        &self,
+        book: &Book,
+        plugin_name: &str,
+        chapter: &Chapter,
+    ) -> Option<(String, String)> {
+        let summary = match chapter.description.is_empty() {
+            true => &book.description,
+            false => &chapter.description,
+        };
+        let web = match chapter.url.is_empty() {
+            true => &book.url,
+            false => &chapter.url,
+        };
+        let fields = [
+            ("Title", chapter.title.clone()),
+            ("Series", book.title.clone()),
+            ("Number", chapter.number.to_string()),
+            ("Summary", summary.clone()),
+            ("Notes", format!("{plugin_name}: {}", book.source_id)),
+            ("Year", lookup(&book.metadata, "year").join(", ")),
+            ("Writer", join_names(book.authors.iter().map(|a| &a.name))),
+            ("Publisher", lookup(&book.metadata, "publisher").join(", ")),
+            ("Genre", lookup(&book.metadata, "genre").join(", ")),
+            ("Tags", join_names(book.tags.iter().map(|t| &t.name))),
+            ("Web", web.clone()),
+            ("PageCount", chapter.pages.len().to_string()),
+            ("LanguageISO", language(&book.metadata).unwrap_or_default()),
+        ];
+
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" ",
+            "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n"
+        ));
+        for (name, value) in fields {
+            // Year is an integer in the schema
+            if value.is_empty() || (name == "Year" && value.parse::<u32>().is_err()) {
+                continue;
+            }
+            xml.push_str(&format!("  <{name}>{}</{name}>\n", escape_xml(&value)));
+        }
+        if !chapter.pages.is_empty() {
+            xml.push_str("  <Pages>\n");
+            for (p, page) in chapter.pages.iter().enumerate() {
+                let kind = match p {
+                    0 => " Type=\"FrontCover\"",
+                    _ => "",
+                };
+                xml.push_str(&format!(
+                    "    <Page Image=\"{p}\"{kind} ImageFile=\"{}\" />\n",
+                    escape_xml(&page.filename)
+                ));
+            }
+            xml.push_str("  </Pages>\n");
+        }
+        xml.push_str("</ComicInfo>\n");
+
+        Some(("ComicInfo.xml".to_string(), xml))
+    }
+}
+
+/// OPF 2.0 package metadata, as read by Calibre when adding a folder
+pub struct Opf;
+
+impl SidecarWriter for Opf {
+    fn book(&self, book: &Book, plugin_name: &str) -> Option<(String, String)> {
+        let mut metadata = vec![
+            format!("<dc:title>{}</dc:title>", escape_xml(&book.title)),
+            format!(
+                "<dc:identifier id=\"uid\" opf:scheme=\"{}\">{}</dc:identifier>",
+                escape_xml(plugin_name),
+                escape_xml(&book.source_id)
+            ),
+        ];
+        if !book.url.is_empty() {
+            metadata.push(format!(
+                "<dc:identifier opf:scheme=\"URL\">{}</dc:identifier>",
+                escape_xml(&book.url)
+            ));
+        }
+        for author in &book.authors {
+            metadata.push(format!(
+                "<dc:creator opf:role=\"aut\">{}</dc:creator>",
+                escape_xml(&author.name)
+            ));
+        }
+        if !book.description.is_empty() {
+            metadata.push(format!(
+                "<dc:description>{}</dc:description>",
+                escape_xml(&book.description)
+            ));
+        }
+        for tag in &book.tags {
+            metadata.push(format!(
+                "<dc:subject>{}</dc:subject>",
+                escape_xml(&tag.name)
+            ));
+        }
+        for publisher in lookup(&book.metadata, "publisher") {
+            metadata.push(format!(
+                "<dc:publisher>{}</dc:publisher>",
+                escape_xml(&publisher)
+            ));
+        }
+        if let Some(language) = language(&book.metadata) {
+            metadata.push(format!(
+                "<dc:language>{}</dc:language>",
+                escape_xml(&language)
+            ));
+        }
+
+        let mut opf = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<package xmlns=\"http://www.idpf.org/2007/opf\" ",
+            "unique-identifier=\"uid\" version=\"2.0\">\n",
+            "  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" ",
+            "xmlns:opf=\"http://www.idpf.org/2007/opf\">\n"
+        ));
+        for line in metadata {
+            opf.push_str(&format!("    {line}\n"));
+        }
+        opf.push_str("  </metadata>\n</package>\n");
+
+        Some(("metadata.opf".to_string(), opf))
+    }
+}
+
+/// Hydrus sidecar, one `namespace:tag` per line in `<page>.txt`
+pub struct HydrusTags;
+
+impl SidecarWriter for HydrusTags {
+    fn page(
+        &self,
+        book: &Book,
+        plugin_name: &str,
+        chapter: &Chapter,
+        page: &Page,
+    ) -> Option<(String, String)> {
+        let mut tags = vec![
+            format!("title:{}", book.title),
+            format!("chapter:{}", chapter.number),
+            format!("page:{}", page.number),
+            format!("site:{plugin_name}"),
+        ];
+        tags.extend(book.authors.iter().map(|a| format!("creator:{}", a.name)));
+        tags.extend(book.tags.iter().map(|t| t.name.clone()));
+        for meta in &book.metadata {
+            let namespace = meta.label.to_lowercase();
+            tags.extend(
+                values(&meta.content)
+                    .into_iter()
+                    .map(|value| format!("{namespace}:{value}")),
+            );
+        }
+
+        let mut content = String::new();
+        let mut seen = std::collections::HashSet::new();
+        for tag in tags {
+            // Hydrus tags are single lines, lowercased on import
+            let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+            let is_blank = tag.is_empty() || tag.ends_with(':');
+            if !is_blank && seen.insert(tag.to_lowercase()) {
+                content.push_str(&tag);
+                content.push('\n');
+            }
+        }
+
+        Some((format!("{}.txt", page.filename), content))
+    }
+}
+
+/// schema.org `Book` with its chapters as parts
+pub struct JsonLd;
+
+impl SidecarWriter for JsonLd {
+    fn book(&self, book: &Book, plugin_name: &str) -> Option<(String, String)> {
+        let mut document = json!({
+            "@context": "https://schema.org",
+            "@type": "Book",
+            "name": book.title,
+            "identifier": {
+                "@type": "PropertyValue",
+                "propertyID": plugin_name,
+                "value": book.source_id,
+            },
+        });
+        let fields = document.as_object_mut().unwrap();
+        if !book.url.is_empty() {
+            fields.insert("url".to_string(), json!(book.url));
+        }
+        if !book.description.is_empty() {
+            fields.insert("description".to_string(), json!(book.description));
+        }
+        if !book.title_aliases.is_empty() {
+            let aliases = book.title_aliases.iter().map(|a| &a.title);
+            fields.insert(
+                "alternateName".to_string(),
+                json!(aliases.collect::<Vec<_>>()),
+            );
+        }
+        if !book.authors.is_empty() {
+            let authors = book.authors.iter().map(|author| {
+                let mut person = json!({ "@type": "Person", "name": author.name });
+                if !author.description.is_empty() {
+                    person["description"] = json!(author.description);
+                }
+                person
+            });
+            fields.insert("author".to_string(), json!(authors.collect::<Vec<_>>()));
+        }
+        if !book.tags.is_empty() {
+            let keywords = book.tags.iter().map(|t| &t.name);
+            fields.insert("keywords".to_string(), json!(keywords.collect::<Vec<_>>()));
+        }
+        if let Some(language) = language(&book.metadata) {
+            fields.insert("inLanguage".to_string(), json!(language));
+        }
+        if !book.metadata.is_empty() {
+            let properties = book.metadata.iter().map(|meta| {
+                json!({ "@type": "PropertyValue", "name": meta.label, "value": meta.content })
+            });
+            fields.insert(
+                "additionalProperty".to_string(),
+                json!(properties.collect::<Vec<_>>()),
+            );
+        }
+        if !book.chapters.is_empty() {
+            let chapters = book.chapters.iter().map(|chapter| {
+                let mut part = json!({
+                    "@type": "Chapter",
+                    "name": chapter.title,
+                    "position": chapter.number,
+                });
+                if !chapter.url.is_empty() {
+                    part["url"] = json!(chapter.url);
+                }
+                if !chapter.pages.is_empty() {
+                    part["numberOfPages"] = json!(chapter.pages.len());
+                }
+                part
+            });
+            fields.insert("hasPart".to_string(), json!(chapters.collect::<Vec<_>>()));
+        }
+
+        let content = serde_json::to_string_pretty(&document).unwrap();
+        Some(("metadata.jsonld".to_string(), content))
+    }
+}
+
+/// Values of the metadata with this label (case insensitive)
+fn lookup(metadata: &[Metadata], label: &str) -> Vec<String> {
+    metadata
+        .iter()
+        .filter(|meta| meta.label.eq_ignore_ascii_case(label))
+        .flat_map(|meta| values(&meta.content))
+        .collect()
+}
+
+/// Scalar values of a metadata content, arrays are flattened and objects left out
+fn values(content: &serde_json::Value) -> Vec<String> {
+    let items = match content {
+        serde_json::Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    items
+        .into_iter()
+        .filter_map(|value| match value {
+            serde_json::Value::String(s) => Some(s.trim().to_string()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            serde_json::Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        })
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// First `language` metadata, only if it looks like a language code (`en`, `ja`, `pt-BR`)
+fn language(metadata: &[Metadata]) -> Option<String> {
+    lookup(metadata, "language")
+        .into_iter()
+        .chain(lookup(metadata, "languages"))
+        .find(|value| {
+            let code = value.split('-').next().unwrap_or_default();
+            (2..=3).contains(&code.len()) && code.chars().all(|c| c.is_ascii_alphabetic())
+        })
+}
+
+fn join_names<'a>(names: impl Iterator<Item = &'a String>) -> String {
+    names
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| name.trim())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Text and attribute values, characters that XML 1.0 cannot hold are dropped
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::Context;
+
+use crate::schemas::book::{Book, CacheFile, Chapter};
+
+/// How a chapter of a freshly fetched book compares to the saved one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterState {
+    New,
+    /// Same chapter with a different url or different pages
+    Changed,
+    Unchanged,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChapterDiff {
+    pub state: ChapterState,
+    /// Index of the matching saved chapter
+    pub previous: Option<usize>,
+}
+
+/// Chapters of a book compared to the last saved metadata
+/// * chapters are matched by url first, then by number
+/// * pages are compared by filename, urls often carry expiring tokens \
+///   the extension is left out since downloaded pages can be renamed or converted
+/// * lazy chapters have no pages yet, they are only compared by url and number
+#[derive(Debug, Clone, Default)]
+pub struct BookDiff {
+    /// One entry per chapter of the current book, in the same order
+    pub chapters: Vec<ChapterDiff>,
+    /// Saved chapters that are no longer listed
+    pub removed: Vec<usize>,
+}
+
+impl BookDiff {
+    pub fn new(previous: &[Chapter], current: &[Chapter]) -> Self {
+        let mut claimed = HashSet::new();
+        let mut chapters = vec![];
+
+        for chapter in current {
+            let by_url = previous.iter().enumerate().position(|(p, saved)| {
+                !claimed.contains(&p) && !chapter.url.is_empty() && saved.url == chapter.url
+            });
+            let diff = match by_url {
+                Some(p) => ChapterDiff {
+                    state: match same_pages(&previous[p], chapter) {
+                        true => ChapterState::Unchanged,
+                        false => ChapterState::Changed,
+                    },
+                    previous: Some(p),
+                },
+                None => {
+                    let by_number = previous.iter().enumerate().position(|(p, saved)| {
+                        !claimed.contains(&p) && saved.number == chapter.number
+                    });
+                    ChapterDiff {
+                        state: match by_number {
+                            Some(_) => ChapterState::Changed,
+                            None => ChapterState::New,
+                        },
+                        previous: by_number,
+                    }
+                }
+            };
+            if let Some(p) = diff.previous {
+                claimed.insert(p);
+            }
+            chapters.push(diff);
+        }
+
+        let removed = (0..previous.len())
+            .filter(|p| !claimed.contains(p))
+            .collect();
+
+        Self { chapters, removed }
+    }
+
+    pub fn count(&self, state: ChapterState) -> usize {
+        self.chapters
+            .iter()
+            .filter(|diff| diff.state == state)
+            .count()
+    }
+
+    pub fn is_up_to_date(&self) -> bool {
+        self.chapters
+            .iter()
+            .all(|diff| diff.state == ChapterState::Unchanged)
+    }
+
+    /// Same book with only its new or changed chapters
+    pub fn pending(&self, book: &Book) -> Book {
+        Book {
+            chapters: book
+                .chapters
+                .iter()
+                .zip(&self.chapters)
+                .filter(|(_, diff)| diff.state != ChapterState::Unchanged)
+                .map(|(chapter, _)| chapter.clone())
+                .collect(),
+            ..book.clone()
+        }
+    }
+
+    /// Chapters to record once `saved` were downloaded
+    ///
+    /// Chapters that were neither saved now nor before are left out, saved chapters that are no
+    /// longer listed are kept at the end.
+    pub fn merge(
+        &self,
+        previous: &[Chapter],
+        current: &[Chapter],
+        saved: &[Chapter],
+    ) -> Vec<Chapter> {
+        let mut merged = vec![];
+        for (chapter, diff) in current.iter().zip(&self.chapters) {
+            let just_saved = saved
+                .iter()
+                .find(|saved| saved.url == chapter.url && saved.number == chapter.number);
+            match (just_saved, diff.previous) {
+                (Some(saved), _) => merged.push(saved.clone()),
+                (None, Some(p)) => merged.push(previous[p].clone()),
+                (None, None) => {}
+            }
+        }
+        merged.extend(self.removed.iter().map(|p| previous[*p].clone()));
+        merged
+    }
+}
+
+fn same_pages(previous: &Chapter, current: &Chapter) -> bool {
+    if current.lazy.is_some() {
+        return true;
+    }
+    let filenames = |chapter: &Chapter| {
+        chapter
+            .pages
+            .iter()
+            .map(|page| match page.filename.rsplit_once('.') {
+                Some((stem, _)) => stem.to_string(),
+                None => page.filename.clone(),
+            })
+            .collect::<HashSet<_>>()
+    };
+    filenames(previous) == filenames(current)
+}
+
+/// Last metadata saved for `book`, from the metadata folder or else from the download folder
+pub fn load_saved(book: &Book, term: &str, plugin_name: &str) -> anyhow::Result<Option<CacheFile>> {
+    let candidates = [
+        book.get_metadata_dest_path(term, plugin_name),
+        book.get_metadata_download_path(term, plugin_name),
+    ];
+    for path in candidates {
+        if path.exists() {
+            return read_metadata(&path).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+pub fn read_metadata(path: &Path) -> anyhow::Result<CacheFile> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading metadata {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Deserializing metadata {}", path.display()))
+}
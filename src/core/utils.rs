@@ -1,7 +1,8 @@
 use anyhow::Context;
+use lazy_static::lazy_static;
 use regex::Regex;
 use sha2::{Digest, Sha256};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use url::Url;
 
 #[cfg(windows)]
@@ -9,6 +10,10 @@ const MAX_PATH_COMPONENT_LEN: usize = 256;
 #[cfg(not(windows))]
 const MAX_PATH_COMPONENT_LEN: usize = usize::MAX;
 
+lazy_static! {
+    static ref RESERVED_PATH_CHARS: Regex = Regex::new(r#"[\\/:"*?<>|\x00-\x1f]+"#).unwrap();
+}
+
 pub fn sanitize_string(s: &str) -> String {
     let s = decode_escaped_unicode_characters(s);
     let re = Regex::new(r#"[\\/:"'*?<>.&%=\{\}|~+]+"#).unwrap();
@@ -16,6 +21,12 @@ pub fn sanitize_string(s: &str) -> String {
     parts.join("_")
 }
 
+/// A single folder or file name, separators and characters reserved on windows are replaced \
+/// Unlike `sanitize_string`, dots are kept so that extensions survive
+pub fn sanitize_path_component(s: &str) -> String {
+    RESERVED_PATH_CHARS.replace_all(s, "_").trim().to_string()
+}
+
 /// This handles the following cases:
 /// 1. File names has to be trimmed on `windows`, will break explorer.exe otherwise
 /// 2. File paths are limited to `255` characters on `windows` (non-unicode) \
@@ -69,6 +80,90 @@ pub fn extract_filename(url: &Url) -> Option<String> {
     }
 }
 
+/// Extension of the actual content, from its magic bytes or else from its `Content-Type`
+pub fn detect_extension(bytes: &[u8], content_type: Option<&str>) -> Option<String> {
+    if let Some(kind) = infer::get(bytes) {
+        return Some(kind.extension().to_string());
+    }
+
+    let mime = content_type?.split(';').next()?.trim().to_lowercase();
+    let extension = match mime.as_str() {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/bmp" => "bmp",
+        "image/jxl" => "jxl",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        "video/quicktime" => "mov",
+        "audio/mpeg" => "mp3",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        // application/octet-stream, text/html error pages, ..
+        _ => return None,
+    };
+    Some(extension.to_string())
+}
+
+/// Whether two extensions denote the same format, e.g. `jpeg` and `jpg`
+pub fn same_format(extension: &str, other: &str) -> bool {
+    fn family(ext: &str) -> String {
+        match ext.to_lowercase().as_str() {
+            "jpg" | "jpeg" | "jpe" | "jfif" => "jpg".to_string(),
+            "tif" | "tiff" => "tif".to_string(),
+            "mp4" | "m4v" => "mp4".to_string(),
+            "htm" | "html" => "html".to_string(),
+            other => other.to_string(),
+        }
+    }
+    family(extension) == family(other)
+}
+
+/// `filename` with the `detected` extension, `None` if it already has it
+/// * `1.jpg, webp => 1.webp`
+/// * `view, png => view.png`
+pub fn fix_extension(filename: &str, detected: &str) -> Option<String> {
+    let path = Path::new(filename);
+    match path.extension() {
+        Some(ext) if same_format(&ext.to_string_lossy(), detected) => None,
+        Some(_) => {
+            let stem = path.file_stem()?.to_string_lossy();
+            Some(format!("{stem}.{detected}"))
+        }
+        None => Some(format!("{filename}.{detected}")),
+    }
+}
+
+/// Names of the files of `dir`, listed once per chapter for `find_renamed`
+pub fn list_filenames(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect()
+}
+
+/// File of `filenames` (see `list_filenames`) that `filename` was renamed to by `fix_extension`
+pub fn find_renamed(filenames: &[String], filename: &str) -> Option<String> {
+    let path = Path::new(filename);
+    let stem = match path.extension() {
+        Some(_) => path.file_stem()?.to_string_lossy().to_string(),
+        None => filename.to_string(),
+    };
+    filenames
+        .iter()
+        .find(|name| {
+            *name != filename
+                && Path::new(name)
+                    .file_stem()
+                    .is_some_and(|s| s.to_string_lossy() == stem)
+        })
+        .cloned()
+}
+
 /// A text may contain escaped unicode characters \
 /// This can be annoying when dealing with file names infered from unsanatized \
 /// escaped japanese/chinese/korean text for example, making everything unreadable
@@ -89,12 +184,67 @@ pub fn decode_escaped_unicode_characters(input: &str) -> String {
     parsed.as_str().unwrap().to_string()
 }
 
+/// Terms sharing the same key are expected to be supported by the same plugins
+/// * `https://x.com/a/status/1 => "https://x.com"`
+/// * `nh:177013 => "nh:"`
+pub fn verdict_key(term: &str) -> Option<String> {
+    if let Ok(url) = Url::parse(term) {
+        if let Some(host) = url.host_str() {
+            return Some(format!("{}://{host}", url.scheme()));
+        }
+    }
+
+    let (prefix, rest) = term.split_once(':')?;
+    let is_prefix = !prefix.is_empty()
+        && !rest.is_empty()
+        && prefix
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    is_prefix.then(|| format!("{prefix}:"))
+}
+
 pub fn compute_query_signature(term: &str, plugin_name: &str) -> String {
     let data = format!("{term}{plugin_name}");
     let digest = hex::encode(Sha256::digest(data));
     format!("mx_{digest}")
 }
 
+/// Short digest of the files of `folder` (recursively) ending with one of `extensions`,
+/// `None` if there is none
+pub fn digest_folder(folder: &Path, extensions: &[&str]) -> Option<String> {
+    fn collect(dir: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                collect(&path, extensions, files);
+            } else if path
+                .extension()
+                .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    let mut files = vec![];
+    collect(folder, extensions, &mut files);
+    if files.is_empty() {
+        return None;
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let relative = file.strip_prefix(folder).unwrap_or(&file);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(std::fs::read(&file).ok()?);
+    }
+    let digest = hex::encode(hasher.finalize());
+    Some(digest[..12].to_string())
+}
+
 pub fn batch_a_list_of<T: Clone>(list: &[T], batch_size: usize) -> Vec<Vec<T>> {
     if batch_size == 0 {
         panic!("Batch size cannot be negative or 0");
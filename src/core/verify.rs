@@ -0,0 +1,185 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::{core::utils, schemas::book::CacheFile};
+
+/// Bytes read at each end of a file, enough for the magic bytes and the end markers
+const PROBE_LEN: u64 = 64;
+
+/// Why a file of a book cannot be trusted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    Missing,
+    Empty,
+    /// The end marker of the format is missing
+    Truncated,
+    /// The magic bytes belong to another format
+    Mismatch {
+        detected: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct BrokenFile {
+    /// Index of the chapter in the metadata
+    pub chapter: usize,
+    /// Index of the page in the chapter, `None` for files of chapters without listed pages
+    pub page: Option<usize>,
+    pub path: PathBuf,
+    pub problem: Problem,
+}
+
+/// Compare the pages listed in the metadata of a book against its download folder
+/// * chapters without listed pages (lazy, custom downloaders) have every file of their folder
+///   checked, they cannot be reported as missing
+/// * formats without a known end marker are never reported as truncated
+pub fn verify_book(saved: &CacheFile, plugin_name: &str, folder: &Path) -> Vec<BrokenFile> {
+    let mut broken = vec![];
+    for (c, chapter) in saved.book.chapters.iter().enumerate() {
+        let chapter_folder = folder.join(saved.book.get_chapter_folder(plugin_name, chapter));
+        if chapter.pages.is_empty() {
+            let mut files = std::fs::read_dir(&chapter_folder)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            files.sort();
+            for path in files {
+                if let Some(problem) = check_file(&path) {
+                    broken.push(BrokenFile {
+                        chapter: c,
+                        page: None,
+                        path,
+                        problem,
+                    });
+                }
+            }
+            continue;
+        }
+
+        for (p, page) in chapter.pages.iter().enumerate() {
+            let path = chapter_folder.join(&page.filename);
+            if let Some(problem) = check_file(&path) {
+                broken.push(BrokenFile {
+                    chapter: c,
+                    page: Some(p),
+                    path,
+                    problem,
+                });
+            }
+        }
+    }
+    broken
+}
+
+/// `None` when the file looks complete
+pub fn check_file(path: &Path) -> Option<Problem> {
+    let Ok(mut file) = File::open(path) else {
+        return Some(Problem::Missing);
+    };
+    let len = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    if len == 0 {
+        return Some(Problem::Empty);
+    }
+
+    let mut head = vec![0; len.min(PROBE_LEN) as usize];
+    let mut tail = vec![0; len.min(PROBE_LEN) as usize];
+    let probed = file.read_exact(&mut head).and_then(|_| {
+        file.seek(SeekFrom::End(-(tail.len() as i64)))?;
+        file.read_exact(&mut tail)
+    });
+    if probed.is_err() {
+        return Some(Problem::Truncated);
+    }
+
+    let kind = infer::get(&head)?;
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    if let Some(extension) = extension {
+        if !utils::same_format(&extension, kind.extension()) {
+            return Some(Problem::Mismatch {
+                detected: kind.extension().to_string(),
+            });
+        }
+    }
+
+    match is_complete(kind.extension(), &head, &tail, len) {
+        false => Some(Problem::Truncated),
+        true => None,
+    }
+}
+
+/// Whether the end marker of the format is present
+fn is_complete(format: &str, head: &[u8], tail: &[u8], len: u64) -> bool {
+    match format {
+        // Some encoders pad the file after the end of image
+        "jpg" => tail.windows(2).any(|w| w == [0xFF, 0xD9]),
+        "png" => tail.windows(4).any(|w| w == b"IEND"),
+        "gif" => tail.last() == Some(&0x3B),
+        // RIFF size, without the 8 bytes of the header
+        "webp" => match head.get(4..8) {
+            Some(size) => {
+                let size = u32::from_le_bytes(size.try_into().unwrap()) as u64;
+                len >= size + 8
+            }
+            None => false,
+        },
+        _ => true,
+    }
+}
+
+/// Book folders left under the temp folder by interrupted downloads, with their number of files
+/// * book folders are `depth` folders below `temp`, see `PathTemplate::depth`
+pub fn temp_leftovers(temp: &Path, depth: usize) -> Vec<(PathBuf, usize)> {
+    fn walk(dir: &Path, depth: usize, leftovers: &mut Vec<(PathBuf, usize)>) {
+        if depth == 0 {
+            leftovers.push((dir.to_path_buf(), count_files(dir)));
+            return;
+        }
+        let mut subfolders = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>();
+        subfolders.sort();
+        for subfolder in subfolders {
+            walk(&subfolder, depth - 1, leftovers);
+        }
+    }
+
+    let mut leftovers = vec![];
+    walk(temp, depth, &mut leftovers);
+    leftovers
+}
+
+fn count_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| match entry.path() {
+            path if path.is_dir() => count_files(&path),
+            _ => 1,
+        })
+        .sum()
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Empty => write!(f, "empty"),
+            Problem::Truncated => write!(f, "truncated"),
+            Problem::Mismatch { detected } => write!(f, "{detected} content"),
+        }
+    }
+}
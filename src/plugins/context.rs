@@ -0,0 +1,115 @@
+use std::collections::BTreeSet;
+
+use indexmap::IndexSet;
+use url::Url;
+
+use crate::plugins::process::TempFile;
+use crate::{
+    core::http::FetchContext,
+    schemas::{config::AuthKind, cookies::NetscapeCookie},
+};
+
+/// How a tool is given the headers of the context
+#[derive(Debug, Clone, Copy)]
+pub enum HeaderArgs {
+    /// `--option headers=<json>`, gallery-dl
+    ExtractorOption,
+    /// `--add-header name:value` for each header, yt-dlp
+    AddHeader,
+}
+
+/// Options of an external tool (gallery-dl, yt-dlp) derived from a `FetchContext`
+/// * cookies are written to a temporary cookies.txt, removed once dropped
+/// * `--user-agent`, `--username`, `--password` and `--proxy` are shared by both tools
+#[derive(Debug, Default)]
+pub struct ContextArgs {
+    pub args: Vec<String>,
+    _cookies_file: Option<TempFile>,
+}
+
+impl ContextArgs {
+    /// Cookies without a domain are bound to the host of every term
+    pub fn new<T: AsRef<str>>(
+        context: &FetchContext,
+        proxy: Option<&str>,
+        terms: &[T],
+        header_args: HeaderArgs,
+    ) -> anyhow::Result<Self> {
+        let mut args = vec![];
+        let mut cookies_file = None;
+
+        if !context.cookies.is_empty() {
+            let hosts = terms
+                .iter()
+                .map(|term| {
+                    Url::parse(term.as_ref())
+                        .ok()
+                        .and_then(|url| url.host_str().map(str::to_string))
+                        .unwrap_or_default()
+                })
+                .collect::<BTreeSet<_>>();
+            // cookies with a domain would be repeated for every host otherwise
+            let lines = hosts
+                .iter()
+                .flat_map(|host| {
+                    NetscapeCookie::to_netscape_text(&context.cookies, host)
+                        .lines()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<IndexSet<_>>();
+            let content = lines.into_iter().collect::<Vec<_>>().join("\n") + "\n";
+            let file = TempFile::write("mx-cookies", &content)?;
+            args.push("--cookies".to_string());
+            args.push(file.path().to_string_lossy().to_string());
+            cookies_file = Some(file);
+        }
+
+        if let Some(user_agent) = &context.user_agent {
+            args.push("--user-agent".to_string());
+            args.push(user_agent.clone());
+        }
+
+        let mut headers = context.headers.clone();
+        match &context.auth {
+            Some(AuthKind::Basic { user, password }) => {
+                args.push("--username".to_string());
+                args.push(user.clone());
+                if let Some(password) = password {
+                    args.push("--password".to_string());
+                    args.push(password.clone());
+                }
+            }
+            Some(bearer @ AuthKind::Bearer { .. }) => {
+                headers.insert("Authorization".to_string(), bearer.stringify());
+            }
+            None => {}
+        }
+
+        match header_args {
+            HeaderArgs::ExtractorOption if !headers.is_empty() => {
+                args.push("--option".to_string());
+                args.push(format!("headers={}", serde_json::to_string(&headers)?));
+            }
+            HeaderArgs::ExtractorOption => {}
+            HeaderArgs::AddHeader => {
+                let mut headers = headers.into_iter().collect::<Vec<_>>();
+                headers.sort();
+                for (name, value) in headers {
+                    args.push("--add-header".to_string());
+                    args.push(format!("{name}:{value}"));
+                }
+            }
+        }
+
+        if let Some(proxy) = proxy {
+            args.push("--proxy".to_string());
+            args.push(proxy.to_string());
+        }
+
+        Ok(Self {
+            args,
+            _cookies_file: cookies_file,
+        })
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use scraper::Html;
+use site::{BookHeader, ListSpec, SiteSpec};
+use url::Url;
+
+use crate::{
+    core::{
+        http::{ContextProvider, FetchContext},
+        utils,
+    },
+    schemas::book::{Author, Book, Chapter, LazyChapter, Page, SearchOption, Tag},
+    GLOBAL_CONFIG,
+};
+
+use super::{MXPlugin, PluginReporter};
+pub mod site;
+
+/// File describing the site within the folder of the plugin
+pub const SITE_FILE: &str = "site.yaml";
+
+/// Plugin defined by a `site.yaml` of selectors, see `SiteSpec`
+#[derive(Debug, Clone)]
+pub struct DeclarativePlugin {
+    pub name: String,
+    pub workdir: PathBuf,
+    /// Loaded by `init`
+    pub spec: Option<Box<SiteSpec>>,
+    /// Digest of the site description, computed by `init`
+    pub version: Option<String>,
+}
+
+impl DeclarativePlugin {
+    pub fn new(name: String, workdir: PathBuf) -> Self {
+        Self {
+            name,
+            workdir,
+            spec: None,
+            version: None,
+        }
+    }
+
+    /// Html of a page of the site, `delay.fetch` is waited after every request
+    async fn fetch(&self, url: Url) -> anyhow::Result<String> {
+        let (client, context, delay) = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            (
+                config.get_http_client(),
+                config.gen_fetch_context_for(Some(&self.name)),
+                config.delay.clone(),
+            )
+        };
+        let bytes = client
+            .get_async(url.clone(), ContextProvider::Concrete(context))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}: fetching {url}: {e}", self.name));
+        tokio::time::sleep(Duration::from_millis(delay.fetch as u64)).await;
+        Ok(String::from_utf8_lossy(&bytes?).to_string())
+    }
+}
+
+impl MXPlugin for DeclarativePlugin {
+    async fn init(&mut self) -> anyhow::Result<()> {
+        self.spec = Some(Box::new(SiteSpec::load(&self.workdir.join(SITE_FILE))?));
+        self.version = utils::digest_folder(&self.workdir, &["yaml", "yml"]);
+        Ok(())
+    }
+
+    async fn destroy(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_book(&self, term: String, reporter: PluginReporter) -> anyhow::Result<Book> {
+        let context = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            config.gen_fetch_context_for(Some(&self.name))
+        };
+        let spec = self
+            .spec
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("{}: plugin not initialized", self.name))?;
+        crawl_book(spec, &term, Some(context), &reporter, |url| self.fetch(url)).await
+    }
+
+    async fn expand_chapter(&self, chapter: &Chapter) -> anyhow::Result<Vec<Page>> {
+        let context = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            config.gen_fetch_context_for(Some(&self.name))
+        };
+        let spec = self
+            .spec
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("{}: plugin not initialized", self.name))?;
+        crawl_chapter(spec, chapter, Some(context), |url| self.fetch(url)).await
+    }
+
+    async fn search(&self, _term: String, _option: SearchOption) -> anyhow::Result<Vec<Book>> {
+        unimplemented!()
+    }
+
+    async fn is_supported(&self, term: String) -> anyhow::Result<bool> {
+        Ok(self.spec.as_ref().is_some_and(|spec| spec.matches(&term)))
+    }
+
+    /// Digest of the site description when it was loaded
+    async fn version(&self) -> Option<String> {
+        self.version.clone()
+    }
+
+    async fn download_url(&self, _dest: &Path, _url: &Url) -> Option<anyhow::Result<()>> {
+        None
+    }
+}
+
+/// Evaluate `spec` against the page of `term`, following chapter links and `next` pages
+/// * `fetch` returns the HTML of an url
+/// * pages inherit `context`, with the chapter as referer
+pub async fn crawl_book<F, Fut>(
+    spec: &SiteSpec,
+    term: &str,
+    context: Option<FetchContext>,
+    reporter: &PluginReporter,
+    fetch: F,
+) -> anyhow::Result<Book>
+where
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    let base = Url::parse(term)?;
+    let html = fetch(base.clone()).await?;
+    let header = spec.extract_header(&Html::parse_document(&html))?;
+
+    let mut chapters = vec![];
+    if let Some(list) = &spec.chapters {
+        let links = crawl_list(list, base.clone(), Some(html), &fetch).await?;
+        let total = links.len() as u64;
+        for (i, (url, title)) in links.into_iter().enumerate() {
+            let number = i as u32 + 1;
+            if list.lazy {
+                chapters.push(Chapter {
+                    lazy: Some(LazyChapter::Plugin),
+                    ..new_chapter(spec, number, url, title, vec![], &context)
+                });
+                continue;
+            }
+            reporter.progress(i as u64, Some(total), title.clone());
+            let pages = crawl_list(&spec.pages, url.clone(), None, &fetch).await?;
+            chapters.push(new_chapter(spec, number, url, title, pages, &context));
+        }
+        reporter.progress(total, Some(total), None);
+    } else {
+        let pages = crawl_list(&spec.pages, base.clone(), Some(html), &fetch).await?;
+        chapters.push(new_chapter(
+            spec,
+            1,
+            base,
+            header.title.clone(),
+            pages,
+            &context,
+        ));
+    }
+
+    Ok(assemble_book(spec, term, header, chapters))
+}
+
+/// Pages of a chapter left out by `crawl_book`, see `ListSpec::lazy`
+pub async fn crawl_chapter<F, Fut>(
+    spec: &SiteSpec,
+    chapter: &Chapter,
+    context: Option<FetchContext>,
+    fetch: F,
+) -> anyhow::Result<Vec<Page>>
+where
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    let url = Url::parse(&chapter.url)?;
+    let pages = crawl_list(&spec.pages, url.clone(), None, &fetch).await?;
+    let title = Some(chapter.title.clone());
+    Ok(new_chapter(spec, chapter.number, url, title, pages, &context).pages)
+}
+
+/// Items of a list along with the ones of its next pages, in reading order
+async fn crawl_list<F, Fut>(
+    list: &ListSpec,
+    url: Url,
+    html: Option<String>,
+    fetch: &F,
+) -> anyhow::Result<Vec<(Url, Option<String>)>>
+where
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    let mut items = vec![];
+    let mut seen = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut next = Some((url, html));
+    while let Some((url, html)) = next.take() {
+        if visited.len() >= list.max_pages.max(1) || !visited.insert(url.clone()) {
+            break;
+        }
+        let html = match html {
+            Some(html) => html,
+            None => fetch(url.clone()).await?,
+        };
+        let listing = list.extract(&Html::parse_document(&html), &url)?;
+        for item in listing.items {
+            if seen.insert(item.0.clone()) {
+                items.push(item);
+            }
+        }
+        next = listing.next.map(|url| (url, None));
+    }
+    if list.reverse {
+        items.reverse();
+    }
+    Ok(items)
+}
+
+fn new_chapter(
+    spec: &SiteSpec,
+    number: u32,
+    url: Url,
+    title: Option<String>,
+    pages: Vec<(Url, Option<String>)>,
+    context: &Option<FetchContext>,
+) -> Chapter {
+    let fetch_context = context.clone().map(|mut context| {
+        context
+            .headers
+            .insert("Referer".to_string(), url.to_string());
+        context
+    });
+    let pages = pages
+        .into_iter()
+        .enumerate()
+        .map(|(p, (page_url, title))| Page {
+            title: title.unwrap_or_default(),
+            intermediate_link_hint: spec.pages.intermediate.clone(),
+            fetch_context: fetch_context.clone(),
+            // Names of the site are often meaningless or shared by every page
+            ..Page::from_url(p as u32 + 1, &page_url)
+        })
+        .collect();
+
+    Chapter {
+        title: title.unwrap_or(format!("Chapter {number}")),
+        url: url.to_string(),
+        number,
+        pages,
+        ..Default::default()
+    }
+}
+
+fn assemble_book(spec: &SiteSpec, term: &str, header: BookHeader, chapters: Vec<Chapter>) -> Book {
+    let BookHeader {
+        title,
+        id,
+        description,
+        authors,
+        tags,
+    } = header;
+    let source_id = id
+        .or_else(|| spec.id_of(term))
+        .unwrap_or_else(|| utils::compute_query_signature(term, "declarative"));
+
+    Book {
+        title: title.unwrap_or_else(|| utils::resume_text(term, Some(20)).trim().to_string()),
+        source_id,
+        description: description.unwrap_or_default(),
+        url: term.to_string(),
+        authors: authors
+            .into_iter()
+            .map(|name| Author {
+                name,
+                ..Default::default()
+            })
+            .collect(),
+        tags: tags
+            .into_iter()
+            .map(|name| Tag {
+                name,
+                metadata: vec![],
+            })
+            .collect(),
+        chapters,
+        ..Default::default()
+    }
+}
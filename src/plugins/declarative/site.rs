@@ -0,0 +1,372 @@
+use std::{path::Path, sync::OnceLock};
+
+use anyhow::Context;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Deserializer, Serialize};
+use url::Url;
+
+use crate::schemas::{book::ParseLinkHint, liftvec_on_singleton};
+
+/// A site described in `site.yaml`, evaluated without any code
+/// ```yaml
+/// urls: ^https://example\.com/manga/(?<id>[^/]+)
+/// book:
+///   title: h1.title
+///   authors: .authors a
+/// chapters:
+///   selector: ul.chapters li a
+///   reverse: true
+///   # lazy: true
+/// pages:
+///   selector: .reader img
+///   url: { attribute: data-src }
+///   next: { selector: a.next, attribute: href }
+///   # pages linking to an image host
+///   # selector: .gallery a
+///   # intermediate: { selector: img#main, attribute: src }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SiteSpec {
+    /// Regexes a term has to match, the `id` group is used as the id of the book
+    #[serde(deserialize_with = "liftvec_on_singleton")]
+    pub urls: Vec<String>,
+    /// `urls` compiled once, when the site is validated
+    #[serde(skip)]
+    pub(crate) patterns: OnceLock<Vec<Regex>>,
+    #[serde(default)]
+    pub book: BookSpec,
+    /// Chapter links found on the page of the book, pages are read from that page otherwise
+    #[serde(default)]
+    pub chapters: Option<ListSpec>,
+    /// Images found on the page of a chapter
+    pub pages: ListSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct BookSpec {
+    pub title: Option<Extract>,
+    pub id: Option<Extract>,
+    pub description: Option<Extract>,
+    /// Every match is an author
+    pub authors: Option<Extract>,
+    /// Every match is a tag
+    pub tags: Option<Extract>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListSpec {
+    /// One element per item
+    pub selector: String,
+    #[serde(skip)]
+    pub(crate) compiled: OnceLock<Selector>,
+    /// Url of the item relative to its element, `href` or `src` of the element by default
+    #[serde(default)]
+    pub url: Option<Extract>,
+    /// Title of the item relative to its element
+    #[serde(default)]
+    pub title: Option<Extract>,
+    /// Url of the next page of the list, relative to the whole document
+    #[serde(default)]
+    pub next: Option<Extract>,
+    /// Maximum number of pages followed through `next`
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+    /// Items are listed newest first
+    #[serde(default)]
+    pub reverse: bool,
+    /// Hops from the url of a page to its image, resolved at download time
+    #[serde(default, deserialize_with = "liftvec_on_singleton")]
+    pub intermediate: Vec<ParseLinkHint>,
+    /// Chapters only, pages of a chapter are crawled when it is downloaded
+    #[serde(default)]
+    pub lazy: bool,
+}
+
+fn default_max_pages() -> usize {
+    50
+}
+
+/// Value of the first matching element (or of every one for lists)
+/// * `selector` is relative to the current element, the element itself if omitted
+/// * `attribute` is read if set, the trimmed text otherwise
+/// * `regex` keeps the `value` group, the first group or the whole match, non-matching values are dropped
+/// * `replace` turns `regex` into a substitution, e.g. `regex: -thumb`, `replace: ""`
+///
+/// A plain string is a selector.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Extract {
+    pub selector: Option<String>,
+    pub attribute: Option<String>,
+    pub regex: Option<String>,
+    pub replace: Option<String>,
+    /// `selector` and `regex` compiled once, when the site is validated
+    #[serde(skip)]
+    pub(crate) compiled: OnceLock<(Option<Selector>, Option<Regex>)>,
+}
+
+impl<'de> Deserialize<'de> for Extract {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Rule {
+            #[serde(default)]
+            selector: Option<String>,
+            #[serde(default)]
+            attribute: Option<String>,
+            #[serde(default)]
+            regex: Option<String>,
+            #[serde(default)]
+            replace: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Selector(String),
+            Rule(Rule),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Selector(selector) => Extract {
+                selector: Some(selector),
+                ..Default::default()
+            },
+            Repr::Rule(rule) => Extract {
+                selector: rule.selector,
+                attribute: rule.attribute,
+                regex: rule.regex,
+                replace: rule.replace,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Book level values found on the page of a term
+#[derive(Debug, Default)]
+pub struct BookHeader {
+    pub title: Option<String>,
+    pub id: Option<String>,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// Items of one page of a list
+#[derive(Debug, Default)]
+pub struct Listing {
+    /// (url, title)
+    pub items: Vec<(Url, Option<String>)>,
+    pub next: Option<Url>,
+}
+
+impl SiteSpec {
+    pub fn load(file: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Reading site {}", file.display()))?;
+        let spec: Self = serde_yaml::from_str(&content)
+            .with_context(|| format!("Parsing site {}", file.display()))?;
+        spec.validate()
+            .with_context(|| format!("Validating site {}", file.display()))?;
+        Ok(spec)
+    }
+
+    /// Fail early on bad regexes or selectors, they are compiled once along the way
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.urls.is_empty() {
+            anyhow::bail!("At least one url pattern is required");
+        }
+        self.patterns()?;
+        let BookSpec {
+            title,
+            id,
+            description,
+            authors,
+            tags,
+        } = &self.book;
+        for extract in [title, id, description, authors, tags]
+            .into_iter()
+            .flatten()
+        {
+            extract.validate()?;
+        }
+        for list in self.chapters.iter().chain([&self.pages]) {
+            list.selector()?;
+            for extract in [&list.url, &list.title, &list.next].into_iter().flatten() {
+                extract.validate()?;
+            }
+            for hint in &list.intermediate {
+                hint.compile()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn patterns(&self) -> anyhow::Result<&[Regex]> {
+        if let Some(patterns) = self.patterns.get() {
+            return Ok(patterns);
+        }
+        let patterns = self
+            .urls
+            .iter()
+            .map(|pattern| Regex::new(pattern).with_context(|| format!("Compiling {pattern:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(self.patterns.get_or_init(|| patterns))
+    }
+
+    pub fn matches(&self, term: &str) -> bool {
+        self.patterns()
+            .unwrap_or_default()
+            .iter()
+            .any(|re| re.is_match(term))
+    }
+
+    /// `id` group of the first matching url pattern
+    pub fn id_of(&self, term: &str) -> Option<String> {
+        self.patterns().unwrap_or_default().iter().find_map(|re| {
+            re.captures(term)?
+                .name("id")
+                .map(|m| m.as_str().to_string())
+        })
+    }
+
+    pub fn extract_header(&self, document: &Html) -> anyhow::Result<BookHeader> {
+        let root = document.root_element();
+        let first = |extract: &Option<Extract>| -> anyhow::Result<Option<String>> {
+            match extract {
+                Some(extract) => Ok(extract.values(root)?.into_iter().next()),
+                None => Ok(None),
+            }
+        };
+        let all = |extract: &Option<Extract>| -> anyhow::Result<Vec<String>> {
+            match extract {
+                Some(extract) => {
+                    let mut values = extract.values(root)?;
+                    let mut seen = std::collections::HashSet::new();
+                    values.retain(|value| seen.insert(value.clone()));
+                    Ok(values)
+                }
+                None => Ok(vec![]),
+            }
+        };
+        Ok(BookHeader {
+            title: first(&self.book.title)?,
+            id: first(&self.book.id)?,
+            description: first(&self.book.description)?,
+            authors: all(&self.book.authors)?,
+            tags: all(&self.book.tags)?,
+        })
+    }
+}
+
+impl ListSpec {
+    fn selector(&self) -> anyhow::Result<&Selector> {
+        if let Some(selector) = self.compiled.get() {
+            return Ok(selector);
+        }
+        let selector = parse_selector(&self.selector)?;
+        Ok(self.compiled.get_or_init(|| selector))
+    }
+
+    pub fn extract(&self, document: &Html, base: &Url) -> anyhow::Result<Listing> {
+        let mut items = vec![];
+        for element in document.select(self.selector()?) {
+            let url = match &self.url {
+                Some(extract) => extract.values(element)?.into_iter().next(),
+                None => ["href", "src"]
+                    .iter()
+                    .find_map(|attr| element.attr(attr).map(str::to_string)),
+            };
+            let Some(url) = url else {
+                continue;
+            };
+            let url = resolve(base, &url)?;
+            let title = match &self.title {
+                Some(extract) => extract.values(element)?.into_iter().next(),
+                None => None,
+            };
+            items.push((url, title));
+        }
+
+        let next = match &self.next {
+            Some(extract) => extract
+                .values(document.root_element())?
+                .into_iter()
+                .next()
+                .map(|url| resolve(base, &url))
+                .transpose()?,
+            None => None,
+        };
+
+        Ok(Listing { items, next })
+    }
+}
+
+impl Extract {
+    fn validate(&self) -> anyhow::Result<()> {
+        self.compile().map(|_| ())
+    }
+
+    fn compile(&self) -> anyhow::Result<&(Option<Selector>, Option<Regex>)> {
+        if let Some(compiled) = self.compiled.get() {
+            return Ok(compiled);
+        }
+        let selector = self.selector.as_deref().map(parse_selector).transpose()?;
+        let regex = self.regex.as_deref().map(Regex::new).transpose()?;
+        Ok(self.compiled.get_or_init(|| (selector, regex)))
+    }
+
+    /// Values of every matching element, in document order
+    pub fn values(&self, scope: ElementRef) -> anyhow::Result<Vec<String>> {
+        let (selector, regex) = self.compile()?;
+        let elements = match selector {
+            Some(selector) => scope.select(selector).collect(),
+            None => vec![scope],
+        };
+
+        let mut values = vec![];
+        for element in elements {
+            let value = match &self.attribute {
+                Some(attribute) => match element.attr(attribute) {
+                    Some(value) => value.trim().to_string(),
+                    None => continue,
+                },
+                None => element.text().collect::<Vec<_>>().join(" "),
+            };
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            let value = match (regex, &self.replace) {
+                (Some(re), Some(replace)) => re.replace_all(&value, replace.as_str()).to_string(),
+                (Some(re), None) => {
+                    let Some(caps) = re.captures(&value) else {
+                        continue;
+                    };
+                    caps.name("value")
+                        .or_else(|| caps.get(1))
+                        .or_else(|| caps.get(0))
+                        .map(|m| m.as_str().to_string())
+                        .unwrap_or_default()
+                }
+                (None, _) => value,
+            };
+            if !value.is_empty() {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn parse_selector(selector: &str) -> anyhow::Result<Selector> {
+    Selector::parse(selector).map_err(|e| anyhow::anyhow!("Bad selector {selector:?}: {e}"))
+}
+
+/// Absolute or relative to `base`
+fn resolve(base: &Url, url: &str) -> anyhow::Result<Url> {
+    base.join(url)
+        .with_context(|| format!("Resolving {url:?} against {base}"))
+}
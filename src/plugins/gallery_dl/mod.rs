@@ -1,27 +1,85 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
-    process::Command,
     str::FromStr,
+    time::Duration,
 };
 
 use crate::{
     core::utils::{self, extract_filename},
-    schemas::book::{Author, Book, Chapter, Metadata, Page, SearchOption, Tag, TitleAlias},
+    schemas::book::{
+        Author, Book, BookChunk, Chapter, LazyChapter, Metadata, Page, SearchOption, Tag,
+        TitleAlias,
+    },
     GLOBAL_CONFIG,
 };
 use anyhow::Ok;
 use indexmap::IndexSet;
+use profile::{ChapterGrouper, MappingProfile, MappingProfiles, QUEUE_MESSAGE};
 use schema::{FirstGalleryEntry, GalleryItem, GalleryPage, UrlGalleryEntry};
 use serde::{Deserialize, Serialize};
+use tokio::process::Command;
 use url::Url;
 
-use super::MXPlugin;
+use super::{
+    context::{ContextArgs, HeaderArgs},
+    process::{binary_version, parse_option, parse_timeout, with_timeout, SubProcess, TempFile},
+    BookSender, DownloadEvent, DownloadEventSender, MXPlugin, PluginReporter,
+};
+pub mod profile;
 pub mod schema;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExtraConfig {
     pub bin: PathBuf,
     pub argv: Vec<String>,
+    /// Limit for fetching the metadata of a term
+    pub timeout: Option<Duration>,
+    /// Limit for `--extractor-info` during auto-detection
+    pub probe_timeout: Option<Duration>,
+    /// Limit for downloading a single url or a whole chapter
+    pub download_timeout: Option<Duration>,
+    /// Pass cookies, user agent, auth and proxy from mx-scraper's config
+    pub forward_context: bool,
+    /// YAML file of mapping profiles, merged with the builtin ones
+    pub profiles: Option<PathBuf>,
+    /// Terms fetched by a single gallery-dl process, 1 disables batching
+    pub batch_size: usize,
+}
+
+impl Default for ExtraConfig {
+    fn default() -> Self {
+        Self {
+            bin: PathBuf::from("gallery-dl"),
+            argv: vec![],
+            timeout: Some(Duration::from_secs(300)),
+            probe_timeout: Some(Duration::from_secs(30)),
+            download_timeout: None,
+            forward_context: true,
+            profiles: None,
+            batch_size: 50,
+        }
+    }
+}
+
+impl ExtraConfig {
+    /// Timeouts are given in seconds, 0 disables them
+    pub fn from_map(cfg: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            bin: cfg.get("bin").map(PathBuf::from).unwrap_or(default.bin),
+            argv: cfg
+                .get("argv")
+                .and_then(|argv| shlex::split(argv))
+                .unwrap_or_default(),
+            timeout: parse_timeout(cfg, "timeout", default.timeout)?,
+            probe_timeout: parse_timeout(cfg, "probe_timeout", default.probe_timeout)?,
+            download_timeout: parse_timeout(cfg, "download_timeout", default.download_timeout)?,
+            forward_context: parse_option(cfg, "forward_context", default.forward_context)?,
+            profiles: cfg.get("profiles").map(PathBuf::from),
+            batch_size: parse_option(cfg, "batch_size", default.batch_size)?,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -32,44 +90,47 @@ pub struct GalleryDLPlugin {
 
 impl GalleryDLPlugin {
     pub fn new() -> Self {
-        // TODO:
-        // Rewrite everything to be generator based
-        // - metadata can still be oneshoted at init
-        // Rewrite downloading to be localized within the plugin and not global
-        // - Still have a generic 'global' downloader for most usecases
-        // - Allow custom downloading process to be taken over by the plugin
-        //   - the plugin should emit instead progress events
-        //   - progress can be unlimited
         let name = String::from("gallery-dl");
         let extra_config = GLOBAL_CONFIG
             .read()
             .unwrap()
             .request
             .get(&name)
-            .and_then(|req| {
-                let bin = req
-                    .extra_config
-                    .clone()
-                    .and_then(|cfg| cfg.get("bin").cloned());
-                let argv = req
-                    .extra_config
-                    .clone()
-                    .and_then(|cfg| cfg.get("argv").cloned());
-
-                Some(ExtraConfig {
-                    bin: bin
-                        .map(PathBuf::from)
-                        .unwrap_or(PathBuf::from("gallery-dl")),
-                    argv: shlex::split(&argv.unwrap_or("".to_owned())).unwrap_or(vec![]),
+            .and_then(|req| req.extra_config.as_ref())
+            .map(|cfg| {
+                ExtraConfig::from_map(cfg).unwrap_or_else(|e| {
+                    tracing::warn!("{name}: {e}, using defaults");
+                    ExtraConfig::default()
                 })
             })
-            .unwrap_or(ExtraConfig {
-                bin: PathBuf::from("gallery-dl"),
-                argv: vec![],
-            });
+            .unwrap_or_default();
 
         Self { extra_config, name }
     }
+
+    fn command(&self) -> Command {
+        Command::new(&self.extra_config.bin)
+    }
+
+    /// Options from the fetch context, `argv` comes after them and can still override them
+    fn context_args<T: AsRef<str>>(&self, terms: &[T]) -> anyhow::Result<ContextArgs> {
+        if !self.extra_config.forward_context {
+            return Ok(ContextArgs::default());
+        }
+        let (context, proxy) = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            (
+                config.gen_fetch_context_for(Some(&self.name)),
+                config.get_proxy(&self.name),
+            )
+        };
+        ContextArgs::new(
+            &context,
+            proxy.as_deref(),
+            terms,
+            HeaderArgs::ExtractorOption,
+        )
+    }
 }
 
 impl MXPlugin for GalleryDLPlugin {
@@ -81,32 +142,197 @@ impl MXPlugin for GalleryDLPlugin {
         Ok(())
     }
 
-    async fn get_book(&self, term: String) -> anyhow::Result<Book> {
-        let mut command = Command::new(&self.extra_config.bin);
-        let command = command.arg(&term).arg("--dump-json");
-        if !self.extra_config.argv.is_empty() {
-            for arg in &self.extra_config.argv {
-                command.arg(arg);
+    async fn get_book(&self, term: String, _reporter: PluginReporter) -> anyhow::Result<Book> {
+        let context = self.context_args(&[&term])?;
+        let mut command = self.command();
+        command
+            .arg(&term)
+            .arg("--dump-json")
+            .args(&context.args)
+            .args(&self.extra_config.argv);
+
+        let mut process = SubProcess::spawn(&self.name, &mut command, None)?;
+        let stdout = with_timeout(self.extra_config.timeout, async {
+            let stdout = process.read_to_end().await?;
+            Ok(stdout)
+        })
+        .await?;
+        let invocation = process.invocation().to_string();
+        process.finish().await?;
+
+        let items: Vec<GalleryItem> = serde_json::from_str(&stdout)
+            .map_err(|e| anyhow::anyhow!("Parse result of '{invocation}': {e}"))?;
+
+        let profiles = MappingProfiles::cached(self.extra_config.profiles.as_deref())?;
+        generate_book(term, items, &profiles)
+    }
+
+    fn can_fetch_many(&self) -> bool {
+        self.extra_config.batch_size > 1
+    }
+
+    /// Run gallery-dl once per `batch_size` terms, urls are given through an input file
+    async fn get_books(
+        &self,
+        terms: Vec<String>,
+        reporter: PluginReporter,
+    ) -> anyhow::Result<Vec<anyhow::Result<Book>>> {
+        let profiles = MappingProfiles::cached(self.extra_config.profiles.as_deref())?;
+        let batch_size = self.extra_config.batch_size.max(1);
+        let total = terms.len() as u64;
+
+        let mut books = vec![];
+        for batch in terms.chunks(batch_size) {
+            reporter.progress(
+                books.len() as u64,
+                Some(total),
+                Some(format!("gallery-dl with {} urls", batch.len())),
+            );
+
+            let input = TempFile::write("mx-gallery-dl-input", &(batch.join("\n") + "\n"))?;
+            let context = self.context_args(batch)?;
+            let mut command = self.command();
+            command
+                .arg("--dump-json")
+                .arg("--input-file")
+                .arg(input.path())
+                .args(&context.args)
+                .args(&self.extra_config.argv);
+
+            let mut process = SubProcess::spawn(&self.name, &mut command, None)?;
+            // The items of an url are printed once it is extracted, so the timeout applies per url
+            let mut lines = vec![];
+            while let Some(line) =
+                with_timeout(self.extra_config.timeout, process.next_line()).await?
+            {
+                lines.push(line);
             }
+            let stdout = lines.join("\n");
+            let invocation = process.invocation().to_string();
+            // Non-zero as soon as one of the urls failed, which is reported per term
+            let (status, stderr) = process.exit().await?;
+
+            let results = split_batch_output(&stdout, batch, &profiles).map_err(|e| {
+                anyhow::anyhow!("'{invocation}' exited with {status}: {e}\nstderr: {stderr}")
+            })?;
+            books.extend(results);
         }
-        let output = command.output()?;
-
-        if !output.status.success() {
-            let lines = &[
-                format!("stdout: {}", String::from_utf8_lossy(&output.stdout)),
-                format!("stderr: {}", String::from_utf8_lossy(&output.stderr)),
-            ];
-            anyhow::bail!("{}", lines.join("\n").to_string());
-        }
+        reporter.progress(total, Some(total), None);
 
-        let items: Vec<GalleryItem> = serde_json::from_slice(&output.stdout).map_err(|e| {
-            anyhow::anyhow!(
-                "Parse result of '{}': {e}",
-                print_command_invocation(&command)
-            )
-        })?;
+        Ok(books)
+    }
+
+    async fn stream_book(
+        &self,
+        term: String,
+        _reporter: PluginReporter,
+        sender: BookSender,
+    ) -> anyhow::Result<()> {
+        let context = self.context_args(&[&term])?;
+        let mut command = self.command();
+        command
+            .arg(&term)
+            .arg("--dump-json")
+            .args(&context.args)
+            .args(&self.extra_config.argv);
+
+        let profiles = MappingProfiles::cached(self.extra_config.profiles.as_deref())?;
+        let mut process = SubProcess::spawn(&self.name, &mut command, None)?;
+        with_timeout(self.extra_config.timeout, async {
+            let mut scanner = ItemScanner::default();
+            let mut mapper = ChunkMapper::new(term.clone(), profiles);
+            while let Some(line) = process.next_line().await? {
+                for raw in scanner.feed_line(&line) {
+                    let item: GalleryItem = serde_json::from_str(&raw).map_err(|e| {
+                        anyhow::anyhow!("Parse item of '{}': {e}", process.invocation())
+                    })?;
+                    for chunk in mapper.map(item)? {
+                        sender
+                            .send(chunk)
+                            .await
+                            .map_err(|_| anyhow::anyhow!("Book stream closed by the receiver"))?;
+                    }
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+        process.finish().await
+    }
 
-        generate_book(term, items)
+    fn can_download_chapter(&self) -> bool {
+        true
+    }
+
+    /// Let gallery-dl download everything, postprocessors from its own config included
+    async fn download_chapter(
+        &self,
+        chapter: &Chapter,
+        dest: &Path,
+        events: DownloadEventSender,
+    ) -> anyhow::Result<()> {
+        let url = profile::source_url(&chapter.url);
+        let context = self.context_args(&[url])?;
+        let mut command = self.command();
+        command
+            .arg("--directory")
+            .arg(dest)
+            // Same names as the filenames of the pages, see `generate_page`
+            .arg("--filename")
+            .arg("{filename}.{extension}")
+            .arg(url)
+            .args(&context.args)
+            .args(&self.extra_config.argv);
+
+        let mut process = SubProcess::spawn(&self.name, &mut command, Some(events.clone()))?;
+        with_timeout(self.extra_config.download_timeout, async {
+            // One path per line, already downloaded files are prefixed with '# '
+            while let Some(line) = process.next_line().await? {
+                let path = line.strip_prefix("# ").unwrap_or(&line);
+                let filename = Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or(path.to_string());
+                let _ = events.send(DownloadEvent::Progress { filename });
+            }
+            Ok(())
+        })
+        .await?;
+
+        process.finish().await
+    }
+
+    /// Pages of a queued chapter, extracted on their own
+    async fn expand_chapter(&self, chapter: &Chapter) -> anyhow::Result<Vec<Page>> {
+        let context = self.context_args(&[&chapter.url])?;
+        let mut command = self.command();
+        command
+            .arg(&chapter.url)
+            .arg("--dump-json")
+            .args(&context.args)
+            .args(&self.extra_config.argv);
+
+        let mut process = SubProcess::spawn(&self.name, &mut command, None)?;
+        let stdout = with_timeout(self.extra_config.timeout, process.read_to_end()).await?;
+        let invocation = process.invocation().to_string();
+        process.finish().await?;
+
+        let items: Vec<GalleryItem> = serde_json::from_str(&stdout)
+            .map_err(|e| anyhow::anyhow!("Parse result of '{invocation}': {e}"))?;
+        let mut pages = vec![];
+        for (p, item) in items.iter().enumerate() {
+            if let GalleryItem::ThreeElementTuple(UrlGalleryEntry(kind, url, meta)) = item {
+                if *kind != QUEUE_MESSAGE {
+                    let (page, _) = generate_page(p, url, meta)?;
+                    pages.push(Page {
+                        number: pages.len() as u32 + 1,
+                        ..page
+                    });
+                }
+            }
+        }
+        Ok(pages)
     }
 
     async fn search(&self, _term: String, _option: SearchOption) -> anyhow::Result<Vec<Book>> {
@@ -114,41 +340,62 @@ impl MXPlugin for GalleryDLPlugin {
     }
 
     async fn is_supported(&self, term: String) -> anyhow::Result<bool> {
-        let mut command = Command::new(&self.extra_config.bin);
-        let output = command.arg(&term).arg("--extractor-info").output()?;
-        Ok(output.status.success())
+        let mut command = self.command();
+        command.arg(&term).arg("--extractor-info");
+
+        let process = SubProcess::spawn(&self.name, &mut command, None)?;
+        with_timeout(self.extra_config.probe_timeout, process.succeeded()).await
     }
 
-    fn download_url(&self, dest: &Path, url: &Url) -> Option<anyhow::Result<()>> {
-        let body = || -> anyhow::Result<()> {
-            let mut command = Command::new(&self.extra_config.bin);
+    async fn version(&self) -> Option<String> {
+        binary_version(
+            &self.name,
+            &self.extra_config.bin,
+            self.extra_config.probe_timeout,
+        )
+        .await
+    }
+
+    async fn download_url(&self, dest: &Path, url: &Url) -> Option<anyhow::Result<()>> {
+        let body = async {
             let parent = dest.parent().unwrap();
             let filename = dest.file_name().unwrap();
             let tmp_dest = parent.join(format!("{}_temp", filename.to_string_lossy()));
 
-            let output = command
+            let context = self.context_args(&[url.as_str()])?;
+            let mut command = self.command();
+            command
                 .arg("--directory")
                 .arg(&tmp_dest)
                 .arg(url.to_string())
-                .output()?;
+                .args(&context.args);
+
+            let process = SubProcess::spawn(&self.name, &mut command, None)?;
+            let succeeded =
+                with_timeout(self.extra_config.download_timeout, process.succeeded()).await?;
 
-            if output.status.success() {
+            if succeeded {
                 // dl expects dest to be a directory, which is not the case
                 // actual/path/page.jpg_temp/custom_name_by_dl.jpg
                 // => actual/path/page.jpg
                 if let Some(entry) = (std::fs::read_dir(&tmp_dest)?).next() {
-                    std::fs::rename(&tmp_dest.join(entry?.file_name()), &dest)?;
+                    std::fs::rename(tmp_dest.join(entry?.file_name()), dest)?;
                     std::fs::remove_dir_all(&tmp_dest)?;
                 }
             }
 
             Ok(())
         };
-        Some(body())
+        Some(body.await)
     }
 }
 
-pub fn generate_book(term: String, items: Vec<GalleryItem>) -> anyhow::Result<Book> {
+/// Build a book from the whole output, the mapping profile of the extractor is applied if any
+pub fn generate_book(
+    term: String,
+    items: Vec<GalleryItem>,
+    profiles: &MappingProfiles,
+) -> anyhow::Result<Book> {
     let placeholder_title = utils::resume_text(&term, Some(20)).to_string();
     let mut title = placeholder_title.trim().to_owned();
     let mut title_aliases = IndexSet::new();
@@ -157,7 +404,8 @@ pub fn generate_book(term: String, items: Vec<GalleryItem>) -> anyhow::Result<Bo
     let mut tags: IndexSet<String> = IndexSet::new();
     let mut authors: IndexSet<String> = IndexSet::new();
 
-    let mut pages = vec![];
+    let mut gallery_meta = None;
+    let mut entries = vec![];
     for (p, item) in items.iter().enumerate() {
         match item {
             GalleryItem::TwoElementTuple(FirstGalleryEntry(_len, gl)) => {
@@ -167,47 +415,237 @@ pub fn generate_book(term: String, items: Vec<GalleryItem>) -> anyhow::Result<Bo
                 title_aliases.extend(gl.get_title_aliases());
                 tags.extend(gl.get_tags());
                 authors.extend(gl.get_authors()?);
+                if gallery_meta.is_none() {
+                    gallery_meta = Some(serde_json::to_value(gl)?);
+                }
             }
-            GalleryItem::ThreeElementTuple(UrlGalleryEntry(_len, url, meta)) => {
-                let gpage = serde_json::from_value::<GalleryPage>(meta.clone())?;
-
-                let filename = gpage.get_filename().unwrap_or_else(|| {
-                    extract_filename(&Url::from_str(&url).unwrap()).unwrap_or_else(|| p.to_string())
-                });
-
-                let page_meta = meta.as_object().map(|o| {
-                    if !o.is_empty() {
-                        vec![Metadata {
-                            label: filename.clone(),
-                            content: meta.clone(),
-                        }]
-                    } else {
-                        vec![]
+            GalleryItem::ThreeElementTuple(UrlGalleryEntry(kind, url, meta)) => {
+                let (page, page_tags) = generate_page(p, url, meta)?;
+                tags.extend(page_tags);
+                entries.push((*kind, page, meta));
+            }
+        }
+    }
+
+    let book_meta = gallery_meta
+        .as_ref()
+        .or_else(|| entries.first().map(|(_, _, meta)| *meta));
+    let profile = book_meta.and_then(|meta| profiles.select(meta));
+
+    let chapters = profile.and_then(|profile| profile.group_chapters(&term, &entries));
+    let pages = match chapters {
+        Some(_) => vec![],
+        None => entries.into_iter().map(|(_, page, _)| page).collect(),
+    };
+
+    let mut book = assemble_book(
+        &term,
+        title,
+        title_aliases,
+        gallery_id,
+        authors,
+        tags,
+        pages,
+    );
+    if let (Some(profile), Some(meta)) = (profile, book_meta) {
+        profile.apply_to_book(&mut book, meta);
+    }
+    if let Some(chapters) = chapters {
+        book.chapters = chapters;
+    }
+
+    Ok(book)
+}
+
+/// gallery-dl prints one array per url, failed extractions end with `["ErrorName", "message"]`
+/// * urls without extractor print nothing, which is an error as results can no longer be matched
+pub fn split_batch_output(
+    stdout: &str,
+    terms: &[String],
+    profiles: &MappingProfiles,
+) -> anyhow::Result<Vec<anyhow::Result<Book>>> {
+    let outputs = serde_json::Deserializer::from_str(stdout)
+        .into_iter::<Vec<serde_json::Value>>()
+        .collect::<Result<Vec<_>, _>>()?;
+    if outputs.len() != terms.len() {
+        anyhow::bail!("{} results for {} urls", outputs.len(), terms.len());
+    }
+
+    let books = terms
+        .iter()
+        .zip(outputs)
+        .map(|(term, values)| {
+            let mut items = vec![];
+            for value in values {
+                if let Some([name, message]) = value.as_array().map(Vec::as_slice) {
+                    if let (Some(name), Some(message)) = (name.as_str(), message.as_str()) {
+                        anyhow::bail!("{name}: {message}");
                     }
-                });
-
-                tags.extend(gpage.get_explicit_tags());
-
-                pages.push(Page {
-                    title: gpage.title,
-                    url: url.clone(),
-                    number: p as u32,
-                    filename,
-                    metadata: page_meta.unwrap_or_else(|| vec![]),
-                    ..Default::default()
-                });
+                }
+                items.push(serde_json::from_value::<GalleryItem>(value)?);
             }
+            generate_book(term.clone(), items, profiles)
+        })
+        .collect();
+
+    Ok(books)
+}
+
+/// Stateful `generate_chunk`, applies the mapping profile of the extractor as items come
+#[derive(Debug)]
+pub struct ChunkMapper {
+    term: String,
+    profiles: MappingProfiles,
+    profile: Option<MappingProfile>,
+    grouper: ChapterGrouper,
+    chapters: u32,
+    p: usize,
+}
+
+impl ChunkMapper {
+    pub fn new(term: String, profiles: MappingProfiles) -> Self {
+        Self {
+            term,
+            profiles,
+            profile: None,
+            grouper: ChapterGrouper::default(),
+            chapters: 0,
+            p: 0,
+        }
+    }
+
+    pub fn map(&mut self, item: GalleryItem) -> anyhow::Result<Vec<BookChunk>> {
+        let p = self.p;
+        self.p += 1;
+
+        match &item {
+            GalleryItem::TwoElementTuple(FirstGalleryEntry(_len, gl)) => {
+                let meta = serde_json::to_value(gl)?;
+                self.select_profile(&meta);
+                let mut chunk = generate_chunk(&self.term, p, item)?;
+                if let (Some(profile), BookChunk::Book(book)) = (&self.profile, &mut chunk) {
+                    profile.apply_to_book(book, &meta);
+                }
+                Ok(vec![chunk])
+            }
+            GalleryItem::ThreeElementTuple(UrlGalleryEntry(kind, url, meta)) => {
+                self.select_profile(meta);
+                let Some(profile) = self.profile.as_ref().filter(|p| p.chapter.is_some()) else {
+                    return Ok(vec![generate_chunk(&self.term, p, item)?]);
+                };
+
+                if *kind == QUEUE_MESSAGE {
+                    self.chapters += 1;
+                    self.grouper.reset();
+                    let chapter = Chapter {
+                        lazy: Some(LazyChapter::Plugin),
+                        ..profile.new_chapter(self.chapters, url, meta)
+                    };
+                    return Ok(vec![BookChunk::Chapter(chapter)]);
+                }
+
+                let mut chunks = vec![];
+                if let Some(chapter) =
+                    self.grouper
+                        .next(profile, &self.term, self.chapters + 1, meta)
+                {
+                    self.chapters += 1;
+                    chunks.push(BookChunk::Chapter(chapter));
+                }
+                let (page, _) = generate_page(p, url, meta)?;
+                // numbered within its chapter by the assembler
+                chunks.push(BookChunk::Page(Page { number: 0, ..page }));
+                Ok(chunks)
+            }
+        }
+    }
+
+    fn select_profile(&mut self, meta: &serde_json::Value) {
+        if self.profile.is_none() {
+            self.profile = self.profiles.select(meta).cloned();
+        }
+    }
+}
+
+/// Convert a single item as soon as gallery-dl outputs it
+pub fn generate_chunk(term: &str, p: usize, item: GalleryItem) -> anyhow::Result<BookChunk> {
+    match item {
+        GalleryItem::TwoElementTuple(FirstGalleryEntry(_len, gl)) => {
+            let title = extract_title_if_url(gl.get_title_or_default(term.to_string()));
+            let book = assemble_book(
+                term,
+                title,
+                gl.get_title_aliases(),
+                gl.get_id_or_default(term.to_string()),
+                gl.get_authors()?,
+                gl.get_tags(),
+                vec![],
+            );
+            Ok(BookChunk::Book(Book {
+                chapters: vec![],
+                ..book
+            }))
+        }
+        GalleryItem::ThreeElementTuple(UrlGalleryEntry(_len, url, meta)) => {
+            let (page, _) = generate_page(p, &url, &meta)?;
+            Ok(BookChunk::Page(page))
         }
     }
+}
+
+fn generate_page(
+    p: usize,
+    url: &str,
+    meta: &serde_json::Value,
+) -> anyhow::Result<(Page, IndexSet<String>)> {
+    let gpage = serde_json::from_value::<GalleryPage>(meta.clone())?;
+
+    let filename = gpage.get_filename().unwrap_or_else(|| {
+        extract_filename(&Url::from_str(url).unwrap()).unwrap_or_else(|| p.to_string())
+    });
+
+    let page_meta = meta.as_object().map(|o| {
+        if !o.is_empty() {
+            vec![Metadata {
+                label: filename.clone(),
+                content: meta.clone(),
+            }]
+        } else {
+            vec![]
+        }
+    });
 
-    gallery_id = utils::set_if_empty(
+    let tags = gpage.get_explicit_tags();
+
+    let page = Page {
+        title: gpage.title,
+        url: url.to_string(),
+        number: p as u32,
+        filename,
+        metadata: page_meta.unwrap_or_default(),
+        ..Default::default()
+    };
+
+    Ok((page, tags))
+}
+
+fn assemble_book(
+    term: &str,
+    title: String,
+    title_aliases: IndexSet<String>,
+    gallery_id: String,
+    authors: IndexSet<String>,
+    tags: IndexSet<String>,
+    pages: Vec<Page>,
+) -> Book {
+    let gallery_id = utils::set_if_empty(
         gallery_id,
-        utils::compute_query_signature(&term, "gallery-dl"),
+        utils::compute_query_signature(term, "gallery-dl"),
     );
 
-    let book = Book {
+    Book {
         title: title.clone(),
-        url: term.clone(),
+        url: term.to_string(),
         title_aliases: title_aliases
             .into_iter()
             .map(|title| TitleAlias {
@@ -219,7 +657,7 @@ pub fn generate_book(term: String, items: Vec<GalleryItem>) -> anyhow::Result<Bo
         description: "".to_string(),
         chapters: vec![Chapter {
             title,
-            url: term.clone(),
+            url: term.to_string(),
             number: 1,
             pages,
             description: "".to_string(),
@@ -252,20 +690,78 @@ pub fn generate_book(term: String, items: Vec<GalleryItem>) -> anyhow::Result<Bo
             })
             .collect(),
         metadata: vec![],
-    };
+    }
+}
 
-    Ok(book)
+/// Split gallery-dl's output into items as soon as they are complete,
+/// works with both the pretty printed `--dump-json` array and one item per line
+#[derive(Debug, Default)]
+pub struct ItemScanner {
+    array_mode: Option<bool>,
+    buffer: String,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
 }
 
-fn print_command_invocation(command: &Command) -> String {
-    let bin = command.get_program().to_string_lossy();
+impl ItemScanner {
+    pub fn feed_line(&mut self, line: &str) -> Vec<String> {
+        if self.array_mode.is_none() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed == "[]" {
+                return vec![];
+            }
+            self.array_mode = Some(trimmed == "[");
+            if trimmed == "[" {
+                return vec![];
+            }
+        }
 
-    let args: Vec<String> = command
-        .get_args()
-        .map(|arg| arg.to_string_lossy().to_string())
-        .collect();
+        if self.array_mode == Some(false) {
+            let trimmed = line.trim().trim_end_matches(',');
+            return match trimmed {
+                "" | "[" | "]" => vec![],
+                item => vec![item.to_string()],
+            };
+        }
+
+        let mut items = vec![];
+        for ch in line.chars().chain(std::iter::once('\n')) {
+            if self.in_string {
+                match (self.escaped, ch) {
+                    (true, _) => self.escaped = false,
+                    (false, '\\') => self.escaped = true,
+                    (false, '"') => self.in_string = false,
+                    _ => {}
+                }
+                self.buffer.push(ch);
+                continue;
+            }
+
+            match ch {
+                '[' | '{' => {
+                    self.depth += 1;
+                    self.buffer.push(ch);
+                }
+                ']' | '}' if self.depth > 0 => {
+                    self.depth -= 1;
+                    self.buffer.push(ch);
+                    if self.depth == 0 {
+                        items.push(self.buffer.trim().to_string());
+                        self.buffer.clear();
+                    }
+                }
+                '"' if self.depth > 0 => {
+                    self.in_string = true;
+                    self.buffer.push(ch);
+                }
+                _ if self.depth > 0 => self.buffer.push(ch),
+                _ => {} // separators and closing bracket of the outer array
+            }
+        }
 
-    format!("{} {}", bin, args.join(" "))
+        items
+    }
 }
 
 fn extract_title_if_url(maybe_url: String) -> String {
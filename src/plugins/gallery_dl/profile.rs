@@ -0,0 +1,363 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::schemas::{
+    book::{Book, Chapter, LazyChapter, Metadata, Page, Tag},
+    liftvec_on_singleton,
+};
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{([^{}]+)\}").unwrap();
+    /// `profiles file => builtin profiles merged with it`
+    static ref LOADED: Mutex<HashMap<Option<PathBuf>, MappingProfiles>> =
+        Mutex::new(HashMap::new());
+}
+
+/// gallery-dl message id of an url that has to be extracted on its own (e.g. a chapter of a manga)
+pub const QUEUE_MESSAGE: i32 = 6;
+
+/// Separates the url of the term from the key of a grouped chapter, see `chapter_url`
+const CHAPTER_KEY: &str = "#chapter:";
+
+/// How the fields of an extractor are mapped into a `Book`
+/// * fields are dot separated paths into the metadata of an item, e.g. `manga.description`
+/// * every key is optional, items are left as is if no profile is found
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MappingProfile {
+    /// Candidate fields of the book title, first non-empty one wins (e.g. the series of a chapter)
+    #[serde(default, deserialize_with = "liftvec_on_singleton")]
+    pub title: Vec<String>,
+    /// Candidate fields, first non-empty one wins
+    #[serde(default, deserialize_with = "liftvec_on_singleton")]
+    pub description: Vec<String>,
+    /// label => field, stored as `Metadata` of the book and chapters
+    #[serde(default)]
+    pub dates: BTreeMap<String, String>,
+    #[serde(default)]
+    pub chapter: Option<ChapterMapping>,
+    /// namespace => field
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Fields stored as is as `Metadata` of the book
+    #[serde(default, deserialize_with = "liftvec_on_singleton")]
+    pub metadata: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChapterMapping {
+    /// Consecutive items sharing these values belong to the same chapter
+    #[serde(deserialize_with = "liftvec_on_singleton")]
+    pub group_by: Vec<String>,
+    /// e.g. `"Vol. {volume} Ch. {chapter} {title}"`, values of `group_by` joined otherwise
+    /// * words before an empty placeholder are left out with it, no `Vol.` without a volume
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// Profiles by gallery-dl category, `category:subcategory` takes precedence
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MappingProfiles(pub BTreeMap<String, MappingProfile>);
+
+impl MappingProfiles {
+    pub fn builtin() -> Self {
+        serde_yaml::from_str(include_str!("profiles.yaml")).unwrap()
+    }
+
+    /// Builtin profiles overridden by the ones from `file`
+    pub fn load(file: Option<&Path>) -> anyhow::Result<Self> {
+        let mut profiles = Self::builtin();
+        if let Some(file) = file {
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("Reading gallery-dl profiles {}", file.display()))?;
+            let custom: Self = serde_yaml::from_str(&content)
+                .with_context(|| format!("Parsing gallery-dl profiles {}", file.display()))?;
+            profiles.0.extend(custom.0);
+        }
+        Ok(profiles)
+    }
+
+    /// `load`, the files are only read once
+    pub fn cached(file: Option<&Path>) -> anyhow::Result<Self> {
+        let key = file.map(Path::to_path_buf);
+        if let Some(profiles) = LOADED.lock().unwrap().get(&key) {
+            return Ok(profiles.clone());
+        }
+        let profiles = Self::load(file)?;
+        LOADED.lock().unwrap().insert(key, profiles.clone());
+        Ok(profiles)
+    }
+
+    pub fn select(&self, meta: &Value) -> Option<&MappingProfile> {
+        let category = as_strings(lookup(meta, "category")?).into_iter().next()?;
+        let subcategory = lookup(meta, "subcategory")
+            .and_then(|v| as_strings(v).into_iter().next())
+            .unwrap_or_default();
+        self.0
+            .get(&format!("{category}:{subcategory}"))
+            .or_else(|| self.0.get(&category))
+    }
+}
+
+impl MappingProfile {
+    /// Description, dates, typed tags and metadata from the metadata of the gallery
+    pub fn apply_to_book(&self, book: &mut Book, meta: &Value) {
+        if let Some(title) = first_non_empty(&self.title, meta) {
+            book.title = title;
+        }
+
+        if book.description.is_empty() {
+            if let Some(description) = first_non_empty(&self.description, meta) {
+                book.description = description;
+            }
+        }
+
+        book.metadata.extend(self.dates_of(meta));
+
+        for (namespace, field) in &self.tags {
+            let Some(value) = lookup(meta, field) else {
+                continue;
+            };
+            for name in as_strings(value) {
+                let namespace = Metadata {
+                    label: "namespace".to_string(),
+                    content: Value::String(namespace.clone()),
+                };
+                match book
+                    .tags
+                    .iter_mut()
+                    .find(|tag| tag.name == name && tag.metadata.is_empty())
+                {
+                    Some(tag) => tag.metadata.push(namespace),
+                    None => book.tags.push(Tag {
+                        name,
+                        metadata: vec![namespace],
+                    }),
+                }
+            }
+        }
+
+        for field in &self.metadata {
+            if let Some(value) = lookup(meta, field) {
+                book.metadata.push(Metadata {
+                    label: field.clone(),
+                    content: value.clone(),
+                });
+            }
+        }
+    }
+
+    /// Key of the chapter an item belongs to, `None` if chapters are not grouped
+    pub fn chapter_key(&self, meta: &Value) -> Option<String> {
+        let mapping = self.chapter.as_ref()?;
+        let values = mapping
+            .group_by
+            .iter()
+            .map(|field| {
+                lookup(meta, field)
+                    .map(|v| as_strings(v).join(","))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        Some(values.join("/"))
+    }
+
+    /// New chapter for the item holding `meta`
+    pub fn new_chapter(&self, number: u32, url: &str, meta: &Value) -> Chapter {
+        let mut title = String::new();
+        if let Some(mapping) = &self.chapter {
+            title = match &mapping.title {
+                Some(template) => render_title(template, meta),
+                None => self.chapter_key(meta).unwrap_or_default(),
+            };
+            title = title
+                .trim_matches(|c: char| c == '/' || c.is_whitespace())
+                .to_string();
+        }
+        if title.is_empty() {
+            title = format!("Chapter {number}");
+        }
+
+        let mut metadata = self.dates_of(meta);
+        if let Some(mapping) = &self.chapter {
+            for field in &mapping.group_by {
+                if let Some(value) = lookup(meta, field) {
+                    metadata.push(Metadata {
+                        label: field.clone(),
+                        content: value.clone(),
+                    });
+                }
+            }
+        }
+
+        Chapter {
+            title,
+            url: url.to_string(),
+            number,
+            metadata,
+            ..Default::default()
+        }
+    }
+
+    /// Split pages into chapters, queued urls become lazy chapters
+    pub fn group_chapters(
+        &self,
+        term: &str,
+        entries: &[(i32, Page, &Value)],
+    ) -> Option<Vec<Chapter>> {
+        self.chapter.as_ref()?;
+        let mut grouper = ChapterGrouper::default();
+        let mut chapters: Vec<Chapter> = vec![];
+        for (kind, page, meta) in entries {
+            if *kind == QUEUE_MESSAGE {
+                let number = chapters.len() as u32 + 1;
+                chapters.push(Chapter {
+                    lazy: Some(LazyChapter::Plugin),
+                    ..self.new_chapter(number, &page.url, meta)
+                });
+                grouper.reset();
+                continue;
+            }
+            if let Some(chapter) = grouper.next(self, term, chapters.len() as u32 + 1, meta) {
+                chapters.push(chapter);
+            }
+            if let Some(chapter) = chapters.last_mut() {
+                let number = chapter.pages.len() as u32 + 1;
+                chapter.pages.push(Page {
+                    number,
+                    ..page.clone()
+                });
+            }
+        }
+        Some(chapters)
+    }
+
+    fn dates_of(&self, meta: &Value) -> Vec<Metadata> {
+        self.dates
+            .iter()
+            .filter_map(|(label, field)| {
+                lookup(meta, field).map(|value| Metadata {
+                    label: label.clone(),
+                    content: value.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Tell when consecutive items start a new chapter
+#[derive(Debug, Default)]
+pub struct ChapterGrouper {
+    current: Option<String>,
+}
+
+impl ChapterGrouper {
+    pub fn next(
+        &mut self,
+        profile: &MappingProfile,
+        term: &str,
+        number: u32,
+        meta: &Value,
+    ) -> Option<Chapter> {
+        let key = profile.chapter_key(meta)?;
+        if self.current.as_ref() == Some(&key) {
+            return None;
+        }
+        let url = chapter_url(term, &key);
+        self.current = Some(key);
+        Some(profile.new_chapter(number, &url, meta))
+    }
+
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Grouped chapters all come from the url of the term, their key tells them apart when
+/// chapters are matched by url (watch, sync), e.g. `https://mangadex.org/title/5e4f#chapter:1/2/`
+pub fn chapter_url(term: &str, key: &str) -> String {
+    format!("{term}{CHAPTER_KEY}{key}")
+}
+
+/// Url to give gallery-dl for a chapter, without the key added by `chapter_url`
+pub fn source_url(url: &str) -> &str {
+    url.split_once(CHAPTER_KEY).map_or(url, |(url, _)| url)
+}
+
+/// Placeholders of `template` resolved from `meta`, empty if none of them has a value
+/// * a word is left out along with the literal words before it when its placeholders are empty
+fn render_title(template: &str, meta: &Value) -> String {
+    let mut words = vec![];
+    // literal words, kept only if the next placeholder has a value
+    let mut pending = vec![];
+    let mut resolved_any = false;
+    for word in template.split_whitespace() {
+        if !PLACEHOLDER.is_match(word) {
+            pending.push(word.to_string());
+            continue;
+        }
+        let mut resolved = false;
+        let word = PLACEHOLDER.replace_all(word, |caps: &regex::Captures| {
+            let value = lookup(meta, &caps[1])
+                .map(|v| as_strings(v).join(", "))
+                .unwrap_or_default();
+            resolved |= !value.is_empty();
+            value
+        });
+        if resolved {
+            words.append(&mut pending);
+            words.push(word.to_string());
+            resolved_any = true;
+        } else {
+            pending.clear();
+        }
+    }
+    if !resolved_any {
+        return String::new();
+    }
+    words.extend(pending);
+    words.join(" ")
+}
+
+/// First candidate field with a non blank value, trimmed
+fn first_non_empty(fields: &[String], meta: &Value) -> Option<String> {
+    fields
+        .iter()
+        .filter_map(|field| lookup(meta, field))
+        .flat_map(as_strings)
+        .find(|s| !s.trim().is_empty())
+        .map(|s| s.trim().to_string())
+}
+
+/// `a.b.c` within nested objects, null values are ignored
+pub fn lookup<'a>(meta: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut value = meta;
+    for key in path.split('.') {
+        value = match value {
+            Value::Object(m) => m.get(key)?,
+            Value::Array(a) => a.get(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    (!value.is_null()).then_some(value)
+}
+
+/// Flatten a value into strings, objects are represented by their `name`
+fn as_strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => vec![s.clone()],
+        Value::Number(n) => vec![n.to_string()],
+        Value::Bool(b) => vec![b.to_string()],
+        Value::Array(a) => a.iter().flat_map(as_strings).collect(),
+        Value::Object(m) => m.get("name").map(as_strings).unwrap_or_default(),
+        _ => vec![],
+    }
+}
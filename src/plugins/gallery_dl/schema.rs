@@ -7,9 +7,13 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Gallery {
-    #[serde(default, alias = "manga")]
+    #[serde(default)]
     title: Option<String>,
 
+    /// Used when `title` is missing, profiles can prefer it (e.g. series of a chapter)
+    #[serde(default)]
+    manga: Option<String>,
+
     #[serde(default, deserialize_with = "liftvec_on_singleton")]
     title_aliases: Vec<String>,
 
@@ -103,7 +107,8 @@ impl Gallery {
     }
 
     pub fn get_title_or_default(&self, default: String) -> String {
-        self.title.clone().unwrap_or_else(|| {
+        let title = self.title.clone().or(self.manga.clone());
+        title.unwrap_or_else(|| {
             let title_like = &["search_tags"];
             for title in title_like {
                 let res: anyhow::Result<Option<String>> = self.inspect_unprocessed_field(title);
@@ -1,38 +1,156 @@
-use std::{path::Path, time::Duration};
+use std::{collections::HashMap, path::Path, sync::Mutex, time::Duration};
 
 use anyhow::Context;
 use async_graphql::SimpleObject;
+use chrono::Local;
+use declarative::DeclarativePlugin;
 use gallery_dl::GalleryDLPlugin;
+use indexmap::IndexMap;
+use indicatif::ProgressBar;
 use python::PythonPlugin;
+use tracing::Level;
 use url::Url;
+use yt_dlp::YtDlpPlugin;
 
 use crate::{
-    schemas::book::{Book, SearchOption},
+    core::utils,
+    schemas::{
+        book::{Book, BookAssembler, BookChunk, Chapter, Page, SearchOption},
+        cache::{CacheEntry, Freshness},
+        config::Route,
+    },
     GLOBAL_CONFIG,
 };
 
+pub mod context;
+pub mod declarative;
 pub mod gallery_dl;
+pub mod process;
 pub mod python;
+pub mod yt_dlp;
+
+/// Capacity of the channel between a streaming plugin and the downloader
+pub const STREAM_BUFFER: usize = 64;
+
+pub type BookSender = tokio::sync::mpsc::Sender<BookChunk>;
+
+pub type DownloadEventSender = tokio::sync::mpsc::UnboundedSender<DownloadEvent>;
+
+/// Progress of a download handled by the plugin itself
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    /// A file was saved (or was already there)
+    Progress {
+        filename: String,
+    },
+    Failed {
+        filename: String,
+        error: String,
+    },
+    Message(String),
+}
 
 pub trait MXPlugin {
     async fn init(&mut self) -> anyhow::Result<()>;
     async fn destroy(&mut self) -> anyhow::Result<()>;
-    async fn get_book(&self, query: String) -> anyhow::Result<Book>;
+    async fn get_book(&self, query: String, reporter: PluginReporter) -> anyhow::Result<Book>;
+    /// Send the book piece by piece as it is being extracted,
+    /// defaults to sending the whole book at once
+    async fn stream_book(
+        &self,
+        query: String,
+        reporter: PluginReporter,
+        sender: BookSender,
+    ) -> anyhow::Result<()> {
+        let book = self.get_book(query, reporter).await?;
+        sender
+            .send(BookChunk::Book(book))
+            .await
+            .map_err(|_| anyhow::anyhow!("Book stream closed by the receiver"))
+    }
+    /// Whether `get_books` is cheaper than calling `get_book` for each term
+    fn can_fetch_many(&self) -> bool {
+        false
+    }
+    /// One result per term in the same order, fails as a whole if results cannot be told apart
+    async fn get_books(
+        &self,
+        queries: Vec<String>,
+        reporter: PluginReporter,
+    ) -> anyhow::Result<Vec<anyhow::Result<Book>>> {
+        let mut books = vec![];
+        for query in queries {
+            books.push(self.get_book(query, reporter.clone()).await);
+        }
+        Ok(books)
+    }
     async fn is_supported(&self, query: String) -> anyhow::Result<bool>;
+    /// Whether the plugin can take over the download of a whole chapter
+    fn can_download_chapter(&self) -> bool {
+        false
+    }
+    /// Download every page of `chapter` into `dest` and report through `events`
+    async fn download_chapter(
+        &self,
+        _chapter: &Chapter,
+        _dest: &Path,
+        _events: DownloadEventSender,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("Downloading a whole chapter is not supported")
+    }
+    /// Pages of a chapter returned without them (`lazy: plugin`), called at download time
+    async fn expand_chapter(&self, _chapter: &Chapter) -> anyhow::Result<Vec<Page>> {
+        anyhow::bail!("Resolving the pages of a chapter is not supported")
+    }
+    /// Cached books fetched by another version are fetched again, `None` if unknown
+    async fn version(&self) -> Option<String> {
+        None
+    }
     #[allow(unused)]
     async fn search(&self, term: String, option: SearchOption) -> anyhow::Result<Vec<Book>>;
-    fn download_url(&self, dest: &Path, url: &Url) -> Option<anyhow::Result<()>>;
+    async fn download_url(&self, dest: &Path, url: &Url) -> Option<anyhow::Result<()>>;
 }
 
 #[derive(Debug)]
 pub enum PluginImpl {
     Python(PythonPlugin),
     GalleryDL(GalleryDLPlugin),
+    YtDlp(YtDlpPlugin),
+    Declarative(DeclarativePlugin),
     // TODO:
     // Lua(LuaPlugin)
     // OldNXScraper(OldNXScraperPlugin) // full rust
 }
 
+impl PluginImpl {
+    pub fn name(&self) -> &str {
+        match self {
+            PluginImpl::Python(plugin) => &plugin.name,
+            PluginImpl::GalleryDL(plugin) => &plugin.name,
+            PluginImpl::YtDlp(plugin) => &plugin.name,
+            PluginImpl::Declarative(plugin) => &plugin.name,
+        }
+    }
+
+    pub async fn version(&self) -> Option<String> {
+        match self {
+            PluginImpl::Python(plugin) => plugin.version().await,
+            PluginImpl::GalleryDL(plugin) => plugin.version().await,
+            PluginImpl::YtDlp(plugin) => plugin.version().await,
+            PluginImpl::Declarative(plugin) => plugin.version().await,
+        }
+    }
+
+    pub async fn is_supported(&self, term: String) -> anyhow::Result<bool> {
+        match self {
+            PluginImpl::Python(plugin) => plugin.is_supported(term).await,
+            PluginImpl::GalleryDL(plugin) => plugin.is_supported(term).await,
+            PluginImpl::YtDlp(plugin) => plugin.is_supported(term).await,
+            PluginImpl::Declarative(plugin) => plugin.is_supported(term).await,
+        }
+    }
+}
+
 // FIXME: refactor wrapper type ops with generic apply
 // "cycle used when checking that `plugins::<impl at src\plugins\mod.rs:33:1: 33:16>::apply` is well-formed"
 // issue partly due to async traits (Sized Requirement for dyn Trait)
@@ -45,12 +163,104 @@ pub enum PluginImpl {
 //         match self {
 //             PluginImpl::Python(plugin) => func(plugin),
 //             PluginImpl::GalleryDL(plugin) => func(plugin),
+//             PluginImpl::YtDlp(plugin) => func(plugin),
+//             PluginImpl::Declarative(plugin) => func(plugin),
 //         }
 //     }
 // }
 
 pub struct PluginManager {
     plugins: Vec<PluginImpl>,
+    /// `(plugin name, host or prefix) => is_supported`
+    verdicts: Mutex<HashMap<(String, String), bool>>,
+}
+
+/// Outcome of asking a plugin whether it supports a term
+#[derive(Debug)]
+pub struct Claim {
+    pub plugin_name: String,
+    pub verdict: anyhow::Result<bool>,
+    /// Host or prefix of a previous term the verdict was reused from
+    pub cached_from: Option<String>,
+}
+
+impl Claim {
+    pub fn explain(&self) -> String {
+        let verdict = match &self.verdict {
+            Ok(true) => "supported".to_string(),
+            Ok(false) => "not supported".to_string(),
+            Err(e) => format!("error: {e}"),
+        };
+        match &self.cached_from {
+            Some(key) => format!(
+                "{}: {verdict} (cached verdict for {key:?})",
+                self.plugin_name
+            ),
+            None => format!("{}: {verdict} (is_supported)", self.plugin_name),
+        }
+    }
+}
+
+/// Logging and progress handle given to a plugin while it processes a term
+#[derive(Debug, Clone, Default)]
+pub struct PluginReporter {
+    pub term: String,
+    progress: Option<ProgressBar>,
+}
+
+impl PluginReporter {
+    pub fn new(term: String, progress: Option<ProgressBar>) -> Self {
+        Self { term, progress }
+    }
+
+    /// Route a message into `tracing` without breaking the progress display
+    pub fn log(&self, level: &str, message: &str) -> anyhow::Result<()> {
+        let level = match level.to_lowercase().as_str() {
+            "trace" => Level::TRACE,
+            "debug" => Level::DEBUG,
+            "info" => Level::INFO,
+            "warn" | "warning" => Level::WARN,
+            "error" => Level::ERROR,
+            _ => anyhow::bail!(
+                "Unknown log level {level:?}, expected trace, debug, info, warn or error"
+            ),
+        };
+
+        let term = &self.term;
+        let emit = || match level {
+            Level::TRACE => tracing::trace!("{term}: {message}"),
+            Level::DEBUG => tracing::debug!("{term}: {message}"),
+            Level::INFO => tracing::info!("{term}: {message}"),
+            Level::WARN => tracing::warn!("{term}: {message}"),
+            _ => tracing::error!("{term}: {message}"),
+        };
+
+        match &self.progress {
+            Some(pb) => pb.suspend(emit),
+            None => emit(),
+        }
+        Ok(())
+    }
+
+    /// Update the spinner associated with the term
+    pub fn progress(&self, current: u64, total: Option<u64>, label: Option<String>) {
+        if let Some(pb) = &self.progress {
+            let count = match total {
+                Some(total) => format!("[{current}/{total}]"),
+                None => format!("[{current}]"),
+            };
+            pb.set_message(match label {
+                Some(label) => format!("{count} {label}"),
+                None => count,
+            });
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(pb) = &self.progress {
+            pb.finish_and_clear();
+        }
+    }
 }
 
 #[derive(Debug, Clone, SimpleObject)]
@@ -74,50 +284,106 @@ impl FetchResult {
 
 impl PluginManager {
     pub fn new() -> Self {
-        Self { plugins: vec![] }
+        Self {
+            plugins: vec![],
+            verdicts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Use already initialized plugins, in the order of `priority`
+    pub fn with_plugins(mut plugins: Vec<PluginImpl>, priority: &[String]) -> Self {
+        // Listed plugins come first, the others keep their relative order
+        plugins.sort_by_key(|plugin| {
+            priority
+                .iter()
+                .position(|name| name == plugin.name())
+                .unwrap_or(priority.len())
+        });
+        Self {
+            plugins,
+            verdicts: Mutex::new(HashMap::new()),
+        }
     }
 
     /// Fetch a term using the first plugin that can handle it
-    pub async fn auto_fetch(&self, term: String) -> anyhow::Result<FetchResult> {
-        let mut issues = vec![];
-        for plugin in &self.plugins {
-            match plugin {
-                PluginImpl::Python(py) => {
-                    let supported = match py.is_supported(term.clone()).await {
-                        Ok(verdict) => verdict,
-                        Err(e) => {
-                            issues.push(format!("  - Plugin {}: {e}", py.name));
-                            false
-                        }
-                    };
+    pub async fn auto_fetch(
+        &self,
+        term: String,
+        reporter: PluginReporter,
+    ) -> anyhow::Result<FetchResult> {
+        self.fallthrough_fetch(term, reporter, vec![]).await
+    }
 
-                    if supported {
-                        match self.fetch(term.clone(), py.name.clone()).await {
-                            Ok(f) => return Ok(f),
-                            Err(e) => {
-                                issues.push(format!("  - Plugin {}: {e}", py.name));
-                            }
-                        }
-                    }
+    /// `auto_fetch` without the plugins that already `failed` on the term (e.g. in a batch)
+    pub async fn fallthrough_fetch(
+        &self,
+        term: String,
+        reporter: PluginReporter,
+        failed: Vec<(String, anyhow::Error)>,
+    ) -> anyhow::Result<FetchResult> {
+        let (fallthrough, route) = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            (config.plugins.fallthrough, config.route(&term)?)
+        };
+
+        let skipped = failed
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let mut issues = failed
+            .into_iter()
+            .map(|(name, e)| format!("  - Plugin {name}: {e}"))
+            .collect::<Vec<_>>();
+        if !fallthrough && !issues.is_empty() {
+            anyhow::bail!("\n{}", issues.join("\n"))
+        }
+
+        let mut routed_plugin = None;
+        if let Some((route, rewritten)) =
+            route.filter(|(route, _)| !skipped.contains(&route.plugin))
+        {
+            match self
+                .fetch(rewritten, route.plugin.clone(), reporter.clone())
+                .await
+            {
+                Ok(f) => return Ok(f),
+                Err(e) if !fallthrough => {
+                    return Err(e.context(format!(
+                        "Routed to {} by {}",
+                        route.plugin,
+                        route.describe()
+                    )))
                 }
-                PluginImpl::GalleryDL(dl) => {
-                    let supported = match dl.is_supported(term.clone()).await {
-                        Ok(verdict) => verdict,
-                        Err(e) => {
-                            issues.push(format!("  - Plugin {}: {e}", dl.name));
-                            false
-                        }
-                    };
+                Err(e) => issues.push(format!("  - Plugin {} (routed): {e}", route.plugin)),
+            }
+            routed_plugin = Some(route.plugin);
+        }
 
-                    if supported {
-                        match self.fetch(term.clone(), dl.name.clone()).await {
-                            Ok(f) => return Ok(f),
-                            Err(e) => {
-                                issues.push(format!("  - Plugin {}: {e}", dl.name));
+        for plugin in &self.plugins {
+            if routed_plugin.as_deref() == Some(plugin.name())
+                || skipped.iter().any(|name| name == plugin.name())
+            {
+                continue;
+            }
+
+            let claim = self.claim(plugin, &term).await;
+            match claim.verdict {
+                Ok(true) => {
+                    match self
+                        .fetch(term.clone(), claim.plugin_name.clone(), reporter.clone())
+                        .await
+                    {
+                        Ok(f) => return Ok(f),
+                        Err(e) => {
+                            issues.push(format!("  - Plugin {}: {e}", claim.plugin_name));
+                            if !fallthrough {
+                                break;
                             }
                         }
                     }
                 }
+                Ok(false) => {}
+                Err(e) => issues.push(format!("  - Plugin {}: {e}", claim.plugin_name)),
             }
         }
 
@@ -128,37 +394,65 @@ impl PluginManager {
         }
     }
 
+    /// Ask every plugin, in priority order, whether it supports the term
+    pub async fn explain(&self, term: &str) -> Vec<Claim> {
+        let mut claims = vec![];
+        for plugin in &self.plugins {
+            claims.push(self.claim(plugin, term).await);
+        }
+        claims
+    }
+
+    async fn claim(&self, plugin: &PluginImpl, term: &str) -> Claim {
+        let plugin_name = plugin.name().to_string();
+        let cache_verdicts = { GLOBAL_CONFIG.read().unwrap().plugins.cache_verdicts };
+        let key = utils::verdict_key(term).filter(|_| cache_verdicts);
+
+        if let Some(key) = &key {
+            let verdicts = self.verdicts.lock().unwrap();
+            if let Some(verdict) = verdicts.get(&(plugin_name.clone(), key.clone())) {
+                return Claim {
+                    plugin_name,
+                    verdict: Ok(*verdict),
+                    cached_from: Some(key.clone()),
+                };
+            }
+        }
+
+        let verdict = plugin.is_supported(term.to_string()).await;
+        if let (Some(key), Ok(verdict)) = (key, &verdict) {
+            let mut verdicts = self.verdicts.lock().unwrap();
+            verdicts.insert((plugin_name.clone(), key), *verdict);
+        }
+
+        Claim {
+            plugin_name,
+            verdict,
+            cached_from: None,
+        }
+    }
+
     async fn fetch_by_plugin<P: MXPlugin>(
         term: String,
         plugin_name: String,
         plugin: &P,
+        reporter: PluginReporter,
     ) -> anyhow::Result<FetchResult> {
-        let (enable_cache, cache_file_path, delay) = {
-            let config = GLOBAL_CONFIG.read().unwrap();
-            (
-                config.cache.enable,
-                config.get_cache_file_path(&term, &plugin_name),
-                config.delay.clone(),
-            )
-        };
+        let delay = { GLOBAL_CONFIG.read().unwrap().delay.clone() };
 
         let mut cached = false;
+        let version = plugin.version().await;
 
-        let book = if enable_cache && cache_file_path.exists() {
-            cached = true;
-            let content = std::fs::read_to_string(&cache_file_path)?;
-            serde_json::from_str(&content).with_context(|| {
-                format!(
-                    "Deserializing cache file for term {term} located at {:?}",
-                    cache_file_path.display()
-                )
-            })?
-        } else {
-            let book = plugin.get_book(term.clone()).await?;
-            let content = serde_json::to_string_pretty(&book)?;
-            std::fs::write(&cache_file_path, content)
-                .with_context(|| format!("Writing cache {:?}", cache_file_path.display()))?;
-            book
+        let book = match read_cache(&term, &plugin_name, version.as_deref()).await? {
+            Some(book) => {
+                cached = true;
+                book
+            }
+            None => {
+                let book = plugin.get_book(term.clone(), reporter).await?;
+                write_cache(&term, &plugin_name, version, &book).await?;
+                book
+            }
         };
         tokio::time::sleep(Duration::from_millis(delay.fetch as u64)).await;
 
@@ -170,18 +464,78 @@ impl PluginManager {
         })
     }
 
+    async fn stream_by_plugin<P: MXPlugin>(
+        term: String,
+        plugin_name: String,
+        plugin: &P,
+        reporter: PluginReporter,
+        sender: BookSender,
+    ) -> anyhow::Result<()> {
+        let version = plugin.version().await;
+        if let Some(book) = read_cache(&term, &plugin_name, version.as_deref()).await? {
+            return sender
+                .send(BookChunk::Book(book))
+                .await
+                .map_err(|_| anyhow::anyhow!("Book stream closed by the receiver"));
+        }
+
+        // Forward everything while keeping a copy for the cache
+        let (inner_sender, mut inner_receiver) =
+            tokio::sync::mpsc::channel::<BookChunk>(STREAM_BUFFER);
+        // `None` once the receiver is gone, dropping `inner_receiver` stops the plugin
+        let forward = {
+            let mut assembler = BookAssembler::new(term.clone());
+            async move {
+                while let Some(chunk) = inner_receiver.recv().await {
+                    assembler.push(chunk.clone());
+                    if sender.send(chunk).await.is_err() {
+                        return None;
+                    }
+                }
+                Some(assembler.finish())
+            }
+        };
+
+        let (produced, book) = tokio::join!(
+            plugin.stream_book(term.clone(), reporter, inner_sender),
+            forward
+        );
+        // Only complete books are cached
+        let Some(book) = book else {
+            anyhow::bail!("Book stream closed by the receiver");
+        };
+        produced?;
+
+        write_cache(&term, &plugin_name, version, &book).await
+    }
+
     /// Fetch and bypass term validation
-    pub async fn fetch(&self, term: String, plugin_name: String) -> anyhow::Result<FetchResult> {
+    pub async fn fetch(
+        &self,
+        term: String,
+        plugin_name: String,
+        reporter: PluginReporter,
+    ) -> anyhow::Result<FetchResult> {
         for plugin in &self.plugins {
             match plugin {
                 PluginImpl::Python(plugin) => {
                     if plugin.name.eq(&plugin_name) {
-                        return Self::fetch_by_plugin(term, plugin_name, plugin).await;
+                        return Self::fetch_by_plugin(term, plugin_name, plugin, reporter).await;
                     }
                 }
                 PluginImpl::GalleryDL(plugin) => {
                     if plugin.name.eq(&plugin_name) {
-                        return Self::fetch_by_plugin(term, plugin_name, plugin).await;
+                        return Self::fetch_by_plugin(term, plugin_name, plugin, reporter).await;
+                    }
+                }
+                PluginImpl::YtDlp(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::fetch_by_plugin(term, plugin_name, plugin, reporter).await;
+                    }
+                }
+                PluginImpl::Declarative(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::fetch_by_plugin(term, plugin_name, plugin, reporter).await;
                     }
                 }
             }
@@ -190,17 +544,84 @@ impl PluginManager {
         anyhow::bail!("No plugin named {plugin_name:?}")
     }
 
+    /// Stream a book into `sender` and bypass term validation
+    pub async fn stream(
+        &self,
+        term: String,
+        plugin_name: String,
+        reporter: PluginReporter,
+        sender: BookSender,
+    ) -> anyhow::Result<()> {
+        for plugin in &self.plugins {
+            match plugin {
+                PluginImpl::Python(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::stream_by_plugin(term, plugin_name, plugin, reporter, sender)
+                            .await;
+                    }
+                }
+                PluginImpl::GalleryDL(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::stream_by_plugin(term, plugin_name, plugin, reporter, sender)
+                            .await;
+                    }
+                }
+                PluginImpl::YtDlp(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::stream_by_plugin(term, plugin_name, plugin, reporter, sender)
+                            .await;
+                    }
+                }
+                PluginImpl::Declarative(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::stream_by_plugin(term, plugin_name, plugin, reporter, sender)
+                            .await;
+                    }
+                }
+            }
+        }
+
+        anyhow::bail!("No plugin named {plugin_name:?}")
+    }
+
+    /// First plugin that can handle the term, along with the term it expects
+    pub async fn find_supporting_plugin(&self, term: String) -> anyhow::Result<(String, String)> {
+        let route = { GLOBAL_CONFIG.read().unwrap().route(&term)? };
+        if let Some((route, rewritten)) = route {
+            return Ok((route.plugin, rewritten));
+        }
+
+        let mut issues = vec![];
+        for plugin in &self.plugins {
+            let claim = self.claim(plugin, &term).await;
+            match claim.verdict {
+                Ok(true) => return Ok((claim.plugin_name, term)),
+                Ok(false) => {}
+                Err(e) => issues.push(format!("  - Plugin {}: {e}", claim.plugin_name)),
+            }
+        }
+
+        if !issues.is_empty() {
+            anyhow::bail!("\n{}", issues.join("\n"))
+        } else {
+            anyhow::bail!("Cannot auto-detect plugin that supports the term {term:?}\nYou can try with --plugin <PLUGIN_NAME>")
+        }
+    }
+
     /// A list of all installed plugins
     pub fn list_plugins(&self) -> Vec<String> {
         self.plugins
             .iter()
-            .map(|plugin| match plugin {
-                PluginImpl::Python(plugin) => plugin.name.clone(),
-                PluginImpl::GalleryDL(plugin) => plugin.name.clone(),
-            })
+            .map(|plugin| plugin.name().to_string())
             .collect()
     }
 
+    /// Current version of a plugin, `None` if unknown or if the plugin does not exist
+    pub async fn plugin_version(&self, plugin_name: &str) -> Option<String> {
+        let plugin = self.plugins.iter().find(|p| p.name() == plugin_name)?;
+        plugin.version().await
+    }
+
     /// Fail if name is missing
     pub fn assert_exists(&self, plugin_name: String) -> anyhow::Result<()> {
         if !self.list_plugins().contains(&plugin_name) {
@@ -209,8 +630,17 @@ impl PluginManager {
         Ok(())
     }
 
+    /// Fail if a route sends terms to a missing plugin
+    pub fn check_routes(&self, routing: &[Route]) -> anyhow::Result<()> {
+        for (p, route) in routing.iter().enumerate() {
+            self.assert_exists(route.plugin.clone())
+                .with_context(|| format!("Invalid route #{} ({})", p + 1, route.describe()))?;
+        }
+        Ok(())
+    }
+
     /// Custom downloader for a plugin
-    pub fn download_url(
+    pub async fn download_url(
         &self,
         plugin_name: &str,
         dest: &Path,
@@ -220,12 +650,282 @@ impl PluginManager {
             match plugin {
                 PluginImpl::Python(plugin) => {
                     if plugin_name.eq(&plugin.name) {
-                        return plugin.download_url(dest, url);
+                        return plugin.download_url(dest, url).await;
+                    }
+                }
+                PluginImpl::GalleryDL(plugin) => {
+                    if plugin_name.eq(&plugin.name) {
+                        return plugin.download_url(dest, url).await;
+                    }
+                }
+                PluginImpl::YtDlp(plugin) => {
+                    if plugin_name.eq(&plugin.name) {
+                        return plugin.download_url(dest, url).await;
+                    }
+                }
+                PluginImpl::Declarative(plugin) => {
+                    if plugin_name.eq(&plugin.name) {
+                        return plugin.download_url(dest, url).await;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn can_fetch_many(&self, plugin_name: &str) -> bool {
+        self.plugins.iter().any(|plugin| match plugin {
+            PluginImpl::Python(plugin) => plugin_name.eq(&plugin.name) && plugin.can_fetch_many(),
+            PluginImpl::GalleryDL(plugin) => {
+                plugin_name.eq(&plugin.name) && plugin.can_fetch_many()
+            }
+            PluginImpl::YtDlp(plugin) => plugin_name.eq(&plugin.name) && plugin.can_fetch_many(),
+            PluginImpl::Declarative(plugin) => {
+                plugin_name.eq(&plugin.name) && plugin.can_fetch_many()
+            }
+        })
+    }
+
+    /// Terms that can be fetched together, by plugin, along with the term given to the plugin
+    /// * every term goes to `plugin` if provided, auto-detected otherwise
+    /// * the remaining terms should be fetched one by one
+    pub async fn group_terms(
+        &self,
+        terms: &[String],
+        plugin: Option<&str>,
+    ) -> (IndexMap<String, Vec<(String, String)>>, Vec<String>) {
+        let mut groups: IndexMap<String, Vec<(String, String)>> = IndexMap::new();
+        let mut remaining = vec![];
+        for term in terms {
+            let target = match plugin {
+                Some(name) => Some((name.to_string(), term.clone())),
+                None => self.find_supporting_plugin(term.clone()).await.ok(),
+            };
+            match target {
+                Some((name, rewritten)) if self.can_fetch_many(&name) => {
+                    groups
+                        .entry(name)
+                        .or_default()
+                        .push((term.clone(), rewritten));
+                }
+                _ => remaining.push(term.clone()),
+            }
+        }
+
+        // Not worth it for a single term
+        groups.retain(|_, group| {
+            if group.len() > 1 {
+                return true;
+            }
+            remaining.extend(group.iter().map(|(term, _)| term.clone()));
+            false
+        });
+
+        (groups, remaining)
+    }
+
+    /// Fetch many terms with as few calls to the plugin as possible, cached terms are skipped
+    pub async fn fetch_many(
+        &self,
+        terms: Vec<String>,
+        plugin_name: String,
+        reporter: PluginReporter,
+    ) -> Vec<anyhow::Result<FetchResult>> {
+        for plugin in &self.plugins {
+            match plugin {
+                PluginImpl::Python(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::fetch_many_by_plugin(terms, plugin_name, plugin, reporter)
+                            .await;
+                    }
+                }
+                PluginImpl::GalleryDL(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::fetch_many_by_plugin(terms, plugin_name, plugin, reporter)
+                            .await;
+                    }
+                }
+                PluginImpl::YtDlp(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::fetch_many_by_plugin(terms, plugin_name, plugin, reporter)
+                            .await;
+                    }
+                }
+                PluginImpl::Declarative(plugin) => {
+                    if plugin.name.eq(&plugin_name) {
+                        return Self::fetch_many_by_plugin(terms, plugin_name, plugin, reporter)
+                            .await;
+                    }
+                }
+            }
+        }
+
+        terms
+            .iter()
+            .map(|_| Err(anyhow::anyhow!("Plugin {plugin_name:?} does not exist")))
+            .collect()
+    }
+
+    async fn fetch_many_by_plugin<P: MXPlugin>(
+        terms: Vec<String>,
+        plugin_name: String,
+        plugin: &P,
+        reporter: PluginReporter,
+    ) -> Vec<anyhow::Result<FetchResult>> {
+        let mut results: Vec<Option<anyhow::Result<FetchResult>>> = vec![];
+        let mut missing = vec![];
+        let version = plugin.version().await;
+        for (p, term) in terms.iter().enumerate() {
+            results.push(
+                match read_cache(term, &plugin_name, version.as_deref()).await {
+                    Ok(Some(book)) => Some(Ok(FetchResult {
+                        query_term: term.clone(),
+                        book,
+                        plugin_name: plugin_name.clone(),
+                        cached: true,
+                    })),
+                    Ok(None) => {
+                        missing.push(p);
+                        None
+                    }
+                    Err(e) => Some(Err(e)),
+                },
+            );
+        }
+
+        if !missing.is_empty() {
+            let delay = { GLOBAL_CONFIG.read().unwrap().delay.clone() };
+            let queries = missing.iter().map(|p| terms[*p].clone()).collect();
+            let fetched = plugin.get_books(queries, reporter.clone()).await;
+            tokio::time::sleep(Duration::from_millis(delay.fetch as u64)).await;
+            let books = match fetched {
+                Ok(books) if books.len() == missing.len() => books,
+                Ok(books) => {
+                    tracing::warn!(
+                        "{plugin_name}: {} results for {} terms, fetching one by one",
+                        books.len(),
+                        missing.len()
+                    );
+                    Self::get_books_one_by_one(plugin, &terms, &missing, &reporter).await
+                }
+                Err(e) => {
+                    tracing::warn!("{plugin_name}: {e}, fetching one by one");
+                    Self::get_books_one_by_one(plugin, &terms, &missing, &reporter).await
+                }
+            };
+
+            for (p, book) in missing.into_iter().zip(books) {
+                let term = &terms[p];
+                results[p] = Some(match book {
+                    Ok(book) => write_cache(term, &plugin_name, version.clone(), &book)
+                        .await
+                        .map(|_| FetchResult {
+                            query_term: term.clone(),
+                            book,
+                            plugin_name: plugin_name.clone(),
+                            cached: false,
+                        }),
+                    Err(e) => Err(e),
+                });
+            }
+        }
+
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    async fn get_books_one_by_one<P: MXPlugin>(
+        plugin: &P,
+        terms: &[String],
+        indices: &[usize],
+        reporter: &PluginReporter,
+    ) -> Vec<anyhow::Result<Book>> {
+        let delay = { GLOBAL_CONFIG.read().unwrap().delay.clone() };
+        let mut books = vec![];
+        for p in indices {
+            books.push(plugin.get_book(terms[*p].clone(), reporter.clone()).await);
+            tokio::time::sleep(Duration::from_millis(delay.fetch as u64)).await;
+        }
+        books
+    }
+
+    pub fn can_download_chapter(&self, plugin_name: &str) -> bool {
+        self.plugins.iter().any(|plugin| match plugin {
+            PluginImpl::Python(plugin) => {
+                plugin_name.eq(&plugin.name) && plugin.can_download_chapter()
+            }
+            PluginImpl::GalleryDL(plugin) => {
+                plugin_name.eq(&plugin.name) && plugin.can_download_chapter()
+            }
+            PluginImpl::YtDlp(plugin) => {
+                plugin_name.eq(&plugin.name) && plugin.can_download_chapter()
+            }
+            PluginImpl::Declarative(plugin) => {
+                plugin_name.eq(&plugin.name) && plugin.can_download_chapter()
+            }
+        })
+    }
+
+    /// Pages of a lazy chapter, resolved by the plugin that produced it
+    pub async fn expand_chapter(
+        &self,
+        plugin_name: &str,
+        chapter: &Chapter,
+    ) -> anyhow::Result<Vec<Page>> {
+        for plugin in self.plugins.iter() {
+            match plugin {
+                PluginImpl::Python(plugin) => {
+                    if plugin_name.eq(&plugin.name) {
+                        return plugin.expand_chapter(chapter).await;
                     }
                 }
                 PluginImpl::GalleryDL(plugin) => {
                     if plugin_name.eq(&plugin.name) {
-                        return plugin.download_url(dest, url);
+                        return plugin.expand_chapter(chapter).await;
+                    }
+                }
+                PluginImpl::YtDlp(plugin) => {
+                    if plugin_name.eq(&plugin.name) {
+                        return plugin.expand_chapter(chapter).await;
+                    }
+                }
+                PluginImpl::Declarative(plugin) => {
+                    if plugin_name.eq(&plugin.name) {
+                        return plugin.expand_chapter(chapter).await;
+                    }
+                }
+            }
+        }
+        anyhow::bail!("Plugin {plugin_name:?} not found")
+    }
+
+    /// Custom downloader for a whole chapter
+    pub async fn download_chapter(
+        &self,
+        plugin_name: &str,
+        chapter: &Chapter,
+        dest: &Path,
+        events: DownloadEventSender,
+    ) -> Option<anyhow::Result<()>> {
+        for plugin in self.plugins.iter() {
+            match plugin {
+                PluginImpl::Python(plugin) => {
+                    if plugin_name.eq(&plugin.name) && plugin.can_download_chapter() {
+                        return Some(plugin.download_chapter(chapter, dest, events).await);
+                    }
+                }
+                PluginImpl::GalleryDL(plugin) => {
+                    if plugin_name.eq(&plugin.name) && plugin.can_download_chapter() {
+                        return Some(plugin.download_chapter(chapter, dest, events).await);
+                    }
+                }
+                PluginImpl::YtDlp(plugin) => {
+                    if plugin_name.eq(&plugin.name) && plugin.can_download_chapter() {
+                        return Some(plugin.download_chapter(chapter, dest, events).await);
+                    }
+                }
+                PluginImpl::Declarative(plugin) => {
+                    if plugin_name.eq(&plugin.name) && plugin.can_download_chapter() {
+                        return Some(plugin.download_chapter(chapter, dest, events).await);
                     }
                 }
             }
@@ -235,11 +935,17 @@ impl PluginManager {
 
     /// Initialize all plugins
     pub async fn init(&mut self) -> anyhow::Result<()> {
-        let location = { GLOBAL_CONFIG.read().unwrap().plugins.clone().location };
+        let (location, priority) = {
+            let plugins = GLOBAL_CONFIG.read().unwrap().plugins.clone();
+            (plugins.location, plugins.priority)
+        };
         self.prepare_folders();
 
         let mut dyn_plugins = vec![];
-        let static_plugins = vec![PluginImpl::GalleryDL(GalleryDLPlugin::new())];
+        let static_plugins = vec![
+            PluginImpl::GalleryDL(GalleryDLPlugin::new()),
+            PluginImpl::YtDlp(YtDlpPlugin::new()),
+        ];
 
         let plug_dir = location.canonicalize()?;
         // +-- plugin_location
@@ -247,6 +953,8 @@ impl PluginManager {
         //      + __init__.py
         //   +- bar
         //      + __init__.py
+        //   +- baz
+        //      + site.yaml
 
         for entry in plug_dir.read_dir()? {
             let entry = entry?;
@@ -257,11 +965,16 @@ impl PluginManager {
                 let python = PythonPlugin {
                     name: plugin_name.clone(),
                     workdir: None,
+                    version: None,
                 };
                 dyn_plugins.push(PluginImpl::Python(python));
+            } else if workdir.join(declarative::SITE_FILE).exists() {
+                let site = DeclarativePlugin::new(plugin_name.clone(), workdir);
+                dyn_plugins.push(PluginImpl::Declarative(site));
             }
         }
 
+        dyn_plugins.sort_by(|a: &PluginImpl, b| a.name().cmp(b.name()));
         let mut plugins = dyn_plugins
             .into_iter()
             .chain(static_plugins.into_iter())
@@ -271,11 +984,16 @@ impl PluginManager {
             match plugin {
                 PluginImpl::Python(py) => py.init().await?,
                 PluginImpl::GalleryDL(dl) => dl.init().await?,
+                PluginImpl::YtDlp(dl) => dl.init().await?,
+                PluginImpl::Declarative(dl) => dl.init().await?,
             }
         }
 
-        self.plugins = plugins;
-        Ok(())
+        self.plugins = Self::with_plugins(plugins, &priority).plugins;
+
+        // Routes are compiled with the config, their plugins can only be checked now
+        let routing = { GLOBAL_CONFIG.read().unwrap().routing.clone() };
+        self.check_routes(&routing)
     }
 
     fn prepare_folders(&self) {
@@ -317,8 +1035,58 @@ impl PluginManager {
             match plugin {
                 PluginImpl::Python(py) => py.destroy().await?,
                 PluginImpl::GalleryDL(dl) => dl.destroy().await?,
+                PluginImpl::YtDlp(dl) => dl.destroy().await?,
+                PluginImpl::Declarative(dl) => dl.destroy().await?,
             }
         }
         Ok(())
     }
 }
+
+/// Cached book of `term`, `None` if caching is disabled or if the entry is stale
+/// Storages block (files, SQLite waiting for another writer), they are kept off the async workers
+async fn read_cache(
+    term: &str,
+    plugin_name: &str,
+    plugin_version: Option<&str>,
+) -> anyhow::Result<Option<Book>> {
+    let (enable_cache, storage, ttl) = {
+        let config = GLOBAL_CONFIG.read().unwrap();
+        (
+            config.cache.enable,
+            config.get_cache_storage(),
+            config.cache.ttl_for(plugin_name),
+        )
+    };
+
+    if !enable_cache {
+        return Ok(None);
+    }
+
+    let signature = utils::compute_query_signature(term, plugin_name);
+    let Some(entry) = tokio::task::spawn_blocking(move || storage.get(&signature))
+        .await?
+        .with_context(|| format!("Reading cache for term {term}"))?
+    else {
+        return Ok(None);
+    };
+    match entry.freshness(ttl, plugin_version, Local::now()) {
+        Freshness::Fresh => Ok(Some(entry.book)),
+        freshness => {
+            tracing::debug!("{plugin_name}: {freshness} cache for term {term}, fetching again");
+            Ok(None)
+        }
+    }
+}
+
+async fn write_cache(
+    term: &str,
+    plugin_name: &str,
+    plugin_version: Option<String>,
+    book: &Book,
+) -> anyhow::Result<()> {
+    let storage = { GLOBAL_CONFIG.read().unwrap().get_cache_storage() };
+    let signature = utils::compute_query_signature(term, plugin_name);
+    let entry = CacheEntry::new(term, plugin_name, plugin_version, book.clone());
+    tokio::task::spawn_blocking(move || storage.put(&signature, &entry)).await?
+}
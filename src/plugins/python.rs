@@ -5,21 +5,27 @@ use std::{
 };
 
 use crate::{
-    core::http::ContextProvider,
-    schemas::book::{Book, SearchOption},
+    core::{http::ContextProvider, utils},
+    schemas::book::{Book, BookAssembler, BookChunk, Chapter, Page, SearchOption},
     GLOBAL_CONFIG,
 };
 use anyhow::{bail, Context, Ok};
-use pyo3::{exceptions::PyException, prelude::*, types::PyBytes};
+use pyo3::{
+    exceptions::PyException,
+    prelude::*,
+    types::{PyBytes, PyList},
+};
 use serde_pyobject::{from_pyobject, to_pyobject};
 use url::Url;
 
-use super::MXPlugin;
+use super::{BookSender, MXPlugin, PluginReporter};
 
 #[derive(Debug, Clone)]
 pub struct PythonPlugin {
     pub name: String,
     pub workdir: Option<PathBuf>,
+    /// Digest of the sources, computed by `init`
+    pub version: Option<String>,
 }
 
 impl MXPlugin for PythonPlugin {
@@ -36,6 +42,8 @@ impl MXPlugin for PythonPlugin {
         }
 
         std::env::set_var("PYTHONPATH", self.workdir.clone().unwrap());
+        let folder = self.workdir.as_ref().unwrap().join(&self.name);
+        self.version = utils::digest_folder(&folder, &["py"]);
         Ok(())
     }
 
@@ -43,22 +51,84 @@ impl MXPlugin for PythonPlugin {
         Ok(())
     }
 
-    async fn get_book(&self, term: String) -> anyhow::Result<Book> {
+    async fn get_book(&self, term: String, reporter: PluginReporter) -> anyhow::Result<Book> {
+        pyo3::prepare_freethreaded_python();
+        let verbose = { GLOBAL_CONFIG.read().unwrap().verbose };
+
+        Python::with_gil(|py| {
+            let mut assembler = BookAssembler::new(term.clone());
+            self.extract_chunks(py, term, reporter, verbose, |chunk| {
+                assembler.push(chunk);
+                Ok(())
+            })?;
+            Ok(assembler.finish())
+        })
+    }
+
+    async fn stream_book(
+        &self,
+        term: String,
+        reporter: PluginReporter,
+        sender: BookSender,
+    ) -> anyhow::Result<()> {
+        pyo3::prepare_freethreaded_python();
+        let verbose = { GLOBAL_CONFIG.read().unwrap().verbose };
+        let plugin = self.clone();
+
+        // Generators can run for a long time, keep them off the async workers
+        tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| {
+                plugin.extract_chunks(py, term, reporter, verbose, |chunk| {
+                    match py.allow_threads(|| sender.blocking_send(chunk).is_ok()) {
+                        true => Ok(()),
+                        false => bail!("Book stream closed by the receiver"),
+                    }
+                })
+            })
+        })
+        .await?
+    }
+
+    /// `mx_get_chapter(url, req)` returns the chapter with its pages or a list of urls
+    async fn expand_chapter(&self, chapter: &Chapter) -> anyhow::Result<Vec<Page>> {
         pyo3::prepare_freethreaded_python();
         let verbose = { GLOBAL_CONFIG.read().unwrap().verbose };
+        let url = chapter.url.clone();
 
         Python::with_gil(|py| {
             let name: &str = self.name.as_ref();
             let plugin = py.import_bound(name)?;
-            let mx_request = MxRequest;
-
-            if plugin.hasattr("mx_get_urls")? {
-                self.mx_get_urls(py, plugin, term, mx_request, verbose)
-            } else if plugin.hasattr("mx_get_book")? {
-                self.mx_get_book(py, plugin, term, mx_request, verbose)
-            } else {
-                bail!("Invalid could not find mx_get_urls(term, req) or mx_get_book(term, req)",)
+            if !plugin.hasattr("mx_get_chapter")? {
+                bail!("{name}: lazy chapter {url:?} requires mx_get_chapter(url, req)");
+            }
+            let mx_context = MxContext {
+                reporter: PluginReporter::new(url.clone(), None),
+            };
+            let res = call_with_optional_context(
+                py,
+                &plugin,
+                "mx_get_chapter",
+                &url,
+                MxRequest,
+                mx_context,
+            )
+            .map_err(|e| self.report_error(py, "mx_get_chapter", &url, e, verbose))?;
+
+            if res.is_instance_of::<PyList>() {
+                let urls: Vec<String> = res.extract()?;
+                return urls
+                    .iter()
+                    .enumerate()
+                    .map(|(p, page_url)| {
+                        let page_url = Url::from_str(page_url)
+                            .with_context(|| format!("Failed Parsing: {page_url:?}"))?;
+                        Ok(Page::from_url(p as u32 + 1, &page_url))
+                    })
+                    .collect();
             }
+            let expanded: Chapter =
+                from_pyobject(res.clone()).with_context(|| format!("Deserializing {res:?}"))?;
+            Ok(expanded.pages)
         })
     }
 
@@ -80,35 +150,77 @@ impl MXPlugin for PythonPlugin {
         })
     }
 
-    fn download_url(&self, _dest: &Path, _url: &Url) -> Option<anyhow::Result<()>> {
+    /// Digest of the sources of the plugin when it was loaded
+    async fn version(&self) -> Option<String> {
+        self.version.clone()
+    }
+
+    async fn download_url(&self, _dest: &Path, _url: &Url) -> Option<anyhow::Result<()>> {
         None
     }
 }
 
 impl PythonPlugin {
-    fn mx_get_book(
+    /// Feed everything produced by the plugin to `consume`
+    fn extract_chunks<F>(
+        &self,
+        py: Python<'_>,
+        term: String,
+        reporter: PluginReporter,
+        verbose: bool,
+        mut consume: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(BookChunk) -> anyhow::Result<()>,
+    {
+        let name: &str = self.name.as_ref();
+        let plugin = py.import_bound(name)?;
+        let mx_request = MxRequest;
+        let mx_context = MxContext { reporter };
+
+        if plugin.hasattr("mx_get_urls")? {
+            let book = self.mx_get_urls(py, plugin, term, mx_request, mx_context, verbose)?;
+            consume(BookChunk::Book(book))
+        } else if plugin.hasattr("mx_get_book")? {
+            self.mx_get_book(py, plugin, term, mx_request, mx_context, verbose, consume)
+        } else {
+            bail!("Invalid could not find mx_get_urls(term, req) or mx_get_book(term, req)",)
+        }
+    }
+
+    /// `mx_get_book` either returns a whole book or is a generator yielding `BookChunk`s
+    #[allow(clippy::too_many_arguments)]
+    fn mx_get_book<F>(
         &self,
         py: Python<'_>,
         plugin: Bound<'_, PyModule>,
         term: String,
         mx_request: MxRequest,
+        mx_context: MxContext,
         verbose: bool,
-    ) -> anyhow::Result<Book> {
-        match plugin.call_method1("mx_get_book", (term.clone(), mx_request)) {
-            Err(e) => {
-                if verbose {
-                    e.print(py)
-                }
-                bail!(
-                    "{}.mx_get_book(term = {term:?}, ..): {e}",
-                    self.name.clone(),
-                )
-            }
-            other => {
-                let other = other?;
-                from_pyobject(other.clone()).with_context(|| format!("Deserializing {:?}", other))
-            }
+        mut consume: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(BookChunk) -> anyhow::Result<()>,
+    {
+        let res =
+            call_with_optional_context(py, &plugin, "mx_get_book", &term, mx_request, mx_context)
+                .map_err(|e| self.report_error(py, "mx_get_book", &term, e, verbose))?;
+
+        if !res.hasattr("__next__")? {
+            let book =
+                from_pyobject(res.clone()).with_context(|| format!("Deserializing {res:?}"))?;
+            return consume(BookChunk::Book(book));
+        }
+
+        for item in res.iter()? {
+            let item = item.map_err(|e| self.report_error(py, "mx_get_book", &term, e, verbose))?;
+            let chunk = from_pyobject(item.clone())
+                .with_context(|| format!("Deserializing chunk {item:?}"))?;
+            consume(chunk)?;
         }
+
+        Ok(())
     }
 
     fn mx_get_urls(
@@ -117,28 +229,67 @@ impl PythonPlugin {
         plugin: Bound<'_, PyModule>,
         term: String,
         mx_request: MxRequest,
+        mx_context: MxContext,
         verbose: bool,
     ) -> anyhow::Result<Book> {
-        let res = plugin
-            .call_method1("mx_get_urls", (term.clone(), mx_request))
-            .map_err(|e| {
-                if verbose {
-                    e.print(py)
-                }
-                anyhow::anyhow!(
-                    "{}.mx_get_urls(term = {term:?}, ..): {e}",
-                    self.name.clone(),
-                )
-            })?;
+        let res =
+            call_with_optional_context(py, &plugin, "mx_get_urls", &term, mx_request, mx_context)
+                .map_err(|e| self.report_error(py, "mx_get_urls", &term, e, verbose))?;
 
         Book::from_raw_urls(from_pyobject(res)?)
     }
+
+    fn report_error(
+        &self,
+        py: Python<'_>,
+        method: &str,
+        term: &str,
+        e: PyErr,
+        verbose: bool,
+    ) -> anyhow::Error {
+        if verbose {
+            e.print(py)
+        }
+        anyhow::anyhow!("{}.{method}(term = {term:?}, ..): {e}", self.name.clone())
+    }
+}
+
+/// Calls `method(term, req, ctx)` if the plugin function accepts a third argument,
+/// `method(term, req)` otherwise
+/// * the arguments are bound to the signature without calling the function, so that
+///   defaults, `*args` and decorators using `functools.wraps` are taken into account
+fn call_with_optional_context<'py>(
+    py: Python<'py>,
+    plugin: &Bound<'py, PyModule>,
+    method: &str,
+    term: &str,
+    mx_request: MxRequest,
+    mx_context: MxContext,
+) -> PyResult<Bound<'py, PyAny>> {
+    let func = plugin.getattr(method)?;
+    let accepts_context = py
+        .import_bound("inspect")?
+        .call_method1("signature", (func.clone(),))
+        .and_then(|signature| signature.call_method1("bind", (term, py.None(), py.None())))
+        .is_ok();
+
+    if accepts_context {
+        func.call1((term.to_string(), mx_request, mx_context))
+    } else {
+        func.call1((term.to_string(), mx_request))
+    }
 }
 
 #[pyclass]
 #[derive(Debug)]
 pub struct MxRequest;
 
+#[pyclass]
+#[derive(Debug)]
+pub struct MxContext {
+    reporter: PluginReporter,
+}
+
 macro_rules! can_throw_exception {
     ($e: expr) => {
         $e.map_err(|e| PyException::new_err(e.to_string()))?
@@ -181,3 +332,21 @@ impl MxRequest {
         to_pyobject(py, &context).unwrap().unbind()
     }
 }
+
+#[pymethods]
+impl MxContext {
+    fn log(&self, level: String, message: String) -> PyResult<()> {
+        can_throw_exception!(self.reporter.log(&level, &message));
+        std::result::Result::Ok(())
+    }
+
+    #[pyo3(signature = (current, total=None, label=None))]
+    fn progress(&self, current: u64, total: Option<u64>, label: Option<String>) {
+        self.reporter.progress(current, total, label);
+    }
+
+    #[getter]
+    pub fn term(&self) -> String {
+        self.reporter.term.clone()
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    core::{http::FetchContext, utils},
+    schemas::book::{Author, Book, Chapter, Metadata, Page, SearchOption, Tag},
+    GLOBAL_CONFIG,
+};
+use schema::{Format, VideoInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::process::Command;
+use url::Url;
+
+use super::{
+    context::{ContextArgs, HeaderArgs},
+    process::{binary_version, parse_option, parse_timeout, with_timeout, SubProcess},
+    DownloadEvent, DownloadEventSender, MXPlugin, PluginReporter,
+};
+pub mod schema;
+
+/// Hosts of common extractors, claimed without asking yt-dlp
+const KNOWN_HOSTS: [&str; 10] = [
+    "youtube.com",
+    "youtu.be",
+    "vimeo.com",
+    "dailymotion.com",
+    "twitch.tv",
+    "soundcloud.com",
+    "bandcamp.com",
+    "bilibili.com",
+    "nicovideo.jp",
+    "streamable.com",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtraConfig {
+    pub bin: PathBuf,
+    pub argv: Vec<String>,
+    /// `-f` format selection, `None` keeps yt-dlp's own default
+    pub format: Option<String>,
+    /// host => format selection, from `format.<host>` keys
+    pub host_formats: HashMap<String, String>,
+    /// Limit for fetching the metadata of a term
+    pub timeout: Option<Duration>,
+    /// Limit for probing an url during auto-detection
+    pub probe_timeout: Option<Duration>,
+    /// Limit for downloading a single url or a whole chapter
+    pub download_timeout: Option<Duration>,
+    /// Pass cookies, user agent, headers, auth and proxy from mx-scraper's config
+    pub forward_context: bool,
+    /// Claimed during auto-detection along with `KNOWN_HOSTS`, subdomains included
+    pub hosts: Vec<String>,
+    /// Ask yt-dlp about the other urls during auto-detection, it requests them,
+    /// off by default since every unknown http(s) term then spawns a network extraction
+    pub probe: bool,
+}
+
+impl Default for ExtraConfig {
+    fn default() -> Self {
+        Self {
+            bin: PathBuf::from("yt-dlp"),
+            argv: vec![],
+            format: None,
+            host_formats: HashMap::new(),
+            timeout: Some(Duration::from_secs(120)),
+            probe_timeout: Some(Duration::from_secs(30)),
+            download_timeout: None,
+            forward_context: true,
+            hosts: vec![],
+            probe: false,
+        }
+    }
+}
+
+impl ExtraConfig {
+    /// Timeouts are given in seconds, 0 disables them
+    pub fn from_map(cfg: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            bin: cfg.get("bin").map(PathBuf::from).unwrap_or(default.bin),
+            argv: cfg
+                .get("argv")
+                .and_then(|argv| shlex::split(argv))
+                .unwrap_or_default(),
+            format: cfg.get("format").cloned(),
+            host_formats: cfg
+                .iter()
+                .filter_map(|(key, format)| {
+                    let host = key.strip_prefix("format.")?;
+                    Some((host.to_lowercase(), format.clone()))
+                })
+                .collect(),
+            timeout: parse_timeout(cfg, "timeout", default.timeout)?,
+            probe_timeout: parse_timeout(cfg, "probe_timeout", default.probe_timeout)?,
+            download_timeout: parse_timeout(cfg, "download_timeout", default.download_timeout)?,
+            forward_context: parse_option(cfg, "forward_context", default.forward_context)?,
+            hosts: cfg
+                .get("hosts")
+                .map(|hosts| {
+                    hosts
+                        .split(',')
+                        .map(|host| host.trim().to_lowercase())
+                        .filter(|host| !host.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            probe: parse_option(cfg, "probe", default.probe)?,
+        })
+    }
+
+    /// Format selection of the host of `term`, subdomains fall back to their parent domain
+    pub fn format_for(&self, term: &str) -> Option<&String> {
+        let host = Url::parse(term)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase));
+        if let Some(host) = host {
+            let mut domain = host.as_str();
+            loop {
+                if let Some(format) = self.host_formats.get(domain) {
+                    return Some(format);
+                }
+                match domain.split_once('.') {
+                    Some((_, parent)) if parent.contains('.') => domain = parent,
+                    _ => break,
+                }
+            }
+        }
+        self.format.as_ref()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct YtDlpPlugin {
+    pub name: String,
+    pub extra_config: ExtraConfig,
+}
+
+impl YtDlpPlugin {
+    pub fn new() -> Self {
+        let name = String::from("yt-dlp");
+        let extra_config = GLOBAL_CONFIG
+            .read()
+            .unwrap()
+            .request
+            .get(&name)
+            .and_then(|req| req.extra_config.as_ref())
+            .map(|cfg| {
+                ExtraConfig::from_map(cfg).unwrap_or_else(|e| {
+                    tracing::warn!("{name}: {e}, using defaults");
+                    ExtraConfig::default()
+                })
+            })
+            .unwrap_or_default();
+
+        Self { extra_config, name }
+    }
+
+    /// Context options, format selection then `argv`, which can still override both
+    fn command(&self, term: &str) -> anyhow::Result<(Command, ContextArgs)> {
+        let context = self.context_args(term)?;
+        let mut command = Command::new(&self.extra_config.bin);
+        command.args(&context.args);
+        if let Some(format) = self.extra_config.format_for(term) {
+            command.arg("--format").arg(format);
+        }
+        command.args(&self.extra_config.argv);
+        Ok((command, context))
+    }
+
+    fn context_args(&self, term: &str) -> anyhow::Result<ContextArgs> {
+        if !self.extra_config.forward_context {
+            return Ok(ContextArgs::default());
+        }
+        let (context, proxy) = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            (
+                config.gen_fetch_context_for(Some(&self.name)),
+                config.get_proxy(&self.name),
+            )
+        };
+        ContextArgs::new(&context, proxy.as_deref(), &[term], HeaderArgs::AddHeader)
+    }
+}
+
+impl MXPlugin for YtDlpPlugin {
+    async fn init(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn destroy(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_book(&self, term: String, _reporter: PluginReporter) -> anyhow::Result<Book> {
+        let (mut command, _context) = self.command(&term)?;
+        command.arg("--dump-single-json").arg("--").arg(&term);
+
+        let mut process = SubProcess::spawn(&self.name, &mut command, None)?;
+        let stdout = with_timeout(self.extra_config.timeout, process.read_to_end()).await?;
+        let invocation = process.invocation().to_string();
+        process.finish().await?;
+
+        let info: VideoInfo = serde_json::from_str(&stdout)
+            .map_err(|e| anyhow::anyhow!("Parse result of '{invocation}': {e}"))?;
+
+        Ok(generate_book(&term, &info))
+    }
+
+    fn can_download_chapter(&self) -> bool {
+        true
+    }
+
+    /// Let yt-dlp download the format of every page, saved under the filename of the page
+    /// * formats of a merged selection are separate pages, they are not merged
+    async fn download_chapter(
+        &self,
+        chapter: &Chapter,
+        dest: &Path,
+        events: DownloadEventSender,
+    ) -> anyhow::Result<()> {
+        for page in &chapter.pages {
+            let path = dest.join(&page.filename);
+            if path.exists() {
+                let filename = page.filename.clone();
+                let _ = events.send(DownloadEvent::Progress { filename });
+                continue;
+            }
+
+            let (mut command, _context) = self.command(&chapter.url)?;
+            // See `generate_page`, the format is stored along with the page
+            let format_id = page
+                .metadata
+                .iter()
+                .find_map(|meta| meta.content.get("format_id")?.as_str());
+            if let Some(format_id) = format_id {
+                command.arg("--format").arg(format_id);
+            }
+            // A literal path, not an output template
+            let output = path.to_string_lossy().replace('%', "%%");
+            command
+                .arg("--output")
+                .arg(output)
+                .arg("--no-part")
+                .arg("--no-progress")
+                .arg("--")
+                .arg(&chapter.url);
+
+            let process = SubProcess::spawn(&self.name, &mut command, Some(events.clone()))?;
+            with_timeout(self.extra_config.download_timeout, process.finish()).await?;
+            let filename = page.filename.clone();
+            let _ = events.send(DownloadEvent::Progress { filename });
+        }
+        Ok(())
+    }
+
+    async fn search(&self, _term: String, _option: SearchOption) -> anyhow::Result<Vec<Book>> {
+        unimplemented!()
+    }
+
+    /// Urls handled by an extractor other than the generic one
+    /// * known hosts are claimed right away, yt-dlp is only probed for the others
+    async fn is_supported(&self, term: String) -> anyhow::Result<bool> {
+        let Some(host) = Url::parse(&term)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+        else {
+            return Ok(false);
+        };
+        let known = KNOWN_HOSTS
+            .iter()
+            .copied()
+            .chain(self.extra_config.hosts.iter().map(String::as_str))
+            .any(|known| host == known || host.ends_with(&format!(".{known}")));
+        if known || !self.extra_config.probe {
+            return Ok(known);
+        }
+
+        let mut command = Command::new(&self.extra_config.bin);
+        command
+            .arg("--simulate")
+            .arg("--flat-playlist")
+            .arg("--use-extractors")
+            .arg("default,-generic")
+            .arg("--print")
+            .arg("extractor_key")
+            .arg("--")
+            .arg(&term);
+
+        let process = SubProcess::spawn(&self.name, &mut command, None)?;
+        with_timeout(self.extra_config.probe_timeout, process.succeeded()).await
+    }
+
+    async fn version(&self) -> Option<String> {
+        binary_version(
+            &self.name,
+            &self.extra_config.bin,
+            self.extra_config.probe_timeout,
+        )
+        .await
+    }
+
+    async fn download_url(&self, dest: &Path, url: &Url) -> Option<anyhow::Result<()>> {
+        let body = async {
+            let (mut command, _context) = self.command(url.as_str())?;
+            // dest is a literal path, not an output template
+            let output = dest.to_string_lossy().replace('%', "%%");
+            command
+                .arg("--output")
+                .arg(output)
+                .arg("--no-part")
+                .arg("--no-progress")
+                .arg("--")
+                .arg(url.as_str());
+
+            let process = SubProcess::spawn(&self.name, &mut command, None)?;
+            with_timeout(self.extra_config.download_timeout, process.finish()).await
+        };
+        Some(body.await)
+    }
+}
+
+/// One chapter per video, pages are the formats picked by the format selection
+/// * video/audio only formats of a merged selection are separate pages
+pub fn generate_book(term: &str, info: &VideoInfo) -> Book {
+    let title = info
+        .title
+        .clone()
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| utils::resume_text(term, Some(20)).trim().to_string());
+    let source_id = utils::set_if_empty(
+        info.id.clone(),
+        utils::compute_query_signature(term, "yt-dlp"),
+    );
+
+    let videos = info.videos();
+    let mut authors = vec![];
+    let mut tags = vec![];
+    for video in std::iter::once(info).chain(videos.iter().copied()) {
+        for name in video.authors() {
+            if !authors.contains(&name) {
+                authors.push(name);
+            }
+        }
+        for name in video.tags.iter().chain(&video.categories) {
+            if !name.is_empty() && !tags.contains(name) {
+                tags.push(name.clone());
+            }
+        }
+    }
+
+    let chapters = videos
+        .iter()
+        .enumerate()
+        .map(|(i, video)| generate_chapter(term, i as u32 + 1, video))
+        .collect();
+
+    Book {
+        title,
+        url: info.webpage_url.clone().unwrap_or(term.to_string()),
+        source_id,
+        description: info.description.clone().unwrap_or_default(),
+        authors: authors
+            .into_iter()
+            .map(|name| Author {
+                name,
+                ..Default::default()
+            })
+            .collect(),
+        tags: tags
+            .into_iter()
+            .map(|name| Tag {
+                name,
+                metadata: vec![],
+            })
+            .collect(),
+        metadata: video_metadata(info),
+        chapters,
+        ..Default::default()
+    }
+}
+
+fn generate_chapter(term: &str, number: u32, video: &VideoInfo) -> Chapter {
+    let title = video
+        .title
+        .clone()
+        .unwrap_or_else(|| format!("Video {number}"));
+    let formats = video.selected_formats();
+    let merged = formats.len() > 1;
+    let pages = formats
+        .into_iter()
+        .filter(|format| format.url.is_some())
+        .enumerate()
+        .map(|(p, format)| generate_page(p as u32 + 1, &title, &video.id, format, merged))
+        .collect();
+
+    Chapter {
+        title,
+        description: video.description.clone().unwrap_or_default(),
+        url: video.webpage_url.clone().unwrap_or(term.to_string()),
+        number,
+        pages,
+        metadata: video_metadata(video),
+        ..Default::default()
+    }
+}
+
+fn generate_page(number: u32, title: &str, id: &str, format: &Format, merged: bool) -> Page {
+    let ext = format.ext.clone().unwrap_or("mp4".to_string());
+    let name = match (merged, &format.format_id) {
+        (true, Some(format_id)) => format!("{title} [{id}].f{format_id}"),
+        _ => format!("{title} [{id}]"),
+    };
+    let filename = format!(
+        "{}.{ext}",
+        utils::sanitize_string_as_path(&name, None).to_string_lossy()
+    );
+
+    let fetch_context = format.http_headers.as_ref().map(|headers| {
+        let mut headers = headers.clone();
+        let user_agent = headers.remove("User-Agent");
+        FetchContext {
+            user_agent,
+            headers,
+            ..Default::default()
+        }
+    });
+
+    Page {
+        title: format
+            .format_note
+            .clone()
+            .or(format.resolution.clone())
+            .unwrap_or_default(),
+        url: format.url.clone().unwrap_or_default(),
+        fetch_context,
+        number,
+        metadata: vec![Metadata {
+            label: filename.clone(),
+            content: serde_json::to_value(format).unwrap_or(Value::Null),
+        }],
+        filename,
+        ..Default::default()
+    }
+}
+
+/// Upload date, duration and extractor, when known
+fn video_metadata(video: &VideoInfo) -> Vec<Metadata> {
+    let mut metadata = vec![];
+    if let Some(date) = &video.upload_date {
+        metadata.push(Metadata {
+            label: "upload_date".to_string(),
+            content: Value::String(date.clone()),
+        });
+    }
+    if let Some(duration) = video.duration {
+        metadata.push(Metadata {
+            label: "duration".to_string(),
+            content: duration.into(),
+        });
+    }
+    if let Some(extractor) = &video.extractor {
+        metadata.push(Metadata {
+            label: "extractor".to_string(),
+            content: Value::String(extractor.clone()),
+        });
+    }
+    metadata
+}
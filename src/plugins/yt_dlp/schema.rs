@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::default_on_null;
+
+/// Output of `yt-dlp --dump-single-json`, a single video or a playlist
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct VideoInfo {
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default, rename = "_type")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub webpage_url: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub creators: Vec<String>,
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub upload_date: Option<String>,
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub extractor: Option<String>,
+    /// Playlist items, unavailable ones are null
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub entries: Vec<Option<VideoInfo>>,
+    /// Every available format, worst to best
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub formats: Vec<Format>,
+    /// Formats merged together by the format selection (e.g. `bv*+ba`)
+    #[serde(default, deserialize_with = "default_on_null")]
+    pub requested_formats: Vec<Format>,
+    /// Format picked by the format selection when nothing has to be merged
+    #[serde(flatten)]
+    pub selected: Format,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Format {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_id: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ext: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vcodec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acodec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tbr: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesize: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesize_approx: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub http_headers: Option<HashMap<String, String>>,
+}
+
+impl VideoInfo {
+    /// Videos of a playlist, or the video itself
+    pub fn videos(&self) -> Vec<&VideoInfo> {
+        match self.kind.as_deref() {
+            Some("playlist") | Some("multi_video") => self.entries.iter().flatten().collect(),
+            _ => vec![self],
+        }
+    }
+
+    /// Formats that would be downloaded
+    pub fn selected_formats(&self) -> Vec<&Format> {
+        if !self.requested_formats.is_empty() {
+            return self.requested_formats.iter().collect();
+        }
+        if self.selected.url.is_some() {
+            return vec![&self.selected];
+        }
+        self.formats.last().into_iter().collect()
+    }
+
+    pub fn authors(&self) -> Vec<String> {
+        let mut authors = vec![];
+        for name in self
+            .creators
+            .iter()
+            .chain(self.uploader.as_ref())
+            .chain(self.channel.as_ref())
+        {
+            if !name.is_empty() && !authors.contains(name) {
+                authors.push(name.clone());
+            }
+        }
+        authors
+    }
+}
@@ -1,7 +1,13 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::Context;
 use async_graphql::SimpleObject;
+use regex::Regex;
+use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -9,7 +15,9 @@ use crate::core::http::FetchContext;
 use crate::{core::utils, GLOBAL_CONFIG};
 
 use super::config::DownloadFolder;
-use super::default_on_null;
+use super::selection::Selection;
+use super::template::TemplateContext;
+use super::{default_on_null, liftvec_on_singleton};
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct Book {
@@ -35,19 +43,51 @@ pub struct Chapter {
     pub pages: Vec<Page>,
     #[serde(default, deserialize_with = "default_on_null")]
     pub metadata: Vec<Metadata>,
+    /// Pages are resolved when the chapter is downloaded, `pages` is empty until then
+    #[graphql(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lazy: Option<LazyChapter>,
+}
+
+/// How the pages of a lazy chapter are resolved
+/// * `"plugin"`: `mx_get_chapter` or its equivalent for builtin plugins
+/// * `{"hint": {..}}`: every url matched by the hint on the page of the chapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LazyChapter {
+    Plugin,
+    Hint(ParseLinkHint),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+/// One hop from a page to the url it points to (e.g. the redirect page of an image host)
+/// * `json_pointer` reads the response as JSON (e.g. `/data/image/url`), `selector` as HTML
+/// * `attribute` of the element is read if set, its text otherwise
+/// * `regex` keeps the `value` group, the first group or the whole match,
+///   it applies to the whole response if neither `selector` nor `json_pointer` is set
+/// * the first element yielding a value wins, relative urls are resolved against the fetched one
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SimpleObject)]
 pub struct ParseLinkHint {
-    pub selector: String,
-    pub attribute: String,
+    #[serde(default)]
+    pub selector: Option<String>,
+    #[serde(default)]
+    pub attribute: Option<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub json_pointer: Option<String>,
+    /// `selector` and `regex` compiled on first use, clones of the hint keep them
+    #[serde(skip)]
+    #[graphql(skip)]
+    pub(crate) compiled: OnceLock<(Option<Selector>, Option<Regex>)>,
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct Page {
     pub title: String,
     pub url: String,
-    pub intermediate_link_hint: Option<ParseLinkHint>,
+    /// Hops to follow before reaching the actual url, a single hint is also accepted
+    #[serde(default, deserialize_with = "liftvec_on_singleton")]
+    pub intermediate_link_hint: Vec<ParseLinkHint>,
     #[graphql(skip)]
     pub fetch_context: Option<FetchContext>,
     pub number: u32,
@@ -95,10 +135,35 @@ pub struct RawUrls {
     pub tags: Vec<String>,
 }
 
+/// A piece of a `Book` yielded by a plugin while it is still being extracted
+/// * `{"book": {..}}` header (title, authors, tags, ..), may already contain chapters
+/// * `{"chapter": {..}}` appended to the book, may already contain pages
+/// * `{"page": {..}}` appended to the last chapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookChunk {
+    Book(Book),
+    Chapter(Chapter),
+    Page(Page),
+}
+
+/// Rebuild a `Book` from a sequence of `BookChunk`
+#[derive(Debug)]
+pub struct BookAssembler {
+    term: String,
+    book: Option<Book>,
+}
+
 #[derive(Serialize, Clone, Deserialize, Debug)]
 pub struct CacheFile {
     pub engine: String,
     pub date: String,
+    /// Set when only part of the book was saved, `book` lists what was selected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Selection>,
+    /// Plugin that fetched the book, older files only have it as the first folder of their path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
     pub book: Book,
 }
 
@@ -135,6 +200,228 @@ impl Chapter {
     }
 }
 
+impl Page {
+    /// Page named after its number, the extension of the url is kept
+    pub fn from_url(number: u32, url: &Url) -> Page {
+        let ext = utils::extract_filename(url)
+            .and_then(|name| Some(Path::new(&name).extension()?.to_string_lossy().to_string()))
+            .unwrap_or("jpg".to_string());
+        Page {
+            url: url.to_string(),
+            number,
+            filename: format!("{number:03}.{ext}"),
+            ..Default::default()
+        }
+    }
+}
+
+impl ParseLinkHint {
+    /// Url found in `body`, the response of `base`
+    pub fn evaluate(&self, body: &str, base: &Url) -> anyhow::Result<Url> {
+        let found = self
+            .values(body, base)?
+            .into_iter()
+            .next()
+            .with_context(|| format!("Could not evaluate {self:?} at {base}"))?;
+        resolve_link(&found, base)
+    }
+
+    /// Every url found in `body`, in document order
+    pub fn evaluate_all(&self, body: &str, base: &Url) -> anyhow::Result<Vec<Url>> {
+        self.values(body, base)?
+            .iter()
+            .map(|found| resolve_link(found, base))
+            .collect()
+    }
+
+    /// Checked when a declarative site is loaded, otherwise on first use
+    pub fn compile(&self) -> anyhow::Result<&(Option<Selector>, Option<Regex>)> {
+        if let Some(compiled) = self.compiled.get() {
+            return Ok(compiled);
+        }
+        let selector = self
+            .selector
+            .as_deref()
+            .map(|selector| {
+                Selector::parse(selector)
+                    .map_err(|e| anyhow::anyhow!("Bad selector {selector:?}: {e}"))
+            })
+            .transpose()?;
+        let regex = self.regex.as_deref().map(Regex::new).transpose()?;
+        Ok(self.compiled.get_or_init(|| (selector, regex)))
+    }
+
+    fn values(&self, body: &str, base: &Url) -> anyhow::Result<Vec<String>> {
+        let (selector, regex) = self.compile()?;
+        let apply_regex = |value: &str| -> Option<String> {
+            let value = value.trim();
+            let value = match regex {
+                Some(re) => {
+                    let caps = re.captures(value)?;
+                    caps.name("value")
+                        .or_else(|| caps.get(1))
+                        .or_else(|| caps.get(0))?
+                        .as_str()
+                        .trim()
+                }
+                None => value,
+            };
+            (!value.is_empty()).then(|| value.to_string())
+        };
+
+        let values = if let Some(pointer) = &self.json_pointer {
+            let json: serde_json::Value = serde_json::from_str(body)
+                .with_context(|| format!("Parsing the response of {base} as JSON"))?;
+            let as_string = |value: &serde_json::Value| match value {
+                serde_json::Value::String(s) => apply_regex(s),
+                serde_json::Value::Null => None,
+                value => apply_regex(&value.to_string()),
+            };
+            match json.pointer(pointer) {
+                Some(serde_json::Value::Array(values)) => {
+                    values.iter().filter_map(as_string).collect()
+                }
+                Some(value) => as_string(value).into_iter().collect(),
+                None => vec![],
+            }
+        } else if let Some(selector) = selector {
+            let document = Html::parse_document(body);
+            let values = document
+                .select(selector)
+                .filter_map(|element| {
+                    let value = match &self.attribute {
+                        Some(attribute) => element.attr(attribute)?.to_string(),
+                        None => element.text().collect::<String>(),
+                    };
+                    apply_regex(&value)
+                })
+                .collect();
+            values
+        } else {
+            match regex {
+                Some(re) => re
+                    .captures_iter(body)
+                    .filter_map(|caps| apply_regex(caps.get(0)?.as_str()))
+                    .collect(),
+                None => apply_regex(body).into_iter().collect(),
+            }
+        };
+
+        Ok(values)
+    }
+}
+
+/// Urls embedded in HTML attributes or scripts are often escaped
+fn resolve_link(found: &str, base: &Url) -> anyhow::Result<Url> {
+    let found = found.replace("&amp;", "&").replace("\\/", "/");
+    base.join(&found)
+        .with_context(|| format!("Resolving {found:?} against {base}"))
+}
+
+impl BookAssembler {
+    pub fn new(term: String) -> Self {
+        Self { term, book: None }
+    }
+
+    /// Merge a chunk, returns the newly added pages along with their chapter index
+    pub fn push(&mut self, chunk: BookChunk) -> Vec<(usize, Page)> {
+        let mut added = vec![];
+        match chunk {
+            BookChunk::Book(header) => {
+                let Book { chapters, .. } = header.clone();
+                match &mut self.book {
+                    Some(book) => {
+                        let known = std::mem::take(&mut book.chapters);
+                        *book = Book {
+                            chapters: known,
+                            ..header
+                        };
+                    }
+                    None => {
+                        self.book = Some(Book {
+                            chapters: vec![],
+                            ..header
+                        })
+                    }
+                }
+                for chapter in chapters {
+                    added.extend(self.push(BookChunk::Chapter(chapter)));
+                }
+            }
+            BookChunk::Chapter(chapter) => {
+                let book = self.get_or_init();
+                let pages = chapter.pages.clone();
+                book.chapters.push(Chapter {
+                    number: match chapter.number {
+                        0 => book.chapters.len() as u32 + 1,
+                        n => n,
+                    },
+                    pages: vec![],
+                    ..chapter
+                });
+                for page in pages {
+                    added.extend(self.push(BookChunk::Page(page)));
+                }
+            }
+            BookChunk::Page(page) => {
+                let book = self.get_or_init();
+                if book.chapters.is_empty() {
+                    book.chapters.push(Chapter {
+                        title: book.title.clone(),
+                        url: book.url.clone(),
+                        number: 1,
+                        ..Default::default()
+                    });
+                }
+
+                let c = book.chapters.len() - 1;
+                let chapter = &mut book.chapters[c];
+                let number = match page.number {
+                    0 => chapter.pages.len() as u32 + 1,
+                    n => n,
+                };
+                let filename = match page.filename.is_empty() {
+                    true => Url::parse(&page.url)
+                        .ok()
+                        .and_then(|url| utils::extract_filename(&url))
+                        .filter(|name| !name.is_empty())
+                        .unwrap_or_else(|| number.to_string()),
+                    false => page.filename.clone(),
+                };
+                let page = Page {
+                    number,
+                    filename,
+                    ..page
+                };
+
+                chapter.pages.push(page.clone());
+                added.push((c, page));
+            }
+        }
+
+        added
+    }
+
+    pub fn book(&mut self) -> &Book {
+        self.get_or_init()
+    }
+
+    pub fn finish(mut self) -> Book {
+        self.get_or_init();
+        self.book.unwrap()
+    }
+
+    fn get_or_init(&mut self) -> &mut Book {
+        let term = self.term.clone();
+        self.book.get_or_insert_with(|| Book {
+            title: utils::resume_text(&term, Some(40)),
+            source_id: term.clone(),
+            url: term,
+            ..Default::default()
+        })
+    }
+}
+
 impl Book {
     pub fn from_raw_urls(raw: RawUrls) -> anyhow::Result<Book> {
         let RawUrls {
@@ -166,56 +453,124 @@ impl Book {
     }
 
     pub fn get_download_folders(&self, term: &str, plugin_name: &str) -> DownloadFolder {
-        let (download, temp, metadata) = {
+        let (download, temp, metadata, template) = {
             let config = GLOBAL_CONFIG.read().unwrap();
             (
                 config.download_folder.download.clone(),
                 config.download_folder.temp.clone(),
                 config.download_folder.metadata.clone(),
+                config.paths.book.clone(),
             )
         };
         let id = utils::compute_query_signature(term, plugin_name)[..10].to_string();
-        let chunk_title_path =
-            utils::sanitize_string_as_path(&self.get_sanitized_title(), Some(id));
+        let book_path = match template {
+            Some(template) => template.render(&TemplateContext {
+                plugin: plugin_name,
+                id: Some(&id),
+                book: self,
+                chapter: None,
+                page: None,
+            }),
+            None => PathBuf::from(plugin_name).join(utils::sanitize_string_as_path(
+                &self.get_sanitized_title(),
+                Some(id),
+            )),
+        };
 
         DownloadFolder {
-            download: download.join(plugin_name).join(&chunk_title_path),
-            temp: temp.join(plugin_name).join(&chunk_title_path),
-            metadata: metadata.join(plugin_name),
+            download: download.join(&book_path),
+            temp: temp.join(&book_path),
+            metadata: metadata.join(&book_path),
         }
     }
 
     /// Metadata temporary path
     pub fn get_metadata_path(&self, term: &str, plugin_name: &str) -> PathBuf {
-        let temp = {
-            let config = GLOBAL_CONFIG.read().unwrap();
-            config.download_folder.temp.clone()
-        };
-
-        let id = utils::compute_query_signature(term, plugin_name)[..10].to_string();
-        let chunk_title_path =
-            utils::sanitize_string_as_path(&self.get_sanitized_title(), Some(id));
-
-        temp.join(plugin_name)
-            .join(chunk_title_path)
-            .join(format!("{}.json", utils::sanitize_string(&self.source_id)))
+        self.get_download_folders(term, plugin_name)
+            .temp
+            .join(self.get_metadata_filename(plugin_name))
     }
 
     /// Metadata download path
     pub fn get_metadata_dest_path(&self, term: &str, plugin_name: &str) -> PathBuf {
-        let metadata = {
-            let config = GLOBAL_CONFIG.read().unwrap();
-            config.download_folder.metadata.clone()
-        };
+        self.get_download_folders(term, plugin_name)
+            .metadata
+            .join(self.get_metadata_filename(plugin_name))
+    }
 
-        let id = utils::compute_query_signature(term, plugin_name)[..10].to_string();
-        let chunk_title_path =
-            utils::sanitize_string_as_path(&self.get_sanitized_title(), Some(id));
+    /// Metadata path once the book is moved to the download folder
+    pub fn get_metadata_download_path(&self, term: &str, plugin_name: &str) -> PathBuf {
+        self.get_download_folders(term, plugin_name)
+            .download
+            .join(self.get_metadata_filename(plugin_name))
+    }
+
+    fn get_metadata_filename(&self, plugin_name: &str) -> PathBuf {
+        let template = { GLOBAL_CONFIG.read().unwrap().paths.metadata.clone() };
+        match template {
+            Some(template) => template.render(&TemplateContext {
+                plugin: plugin_name,
+                id: None,
+                book: self,
+                chapter: None,
+                page: None,
+            }),
+            None => PathBuf::from(format!("{}.json", utils::sanitize_string(&self.source_id))),
+        }
+    }
+
+    /// Folder of a chapter relative to the folder of the book
+    pub fn get_chapter_folder(&self, plugin_name: &str, chapter: &Chapter) -> PathBuf {
+        let template = { GLOBAL_CONFIG.read().unwrap().paths.chapter.clone() };
+        match template {
+            Some(template) => template.render(&TemplateContext {
+                plugin: plugin_name,
+                id: None,
+                book: self,
+                chapter: Some(chapter),
+                page: None,
+            }),
+            None => utils::sanitize_string_as_path(&chapter.title, None),
+        }
+    }
 
-        metadata
-            .join(plugin_name)
-            .join(chunk_title_path)
-            .join(format!("{}.json", utils::sanitize_string(&self.source_id)))
+    /// Rename the pages of every chapter after the page template, if any
+    pub fn apply_page_template(&mut self, plugin_name: &str) {
+        let mut chapters = std::mem::take(&mut self.chapters);
+        for chapter in &mut chapters {
+            self.apply_page_template_to(plugin_name, chapter);
+        }
+        self.chapters = chapters;
+    }
+
+    /// Rename the pages of a chapter of the book (e.g. once resolved) after the page template
+    pub fn apply_page_template_to(&self, plugin_name: &str, chapter: &mut Chapter) {
+        let filenames = chapter
+            .pages
+            .iter()
+            .map(|page| self.get_page_filename(plugin_name, chapter, page))
+            .collect::<Vec<_>>();
+        for (page, filename) in chapter.pages.iter_mut().zip(filenames) {
+            page.filename = filename;
+        }
+    }
+
+    /// Filename of a page after the page template, the one of the plugin if there is none
+    pub fn get_page_filename(&self, plugin_name: &str, chapter: &Chapter, page: &Page) -> String {
+        let template = { GLOBAL_CONFIG.read().unwrap().paths.page.clone() };
+        match template {
+            Some(template) => template
+                .render(&TemplateContext {
+                    plugin: plugin_name,
+                    id: None,
+                    book: self,
+                    chapter: Some(chapter),
+                    page: Some(page),
+                })
+                .to_string_lossy()
+                .to_string(),
+            None => page.filename.clone(),
+        }
     }
 
     pub fn resume(&self) -> String {
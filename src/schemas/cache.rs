@@ -0,0 +1,72 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::{book::Book, rfc3339};
+
+/// A fetched book stamped with when and by which version of the plugin it was fetched
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub term: String,
+    pub plugin: String,
+    /// Unknown for plugins that do not expose one
+    #[serde(default)]
+    pub plugin_version: Option<String>,
+    #[serde(with = "rfc3339")]
+    pub fetched_at: DateTime<Local>,
+    pub book: Book,
+}
+
+/// Whether a cache entry can still be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    Fresh,
+    /// Older than the TTL of its plugin
+    Expired,
+    /// Fetched by another version of its plugin
+    Outdated,
+}
+
+impl CacheEntry {
+    pub fn new(term: &str, plugin: &str, plugin_version: Option<String>, book: Book) -> Self {
+        Self {
+            term: term.to_string(),
+            plugin: plugin.to_string(),
+            plugin_version,
+            fetched_at: Local::now(),
+            book,
+        }
+    }
+
+    pub fn age(&self, now: DateTime<Local>) -> Duration {
+        (now - self.fetched_at).to_std().unwrap_or(Duration::ZERO)
+    }
+
+    /// Entries are outdated only when both versions are known
+    pub fn freshness(
+        &self,
+        ttl: Option<Duration>,
+        plugin_version: Option<&str>,
+        now: DateTime<Local>,
+    ) -> Freshness {
+        match (&self.plugin_version, plugin_version) {
+            (Some(stamped), Some(current)) if stamped != current => return Freshness::Outdated,
+            _ => {}
+        }
+        match ttl {
+            Some(ttl) if self.age(now) >= ttl => Freshness::Expired,
+            _ => Freshness::Fresh,
+        }
+    }
+}
+
+impl Display for Freshness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Freshness::Fresh => write!(f, "fresh"),
+            Freshness::Expired => write!(f, "expired"),
+            Freshness::Outdated => write!(f, "outdated"),
+        }
+    }
+}
@@ -1,24 +1,37 @@
 use crate::{
     cli::fetch::SharedFetchOption,
     core::{
+        cache::{files::FileCacheStorage, sqlite::SqliteCacheStorage, CacheStorage},
         http::{
-            basic::BasicRequestResolver, cf_worker::CloudflareWorkerResolver,
+            basic::BasicRequestResolver, cache::HttpCache, cf_worker::CloudflareWorkerResolver,
             flaresolverr::FlareSolverrResolver, FetchContext, MxScraperHttpClient,
+            MxScraperHttpResolver,
         },
-        utils,
+        postprocess, utils,
+    },
+    schemas::{
+        cookies::NetscapeCookie,
+        selection::Selection,
+        template::{PathTemplate, TemplateScope},
+        watch::Interval,
     },
-    schemas::cookies::NetscapeCookie,
 };
 use anyhow::Context;
 use base64::{prelude::BASE64_STANDARD, Engine};
 use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::path::PathBuf;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
+use std::{path::PathBuf, time::Duration};
+use url::Url;
 
 lazy_static! {
     static ref ALL: String = String::from("_all");
+    static ref WHOLE_TERM: Regex = Regex::new("^(?s:.*)$").unwrap();
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -26,6 +39,8 @@ pub struct Config {
     pub version: String,
     pub plugins: PluginOptions,
     pub download_folder: DownloadFolder,
+    #[serde(default)]
+    pub paths: PathTemplates,
     pub cache: Cache,
     pub delay: Delay,
     pub max_size_batch: usize,
@@ -34,8 +49,25 @@ pub struct Config {
     pub max_parallel_fetch: usize,
     pub verbose: bool,
     pub custom_downloader: bool,
+    /// Rename pages after the type detected from their bytes or `Content-Type`
+    #[serde(default)]
+    pub fix_extensions: bool,
     pub http_client: Option<HttpClientResolverKind>,
     pub request: HashMap<String, Request>,
+    #[serde(default)]
+    pub routing: Vec<Route>,
+    #[serde(default)]
+    pub watch: WatchOptions,
+    #[serde(default)]
+    pub http_cache: HttpCacheOptions,
+    #[serde(default)]
+    pub library: LibraryOptions,
+    /// Conversion of the pages once downloaded, by plugin (`_all` applies to every plugin)
+    #[serde(default)]
+    pub postprocess: HashMap<String, PostProcess>,
+    /// Metadata files written along with the downloaded books, for other tools to import them
+    #[serde(default)]
+    pub sidecars: Vec<SidecarFormat>,
     #[serde(skip)]
     pub __options: AdditionalOptions,
     #[serde(skip)]
@@ -74,6 +106,8 @@ impl AuthKind {
 pub struct AdditionalOptions {
     focused_plugin: Option<String>,
     auth_kind: Option<AuthKind>,
+    /// Chapters and pages to download, applied after metadata retrieval
+    pub selection: Selection,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -83,10 +117,229 @@ pub struct DownloadFolder {
     pub metadata: PathBuf,
 }
 
+/// Layout of the saved books, see `schemas::template::PathTemplate` for the fields
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PathTemplates {
+    /// Folder of a book in the download, temp and metadata folders, with `{id}` or `{source_id}`,
+    /// `<plugin>/<title> (<id>)` if unset
+    pub book: Option<PathTemplate>,
+    /// Folder of a chapter in the folder of its book, its title if unset
+    pub chapter: Option<PathTemplate>,
+    /// Name of a page in the folder of its chapter, the filename given by the plugin if unset
+    pub page: Option<PathTemplate>,
+    /// Name of the metadata file in the folder of its book, `<source_id>.json` if unset
+    pub metadata: Option<PathTemplate>,
+}
+
+impl PathTemplates {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let templates = [
+            ("book", &self.book, TemplateScope::Book, false),
+            ("chapter", &self.chapter, TemplateScope::Chapter, false),
+            ("page", &self.page, TemplateScope::Page, true),
+            ("metadata", &self.metadata, TemplateScope::Book, true),
+        ];
+        for (name, template, scope, single) in templates {
+            if let Some(template) = template {
+                template
+                    .validate(scope, single)
+                    .with_context(|| format!("Invalid paths.{name} template {template}"))?;
+            }
+        }
+        // Books with the same title would be merged in the same folder
+        if let Some(book) = self.book.as_ref().filter(|book| !book.is_unique()) {
+            anyhow::bail!(
+                "Invalid paths.book template {book}, it has to use {{id}} or {{source_id}}"
+            );
+        }
+        // The library only looks for JSON files
+        if let Some(metadata) = &self.metadata {
+            if !metadata.to_string().ends_with(".json") {
+                anyhow::bail!(
+                    "Invalid paths.metadata template {metadata}, it has to end with .json"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of folders of a book folder, two for the default `<plugin>/<title> (<id>)`
+    pub fn book_depth(&self) -> usize {
+        self.book.as_ref().map_or(2, PathTemplate::depth)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchOptions {
+    /// Watchlist file, created on the first `watch add`
+    pub file: PathBuf,
+    /// Default time between two checks of a term
+    pub interval: Interval,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("./watchlist.yaml"),
+            interval: Interval::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryOptions {
+    /// Index of the saved books, written by `library index`
+    pub index: PathBuf,
+}
+
+impl Default for LibraryOptions {
+    fn default() -> Self {
+        Self {
+            index: PathBuf::from("./download/library.json"),
+        }
+    }
+}
+
+/// On-disk cache of the pages requested by plugins, see `core::http::cache`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HttpCacheOptions {
+    pub enable: bool,
+    pub folder: PathBuf,
+    /// Request headers that change the response, the method and url are always part of the key
+    pub vary: Vec<String>,
+    /// Keep every successful response regardless of `Cache-Control` and never revalidate,
+    /// for developing plugins offline
+    pub dev_mode: bool,
+}
+
+impl Default for HttpCacheOptions {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            folder: PathBuf::from("./http_cache"),
+            vary: vec![
+                "accept".to_string(),
+                "accept-language".to_string(),
+                "authorization".to_string(),
+            ],
+            dev_mode: false,
+        }
+    }
+}
+
+/// Pages converted before being moved to the download folder, see `core::postprocess`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PostProcess {
+    /// Pages keep their format if unset
+    pub format: Option<PageFormat>,
+    /// Only convert pages in these formats (e.g. `[webp]`), any still image by default,
+    /// see `postprocess::DECODED_FORMATS`
+    pub from: Vec<String>,
+    /// JPEG quality, from 1 to 100
+    pub quality: u8,
+    /// Drop the EXIF metadata, converted pages never keep it
+    pub strip_exif: bool,
+    /// Downsize pages wider or taller than this, in pixels
+    pub max_dimension: Option<u32>,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            format: None,
+            from: vec![],
+            quality: 90,
+            strip_exif: false,
+            max_dimension: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFormat {
+    #[serde(alias = "jpeg")]
+    Jpg,
+    Png,
+    /// Lossless only
+    Webp,
+}
+
+impl PageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PageFormat::Jpg => "jpg",
+            PageFormat::Png => "png",
+            PageFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Metadata files written next to the downloaded pages, see `core::sidecar`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SidecarFormat {
+    /// `ComicInfo.xml` in each chapter folder
+    #[serde(alias = "comicinfo.xml")]
+    ComicInfo,
+    /// Calibre `metadata.opf` in the book folder
+    Opf,
+    /// `<page>.txt` tags next to each page, as imported by Hydrus
+    Hydrus,
+    /// schema.org `metadata.jsonld` in the book folder
+    #[serde(alias = "json-ld")]
+    JsonLd,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Cache {
     pub enable: bool,
     pub folder: PathBuf,
+    /// Age after which a term is fetched again, entries never expire if unset
+    #[serde(default)]
+    pub ttl: Option<Interval>,
+    /// Overrides `ttl` for specific plugins, e.g. `gallery-dl: 6h`
+    #[serde(default)]
+    pub plugin_ttl: HashMap<String, Interval>,
+    #[serde(default)]
+    pub backend: CacheBackendKind,
+}
+
+/// Storage of the query cache, see `cache migrate` to move JSON files to SQLite
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase", tag = "use")]
+pub enum CacheBackendKind {
+    /// One JSON file per entry in `cache.folder`
+    #[default]
+    Files,
+    /// A single database, `<cache.folder>/cache.sqlite` by default
+    Sqlite {
+        #[serde(default)]
+        file: Option<PathBuf>,
+    },
+}
+
+impl Cache {
+    pub fn ttl_for(&self, plugin_name: &str) -> Option<Duration> {
+        self.plugin_ttl
+            .get(plugin_name)
+            .or(self.ttl.as_ref())
+            .map(|ttl| ttl.0)
+    }
+
+    /// `None` unless the SQLite backend is used
+    pub fn database_file(&self) -> Option<PathBuf> {
+        match &self.backend {
+            CacheBackendKind::Files => None,
+            CacheBackendKind::Sqlite { file } => Some(
+                file.clone()
+                    .unwrap_or_else(|| self.folder.join("cache.sqlite")),
+            ),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -108,13 +361,49 @@ pub struct Request {
     pub user_agent: Option<String>,
     pub headers: Option<HashMap<String, String>>,
     pub cookies: Option<HashMap<String, String>>,
+    /// e.g. http://127.0.0.1:8080, inherits `_all`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
     pub extra_config: Option<HashMap<String, String>>,
 }
 
+/// Send matching terms to a plugin without asking every plugin
+/// * `prefix` captures the remaining text as `$1`
+/// * `hosts` also matches subdomains
+/// * `rewrite` can refer to capture groups (`$0`, `$1`, `${name}`), the term is left as is otherwise
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Route {
+    pub plugin: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite: Option<String>,
+    /// `regex` and `prefix` compiled once by `validate`
+    #[serde(skip)]
+    pub(crate) patterns: OnceLock<Vec<Regex>>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PluginOptions {
     pub location: PathBuf,
     pub meta_only: bool,
+    /// Plugins checked first during auto-detection, in order
+    #[serde(default)]
+    pub priority: Vec<String>,
+    /// Try the next supporting plugin when fetching fails
+    #[serde(default = "enabled")]
+    pub fallthrough: bool,
+    /// Reuse `is_supported` verdicts for terms sharing the same host or prefix
+    #[serde(default = "enabled")]
+    pub cache_verdicts: bool,
+}
+
+fn enabled() -> bool {
+    true
 }
 
 impl Config {
@@ -143,15 +432,22 @@ impl Config {
             plugins: PluginOptions {
                 location: PathBuf::from("./plugins"),
                 meta_only: false,
+                priority: vec![],
+                fallthrough: true,
+                cache_verdicts: true,
             },
             download_folder: DownloadFolder {
                 download: PathBuf::from("./download/download"),
                 temp: PathBuf::from("./download/temp"),
                 metadata: PathBuf::from("./download/metadata"),
             },
+            paths: PathTemplates::default(),
             cache: Cache {
                 enable: true,
                 folder: PathBuf::from("./query_cache"),
+                ttl: None,
+                plugin_ttl: HashMap::new(),
+                backend: CacheBackendKind::Files,
             },
             delay: Delay {
                 fetch: 25,
@@ -163,11 +459,18 @@ impl Config {
             max_parallel_fetch: 999,
             verbose: false,
             request,
+            routing: vec![],
+            watch: WatchOptions::default(),
+            http_cache: HttpCacheOptions::default(),
+            library: LibraryOptions::default(),
+            postprocess: HashMap::new(),
+            sidecars: vec![],
             __options: AdditionalOptions {
                 ..Default::default()
             },
             __known_fetch_context: None,
             custom_downloader: false,
+            fix_extensions: false,
             http_client: None,
         }
     }
@@ -179,11 +482,13 @@ impl Config {
         if yaml_cfg.exists() {
             let content = std::fs::read_to_string(&yaml_cfg)
                 .with_context(|| format!("Loading {}", yaml_cfg.to_string_lossy()))?;
-            return serde_yaml::from_str(&content).map_err(|e| e.into());
+            let config: Config = serde_yaml::from_str(&content)?;
+            return config.validate().map(|_| config);
         } else if json_cfg.exists() {
             let content = std::fs::read_to_string(&json_cfg)
                 .with_context(|| format!("Loading {}", yaml_cfg.to_string_lossy()))?;
-            return serde_yaml::from_str(&content).map_err(|e| e.into());
+            let config: Config = serde_yaml::from_str(&content)?;
+            return config.validate().map(|_| config);
         }
 
         tracing::info!(
@@ -197,6 +502,45 @@ impl Config {
         Ok(default_value)
     }
 
+    /// Catch mistakes that serde cannot
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (p, route) in self.routing.iter().enumerate() {
+            route
+                .validate()
+                .with_context(|| format!("Invalid route #{} to {:?}", p + 1, route.plugin))?;
+        }
+        self.paths.validate()?;
+        for (plugin, postprocess) in &self.postprocess {
+            if !(1..=100).contains(&postprocess.quality) {
+                anyhow::bail!(
+                    "Invalid postprocess of {plugin:?}: quality {} is not between 1 and 100",
+                    postprocess.quality
+                );
+            }
+            if let Some(format) = postprocess.from.iter().find(|format| {
+                !postprocess::DECODED_FORMATS
+                    .iter()
+                    .any(|decoded| utils::same_format(decoded, format))
+            }) {
+                anyhow::bail!(
+                    "Invalid postprocess of {plugin:?}: {format:?} pages cannot be decoded, only {} are supported",
+                    postprocess::DECODED_FORMATS.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// First route matching the term along with the rewritten term
+    pub fn route(&self, term: &str) -> anyhow::Result<Option<(Route, String)>> {
+        for route in &self.routing {
+            if let Some(rewritten) = route.apply(term)? {
+                return Ok(Some((route.clone(), rewritten)));
+            }
+        }
+        Ok(None)
+    }
+
     pub fn adapt_override(&mut self, fetch_option: SharedFetchOption) -> anyhow::Result<&mut Self> {
         if fetch_option.no_cache {
             self.cache.enable = false;
@@ -210,6 +554,10 @@ impl Config {
 
         self.custom_downloader = fetch_option.custom_downloader;
 
+        if fetch_option.fix_extensions {
+            self.fix_extensions = true;
+        }
+
         if let Some(file) = fetch_option.cookies {
             let content = std::fs::read_to_string(file)?;
             let cookies = NetscapeCookie::from_json(&content)?;
@@ -243,15 +591,22 @@ impl Config {
             self.max_parallel_fetch = max_parallel_fetch;
         }
 
+        self.__options.selection = Selection {
+            chapters: fetch_option.chapters,
+            pages: fetch_option.pages,
+        };
+
         Ok(self)
     }
 
     /// Collect headers, auth and other relevant options for http queries
     pub fn gen_fetch_context(&self) -> FetchContext {
-        let AdditionalOptions {
-            focused_plugin,
-            auth_kind,
-        } = &self.__options;
+        self.gen_fetch_context_for(self.__options.focused_plugin.as_ref())
+    }
+
+    /// Same as `gen_fetch_context` but for a given plugin regardless of the focused one
+    pub fn gen_fetch_context_for(&self, focused_plugin: Option<&String>) -> FetchContext {
+        let AdditionalOptions { auth_kind, .. } = &self.__options;
 
         let shared = self.request.get(&ALL.to_string()).unwrap();
         let shared_ctx = FetchContext {
@@ -295,26 +650,115 @@ impl Config {
         fetch_context
     }
 
-    pub fn get_cache_file_path(&self, term: &str, plugin_name: &str) -> PathBuf {
-        let signature = utils::compute_query_signature(term, plugin_name);
-        self.cache.folder.join(format!("{signature}.json"))
+    /// Proxy of a plugin, inherits `_all`
+    pub fn get_proxy(&self, plugin_name: &str) -> Option<String> {
+        self.request
+            .get(plugin_name)
+            .and_then(|req| req.proxy.clone())
+            .or_else(|| self.request.get(&*ALL)?.proxy.clone())
+    }
+
+    /// Post-processing of a plugin, `_all` is used when the plugin has none
+    pub fn get_postprocess(&self, plugin_name: &str) -> Option<PostProcess> {
+        self.postprocess
+            .get(plugin_name)
+            .or_else(|| self.postprocess.get(&*ALL))
+            .cloned()
+    }
+
+    pub fn get_cache_storage(&self) -> Arc<dyn CacheStorage> {
+        match self.cache.database_file() {
+            Some(file) => Arc::new(SqliteCacheStorage::new(file)),
+            None => Arc::new(FileCacheStorage::new(self.cache.folder.clone())),
+        }
     }
 
     pub fn get_http_client(&self) -> MxScraperHttpClient {
-        if let Some(kind) = &self.http_client {
-            return match kind {
-                HttpClientResolverKind::Default => {
-                    MxScraperHttpClient::new(Arc::new(BasicRequestResolver))
-                }
-                HttpClientResolverKind::FlareSolverr { config: resolver } => {
-                    MxScraperHttpClient::new(Arc::new(resolver.clone()))
-                }
-                HttpClientResolverKind::CfWorker { config: resolver } => {
-                    MxScraperHttpClient::new(Arc::new(resolver.clone()))
-                }
-            };
+        let resolver: Arc<dyn MxScraperHttpResolver> = match &self.http_client {
+            Some(HttpClientResolverKind::FlareSolverr { config: resolver }) => {
+                Arc::new(resolver.clone())
+            }
+            Some(HttpClientResolverKind::CfWorker { config: resolver }) => {
+                Arc::new(resolver.clone())
+            }
+            Some(HttpClientResolverKind::Default) | None => Arc::new(BasicRequestResolver),
+        };
+        let cache = self
+            .http_cache
+            .enable
+            .then(|| HttpCache::new(self.http_cache.clone()));
+        MxScraperHttpClient::new(resolver).with_cache(cache)
+    }
+}
+
+impl Route {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.prefix.is_none() && self.hosts.is_empty() && self.regex.is_none() {
+            anyhow::bail!("At least one of prefix, hosts or regex is required")
+        }
+        self.compile().map(|_| ())
+    }
+
+    fn compile(&self) -> anyhow::Result<&[Regex]> {
+        if let Some(patterns) = self.patterns.get() {
+            return Ok(patterns);
+        }
+
+        let mut patterns = vec![];
+        if let Some(regex) = &self.regex {
+            patterns.push(Regex::new(regex).with_context(|| format!("Compiling {regex:?}"))?);
+        }
+        if let Some(prefix) = &self.prefix {
+            patterns.push(Regex::new(&format!(
+                "^{}(?s:(.*))$",
+                regex::escape(prefix)
+            ))?);
+        }
+        Ok(self.patterns.get_or_init(|| patterns))
+    }
+
+    /// Rewritten term if the route matches, criteria are tried in order: regex, prefix, hosts
+    pub fn apply(&self, term: &str) -> anyhow::Result<Option<String>> {
+        let mut patterns = self.compile()?.iter().collect::<Vec<_>>();
+        let host_matches = Url::parse(term)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .is_some_and(|host| {
+                self.hosts
+                    .iter()
+                    .any(|h| host == *h || host.ends_with(&format!(".{h}")))
+            });
+        if host_matches {
+            patterns.push(&WHOLE_TERM);
         }
 
-        MxScraperHttpClient::new(Arc::new(BasicRequestResolver))
+        for pattern in patterns {
+            if let Some(captures) = pattern.captures(term) {
+                return Ok(Some(match &self.rewrite {
+                    Some(template) => {
+                        let mut rewritten = String::new();
+                        captures.expand(template, &mut rewritten);
+                        rewritten
+                    }
+                    None => term.to_string(),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut criteria = vec![];
+        if let Some(regex) = &self.regex {
+            criteria.push(format!("regex {regex:?}"));
+        }
+        if let Some(prefix) = &self.prefix {
+            criteria.push(format!("prefix {prefix:?}"));
+        }
+        if !self.hosts.is_empty() {
+            criteria.push(format!("hosts {:?}", self.hosts));
+        }
+        criteria.join(", ")
     }
 }
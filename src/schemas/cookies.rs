@@ -70,6 +70,34 @@ impl NetscapeCookie {
             .collect()
     }
 
+    /// Format as a cookies.txt file, cookies without a domain are bound to `fallback_domain`
+    pub fn to_netscape_text(cookies: &[NetscapeCookie], fallback_domain: &str) -> String {
+        let mut lines = vec!["# Netscape HTTP Cookie File".to_string()];
+        for cookie in cookies {
+            let domain = match cookie.domain.as_deref() {
+                Some(domain) if !domain.is_empty() => domain.to_string(),
+                _ => format!(".{}", fallback_domain.trim_start_matches('.')),
+            };
+            let flag = |b: bool| if b { "TRUE" } else { "FALSE" };
+            lines.push(
+                [
+                    domain.clone(),
+                    flag(domain.starts_with('.')).to_string(),
+                    cookie.path.clone().unwrap_or("/".to_string()),
+                    flag(cookie.secure.unwrap_or(false)).to_string(),
+                    cookie
+                        .expiration_date
+                        .map(|date| (date as u64).to_string())
+                        .unwrap_or("0".to_string()),
+                    cookie.name.clone(),
+                    cookie.value.clone(),
+                ]
+                .join("\t"),
+            );
+        }
+        lines.join("\n") + "\n"
+    }
+
     /// Format k1=v1; k2=v2; ..
     pub fn to_raw_string(cookies: &[NetscapeCookie]) -> String {
         cookies
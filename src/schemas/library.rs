@@ -0,0 +1,178 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{rfc3339, selection::Selection};
+
+/// Saved books found in the metadata and download folders, see `library index`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryIndex {
+    #[serde(with = "rfc3339")]
+    pub indexed_at: DateTime<Local>,
+    #[serde(default)]
+    pub books: Vec<LibraryBook>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryBook {
+    pub title: String,
+    pub plugin: String,
+    pub url: String,
+    pub source_id: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Date of the metadata, as written by the engine
+    pub saved_at: String,
+    /// Metadata file the book was read from
+    pub metadata: PathBuf,
+    /// Download folder of the book, it may not exist
+    pub folder: PathBuf,
+    pub state: DownloadState,
+    pub chapters: usize,
+    pub downloaded_chapters: usize,
+    /// Pages listed in the metadata, lazy chapters are not counted
+    pub pages: usize,
+    pub downloaded_pages: usize,
+    /// Only part of the book was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Selection>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadState {
+    /// Every chapter listed in the metadata is on disk
+    Complete,
+    Partial,
+    /// Metadata only
+    Missing,
+}
+
+/// Filters of `library search`, every one of them has to match
+#[derive(Debug, Clone, Default)]
+pub struct LibraryQuery {
+    /// Case insensitive, every tag is required
+    pub tags: Vec<String>,
+    /// Case insensitive substring of one of the authors
+    pub author: Option<String>,
+    pub plugin: Option<String>,
+    pub title: Option<TitleFilter>,
+    pub state: Option<DownloadState>,
+}
+
+/// `~regex` or a case insensitive substring
+#[derive(Debug, Clone)]
+pub enum TitleFilter {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl LibraryIndex {
+    pub fn new(books: Vec<LibraryBook>) -> Self {
+        Self {
+            indexed_at: Local::now(),
+            books,
+        }
+    }
+
+    /// `None` if the library was never indexed
+    pub fn load(file: &Path) -> anyhow::Result<Option<Self>> {
+        if !file.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Reading library index {}", file.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Parsing library index {}", file.display()))
+            .map(Some)
+    }
+
+    pub fn save(&self, file: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(file, serde_json::to_string(self)?)
+            .with_context(|| format!("Writing library index {}", file.display()))
+    }
+
+    pub fn search<'a>(&'a self, query: &'a LibraryQuery) -> impl Iterator<Item = &'a LibraryBook> {
+        self.books.iter().filter(|book| query.matches(book))
+    }
+}
+
+impl LibraryQuery {
+    pub fn matches(&self, book: &LibraryBook) -> bool {
+        let tags = self.tags.iter().all(|tag| {
+            book.tags
+                .iter()
+                .any(|book_tag| book_tag.trim().eq_ignore_ascii_case(tag.trim()))
+        });
+        let author = self.author.as_ref().is_none_or(|author| {
+            let author = author.to_lowercase();
+            book.authors
+                .iter()
+                .any(|name| name.to_lowercase().contains(&author))
+        });
+        let plugin = self.plugin.as_ref().is_none_or(|p| *p == book.plugin);
+        let title = self
+            .title
+            .as_ref()
+            .is_none_or(|title| title.matches(&book.title));
+        let state = self.state.is_none_or(|state| state == book.state);
+        tags && author && plugin && title && state
+    }
+}
+
+impl TitleFilter {
+    pub fn matches(&self, title: &str) -> bool {
+        match self {
+            TitleFilter::Contains(s) => title.to_lowercase().contains(&s.to_lowercase()),
+            TitleFilter::Regex(regex) => regex.is_match(title),
+        }
+    }
+}
+
+impl FromStr for TitleFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('~') {
+            Some(pattern) => Regex::new(pattern)
+                .map(TitleFilter::Regex)
+                .map_err(|e| anyhow::anyhow!("Invalid title pattern {pattern:?}: {e}")),
+            None => Ok(TitleFilter::Contains(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for DownloadState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "complete" => Ok(Self::Complete),
+            "partial" => Ok(Self::Partial),
+            "missing" => Ok(Self::Missing),
+            other => anyhow::bail!("Invalid state {other:?}, use complete, partial or missing"),
+        }
+    }
+}
+
+impl Display for DownloadState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadState::Complete => write!(f, "complete"),
+            DownloadState::Partial => write!(f, "partial"),
+            DownloadState::Missing => write!(f, "missing"),
+        }
+    }
+}
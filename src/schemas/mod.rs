@@ -2,8 +2,13 @@ use serde::{de::DeserializeOwned, Deserialize, Deserializer};
 use serde_json::Value;
 
 pub mod book;
+pub mod cache;
 pub mod config;
 pub mod cookies;
+pub mod library;
+pub mod selection;
+pub mod template;
+pub mod watch;
 
 /// * `value => Vec<O>`
 /// * `Array[.. values] => Vec<O>`
@@ -73,3 +78,58 @@ where
     }
     serde_json::from_value(value).map_err(serde::de::Error::custom)
 }
+
+/// Dates as RFC 3339 strings
+pub mod rfc3339 {
+    use chrono::{DateTime, Local};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(date: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&date.to_rfc3339())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let date = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&date)
+            .map(|date| date.with_timezone(&Local))
+            .map_err(serde::de::Error::custom)
+    }
+
+    /// Optional dates, `null` when unset
+    pub mod option {
+        use chrono::{DateTime, Local};
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S>(
+            date: &Option<DateTime<Local>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match date {
+                Some(date) => super::serialize(date, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Local>>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Option::<String>::deserialize(deserializer)?
+                .map(|date| {
+                    DateTime::parse_from_rfc3339(&date)
+                        .map(|date| date.with_timezone(&Local))
+                        .map_err(serde::de::Error::custom)
+                })
+                .transpose()
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{collections::BTreeSet, fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::book::{Book, Chapter};
+
+/// Numbers picked out of a list, e.g. `1-10,15,latest:3`
+/// * `7`: a single number
+/// * `1-10`: both ends included, `5-` for everything from 5
+/// * `latest:3`: the 3 highest numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberSelection {
+    items: Vec<SelectionItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SelectionItem {
+    Single(u32),
+    Range(u32, Option<u32>),
+    Latest(usize),
+}
+
+/// Chapters and pages to download from a book, everything if unset
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Selection {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapters: Option<NumberSelection>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<NumberSelection>,
+}
+
+impl FromStr for NumberSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_number = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|e| anyhow::anyhow!("Invalid number {n:?} in {s:?}: {e}"))
+        };
+
+        let mut items = vec![];
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let item = if let Some(count) = item.strip_prefix("latest:") {
+                SelectionItem::Latest(parse_number(count)? as usize)
+            } else if let Some((start, end)) = item.split_once('-') {
+                let start = parse_number(start)?;
+                let end = match end.trim() {
+                    "" => None,
+                    end => Some(parse_number(end)?),
+                };
+                if end.is_some_and(|end| end < start) {
+                    anyhow::bail!("Invalid range {item:?} in {s:?}: {start} is after the end");
+                }
+                SelectionItem::Range(start, end)
+            } else {
+                SelectionItem::Single(parse_number(item)?)
+            };
+            items.push(item);
+        }
+
+        if items.is_empty() {
+            anyhow::bail!("Empty selection {s:?}, expected e.g. 1-10,15,latest:3");
+        }
+        Ok(Self { items })
+    }
+}
+
+impl Display for NumberSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let items = self
+            .items
+            .iter()
+            .map(|item| match item {
+                SelectionItem::Single(n) => n.to_string(),
+                SelectionItem::Range(start, Some(end)) => format!("{start}-{end}"),
+                SelectionItem::Range(start, None) => format!("{start}-"),
+                SelectionItem::Latest(count) => format!("latest:{count}"),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+impl Serialize for NumberSelection {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for NumberSelection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl NumberSelection {
+    /// Items whose number is selected, in their original order
+    pub fn retain<T>(&self, items: &mut Vec<T>, number: impl Fn(&T) -> u32) {
+        let numbers = items.iter().map(&number).collect::<BTreeSet<_>>();
+        let latest = self
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                SelectionItem::Latest(count) => Some(*count),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let latest = numbers.iter().rev().take(latest).collect::<BTreeSet<_>>();
+
+        items.retain(|item| {
+            let n = number(item);
+            latest.contains(&n) || self.items.iter().any(|selected| selected.contains(n))
+        });
+    }
+}
+
+impl SelectionItem {
+    fn contains(&self, n: u32) -> bool {
+        match self {
+            SelectionItem::Single(number) => *number == n,
+            SelectionItem::Range(start, end) => *start <= n && end.is_none_or(|end| n <= end),
+            SelectionItem::Latest(_) => false,
+        }
+    }
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.chapters.is_none() && self.pages.is_none()
+    }
+
+    /// Keep the selected chapters by `Chapter.number`, then their selected pages by `Page.number`
+    ///
+    /// Lazy chapters have no pages yet, see `Selection::apply_to_chapter`
+    pub fn apply(&self, book: &mut Book) {
+        if let Some(chapters) = &self.chapters {
+            chapters.retain(&mut book.chapters, |chapter| chapter.number);
+        }
+        for chapter in &mut book.chapters {
+            self.apply_to_chapter(chapter);
+        }
+    }
+
+    pub fn apply_to_chapter(&self, chapter: &mut Chapter) {
+        if let Some(pages) = &self.pages {
+            pages.retain(&mut chapter.pages, |page| page.number);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::book::{Book, Chapter, Page};
+use crate::core::utils;
+
+/// Relative path built from the fields of a book, e.g. `{plugin}/{author}/{title}`
+/// * book: `{plugin}`, `{author}` (the first one), `{title}`, `{source_id}`, `{meta.<label>}`
+///   and `{id}`, a short digest of the term and plugin
+/// * chapter: `{chapter.number}`, `{chapter.title}`, `{chapter.meta.<label>}`
+/// * page: `{page.number}`, `{page.filename}`, `{ext}` (extension of the filename)
+/// * numbers are padded with zeros by a width, e.g. `{chapter.number:03}`
+/// * each `/` of the template starts a folder, fields never do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field {
+        name: String,
+        field: Field,
+        width: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Plugin,
+    Author,
+    Title,
+    SourceId,
+    Id,
+    Meta(String),
+    ChapterNumber,
+    ChapterTitle,
+    ChapterMeta(String),
+    PageNumber,
+    PageFilename,
+    Ext,
+}
+
+/// What a template is rendered for, templates only refer to the fields in their scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TemplateScope {
+    Book,
+    Chapter,
+    Page,
+}
+
+pub struct TemplateContext<'a> {
+    pub plugin: &'a str,
+    /// Only known for book templates
+    pub id: Option<&'a str>,
+    pub book: &'a Book,
+    pub chapter: Option<&'a Chapter>,
+    pub page: Option<&'a Page>,
+}
+
+/// Titles are shortened like the default book folders
+const MAX_TITLE_LEN: usize = 70;
+
+impl FromStr for PathTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let Some(end) = rest[start..].find('}') else {
+                anyhow::bail!("Unclosed field in template {s:?}");
+            };
+            parts.push(parse_field(&rest[start + 1..start + end])?);
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        let literals = parts.iter().filter_map(|part| match part {
+            Part::Literal(literal) => Some(literal),
+            Part::Field { .. } => None,
+        });
+        for literal in literals {
+            if literal.contains('}') {
+                anyhow::bail!("Unopened field in template {s:?}");
+            }
+            if literal.contains('\\') || literal.split('/').any(|c| c == "..") {
+                anyhow::bail!("Template {s:?} can only use `/` to separate folders");
+            }
+        }
+        if parts.is_empty() || s.starts_with('/') || s.ends_with('/') || s.contains("//") {
+            anyhow::bail!("Template {s:?} has to be a relative path");
+        }
+
+        Ok(Self {
+            source: s.to_string(),
+            parts,
+        })
+    }
+}
+
+fn parse_field(field: &str) -> anyhow::Result<Part> {
+    let (name, width) = match field.split_once(':') {
+        Some((name, width)) => {
+            let width = width
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("Invalid width {width:?} of {{{field}}}"))?;
+            (name.trim(), Some(width))
+        }
+        None => (field.trim(), None),
+    };
+
+    let parsed = match name {
+        "plugin" => Field::Plugin,
+        "author" => Field::Author,
+        "title" => Field::Title,
+        "source_id" => Field::SourceId,
+        "id" => Field::Id,
+        "chapter.number" => Field::ChapterNumber,
+        "chapter.title" => Field::ChapterTitle,
+        "page.number" => Field::PageNumber,
+        "page.filename" => Field::PageFilename,
+        "ext" => Field::Ext,
+        name => match (
+            name.strip_prefix("chapter.meta."),
+            name.strip_prefix("meta."),
+        ) {
+            (Some(label), _) if !label.is_empty() => Field::ChapterMeta(label.to_string()),
+            (None, Some(label)) if !label.is_empty() => Field::Meta(label.to_string()),
+            _ => anyhow::bail!("Unknown field {{{name}}}"),
+        },
+    };
+    if width.is_some() && !matches!(parsed, Field::ChapterNumber | Field::PageNumber) {
+        anyhow::bail!("Only numbers can be padded, {{{field}}} is not one");
+    }
+
+    Ok(Part::Field {
+        name: name.to_string(),
+        field: parsed,
+        width,
+    })
+}
+
+impl PathTemplate {
+    /// Fields are known in `scope`, `single` templates cannot contain folders
+    pub fn validate(&self, scope: TemplateScope, single: bool) -> anyhow::Result<()> {
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) if single && literal.contains('/') => {
+                    anyhow::bail!("{:?} cannot contain folders", self.source)
+                }
+                Part::Field { name, field, .. }
+                    if field.scope() > scope
+                        || (*field == Field::Id && scope != TemplateScope::Book) =>
+                {
+                    anyhow::bail!("{{{name}}} is not available in {scope:?} templates")
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `{id}` or `{source_id}` is used, books with the same title then get their own folder
+    pub fn is_unique(&self) -> bool {
+        self.parts.iter().any(|part| {
+            matches!(
+                part,
+                Part::Field {
+                    field: Field::Id | Field::SourceId,
+                    ..
+                }
+            )
+        })
+    }
+
+    /// Number of folders of the rendered path, fields never add one
+    pub fn depth(&self) -> usize {
+        let separators = self.parts.iter().map(|part| match part {
+            Part::Literal(literal) => literal.matches('/').count(),
+            Part::Field { .. } => 0,
+        });
+        separators.sum::<usize>() + 1
+    }
+
+    /// Fields are sanitized, `_` stands for the folders that end up empty
+    pub fn render(&self, context: &TemplateContext) -> PathBuf {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Field { field, width, .. } => {
+                    let value = match (field.value(context), width) {
+                        (FieldValue::Number(n), Some(width)) => format!("{n:0width$}"),
+                        (FieldValue::Number(n), None) => n.to_string(),
+                        (FieldValue::Text(text), _) => utils::sanitize_path_component(&text),
+                    };
+                    rendered.push_str(&value);
+                }
+            }
+        }
+
+        rendered
+            .split('/')
+            .map(|folder| folder.trim().trim_end_matches('.').trim())
+            .map(|folder| match folder.is_empty() {
+                true => "_",
+                false => folder,
+            })
+            .collect()
+    }
+}
+
+enum FieldValue {
+    Number(u32),
+    Text(String),
+}
+
+impl Field {
+    fn scope(&self) -> TemplateScope {
+        match self {
+            Field::Plugin
+            | Field::Author
+            | Field::Title
+            | Field::SourceId
+            | Field::Id
+            | Field::Meta(_) => TemplateScope::Book,
+            Field::ChapterNumber | Field::ChapterTitle | Field::ChapterMeta(_) => {
+                TemplateScope::Chapter
+            }
+            Field::PageNumber | Field::PageFilename | Field::Ext => TemplateScope::Page,
+        }
+    }
+
+    fn value(&self, context: &TemplateContext) -> FieldValue {
+        let text = |s: &str| FieldValue::Text(s.to_string());
+        let chapter = context.chapter;
+        let page = context.page;
+        match self {
+            Field::Plugin => text(context.plugin),
+            Field::Author => text(
+                context
+                    .book
+                    .authors
+                    .first()
+                    .map(|author| author.name.as_str())
+                    .unwrap_or_default(),
+            ),
+            Field::Title => {
+                let title = utils::sanitize_string(&context.book.title);
+                FieldValue::Text(utils::unicode_safe_shorten(
+                    title.trim(),
+                    title.len().min(MAX_TITLE_LEN),
+                ))
+            }
+            Field::SourceId => text(&context.book.source_id),
+            Field::Id => text(context.id.unwrap_or_default()),
+            Field::Meta(label) => FieldValue::Text(lookup(&context.book.metadata, label)),
+            Field::ChapterNumber => FieldValue::Number(chapter.map(|c| c.number).unwrap_or(0)),
+            Field::ChapterTitle => text(chapter.map(|c| c.title.as_str()).unwrap_or_default()),
+            Field::ChapterMeta(label) => FieldValue::Text(
+                chapter
+                    .map(|c| lookup(&c.metadata, label))
+                    .unwrap_or_default(),
+            ),
+            Field::PageNumber => FieldValue::Number(page.map(|p| p.number).unwrap_or(0)),
+            Field::PageFilename => text(page.map(|p| p.filename.as_str()).unwrap_or_default()),
+            Field::Ext => text(
+                page.and_then(|p| p.filename.rsplit_once('.'))
+                    .map(|(_, ext)| ext)
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// Content of the first metadata with this label (case insensitive), empty if there is none
+fn lookup(metadata: &[super::book::Metadata], label: &str) -> String {
+    let content = metadata
+        .iter()
+        .find(|meta| meta.label.eq_ignore_ascii_case(label))
+        .map(|meta| &meta.content);
+    match content {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        Some(other) => other.to_string(),
+    }
+}
+
+impl Display for PathTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for PathTemplate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for PathTemplate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
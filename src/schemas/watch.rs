@@ -0,0 +1,266 @@
+use std::{fmt::Display, path::Path, str::FromStr, time::Duration};
+
+use anyhow::Context;
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    book::{Book, Chapter},
+    rfc3339,
+};
+
+/// Terms checked periodically for new chapters, see `watch run`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Watchlist {
+    #[serde(default)]
+    pub entries: Vec<WatchEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchEntry {
+    pub term: String,
+    /// Plugin used to fetch the term, auto-detected if unset
+    #[serde(default)]
+    pub plugin: Option<String>,
+    pub interval: Interval,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default, with = "rfc3339::option")]
+    pub last_check: Option<DateTime<Local>>,
+    /// Error of the last check, the term is retried at the next interval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Chapters downloaded so far
+    #[serde(default)]
+    pub chapters: Vec<KnownChapter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KnownChapter {
+    pub number: u32,
+    pub url: String,
+    pub title: String,
+}
+
+/// Time between two checks, e.g. `30m`, `6h`, `1d`, `2w` or a number of seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval(pub Duration);
+
+/// About 10 years
+const MAX_INTERVAL_SECS: u64 = 520 * 7 * 24 * 60 * 60;
+
+impl Watchlist {
+    /// Empty if the file does not exist yet
+    pub fn load(file: &Path) -> anyhow::Result<Self> {
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Reading watchlist {}", file.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("Parsing watchlist {}", file.display()))
+    }
+
+    pub fn save(&self, file: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(file, serde_yaml::to_string(self)?)
+            .with_context(|| format!("Writing watchlist {}", file.display()))
+    }
+
+    pub fn find_mut(&mut self, term: &str) -> Option<&mut WatchEntry> {
+        self.entries.iter_mut().find(|entry| entry.term == term)
+    }
+
+    /// Add a term or update the plugin and interval of an existing one
+    pub fn upsert(
+        &mut self,
+        term: &str,
+        plugin: Option<String>,
+        interval: Interval,
+    ) -> &mut WatchEntry {
+        let position = match self.entries.iter().position(|entry| entry.term == term) {
+            Some(position) => position,
+            None => {
+                self.entries
+                    .push(WatchEntry::new(term.to_string(), interval));
+                self.entries.len() - 1
+            }
+        };
+        let entry = &mut self.entries[position];
+        entry.plugin = plugin.or(entry.plugin.take());
+        entry.interval = interval;
+        entry
+    }
+
+    pub fn remove(&mut self, term: &str) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|entry| entry.term != term);
+        count != self.entries.len()
+    }
+
+    /// Time left before the next entry is due, zero if one already is
+    pub fn next_due_in(&self, now: DateTime<Local>) -> Option<Duration> {
+        self.entries
+            .iter()
+            .map(|entry| match entry.next_check() {
+                Some(next_check) => (next_check - now).to_std().unwrap_or(Duration::ZERO),
+                None => Duration::ZERO,
+            })
+            .min()
+    }
+}
+
+impl WatchEntry {
+    pub fn new(term: String, interval: Interval) -> Self {
+        Self {
+            term,
+            plugin: None,
+            interval,
+            title: None,
+            last_check: None,
+            last_error: None,
+            chapters: vec![],
+        }
+    }
+
+    /// `None` if the entry was never checked, it is due right away
+    pub fn next_check(&self) -> Option<DateTime<Local>> {
+        let last_check = self.last_check?;
+        let interval = TimeDelta::from_std(self.interval.0).unwrap_or(TimeDelta::MAX);
+        Some(
+            last_check
+                .checked_add_signed(interval)
+                .unwrap_or(last_check),
+        )
+    }
+
+    pub fn is_due(&self, now: DateTime<Local>) -> bool {
+        self.next_check().is_none_or(|next_check| next_check <= now)
+    }
+
+    /// Indices of the chapters of `book` that were never downloaded
+    /// * matched by url, the number is only used when one of the urls is missing
+    ///   (sites numbering chapters by their position would hide the new ones otherwise)
+    pub fn unknown_chapters(&self, book: &Book) -> Vec<usize> {
+        book.chapters
+            .iter()
+            .enumerate()
+            .filter(|(_, chapter)| {
+                !self.chapters.iter().any(|known| {
+                    match known.url.is_empty() || chapter.url.is_empty() {
+                        true => known.number == chapter.number,
+                        false => known.url == chapter.url,
+                    }
+                })
+            })
+            .map(|(c, _)| c)
+            .collect()
+    }
+
+    /// Every chapter of `book` is now known
+    pub fn record(&mut self, book: &Book, now: DateTime<Local>) {
+        let unknown = self.unknown_chapters(book);
+        self.chapters.extend(
+            unknown
+                .into_iter()
+                .map(|c| KnownChapter::from(&book.chapters[c])),
+        );
+        self.title = Some(book.title.clone());
+        self.last_check = Some(now);
+        self.last_error = None;
+    }
+
+    pub fn record_error(&mut self, error: &anyhow::Error, now: DateTime<Local>) {
+        self.last_check = Some(now);
+        self.last_error = Some(format!("{error:#}"));
+    }
+}
+
+impl From<&Chapter> for KnownChapter {
+    fn from(chapter: &Chapter) -> Self {
+        Self {
+            number: chapter.number,
+            url: chapter.url.clone(),
+            title: chapter.title.clone(),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (count, unit) = s.split_at(split);
+        let count: u64 = count
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid interval {s:?}, expected e.g. 30m, 6h, 1d"))?;
+        let secs = match unit.trim() {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            unit => anyhow::bail!("Invalid interval unit {unit:?} in {s:?}, use s, m, h, d or w"),
+        };
+        match count.checked_mul(secs) {
+            Some(0) => anyhow::bail!("Interval {s:?} must be greater than zero"),
+            Some(secs) if secs <= MAX_INTERVAL_SECS => Ok(Self(Duration::from_secs(secs))),
+            _ => anyhow::bail!("Interval {s:?} is too long"),
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.0.as_secs();
+        let (count, unit) = [
+            (7 * 24 * 60 * 60, "w"),
+            (24 * 60 * 60, "d"),
+            (60 * 60, "h"),
+            (60, "m"),
+        ]
+        .into_iter()
+        .find(|(unit, _)| secs.is_multiple_of(*unit))
+        .map(|(unit, name)| (secs / unit, name))
+        .unwrap_or((secs, "s"));
+        write!(f, "{count}{unit}")
+    }
+}
+
+impl Default for Interval {
+    fn default() -> Self {
+        Self(Duration::from_secs(24 * 60 * 60))
+    }
+}
+
+impl Serialize for Interval {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Interval {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let s = match value {
+            serde_json::Value::Number(secs) => secs.to_string(),
+            serde_json::Value::String(s) => s,
+            other => {
+                return Err(serde::de::Error::custom(format!(
+                    "Invalid interval {other:?}, expected e.g. 30m, 6h, 1d"
+                )))
+            }
+        };
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use chrono::{Local, TimeDelta};
+
+use crate::{
+    core::{
+        cache::{
+            files::{self, FileCacheStorage},
+            sqlite::SqliteCacheStorage,
+            CacheStorage,
+        },
+        utils,
+    },
+    schemas::{
+        book::{Book, Tag},
+        cache::{CacheEntry, Freshness},
+        config::Cache,
+    },
+    tests::ScratchDir,
+};
+
+#[test]
+fn cache_entries_expire_after_the_ttl_of_their_plugin() {
+    let cache: Cache = serde_yaml::from_str(
+        r#"
+        enable: true
+        folder: ./query_cache
+        ttl: 7d
+        plugin_ttl:
+          gallery-dl: 6h
+        "#,
+    )
+    .unwrap();
+    let hours = |h: u64| Some(Duration::from_secs(h * 60 * 60));
+    assert_eq!(cache.ttl_for("gallery-dl"), hours(6));
+    assert_eq!(cache.ttl_for("nhentai"), hours(7 * 24));
+
+    let now = Local::now();
+    let mut entry = CacheEntry::new("term", "gallery-dl", None, Book::default());
+    entry.fetched_at = now - TimeDelta::hours(7);
+    assert_eq!(entry.age(now), Duration::from_secs(7 * 60 * 60));
+    assert_eq!(
+        entry.freshness(cache.ttl_for("gallery-dl"), None, now),
+        Freshness::Expired
+    );
+    assert_eq!(
+        entry.freshness(cache.ttl_for("nhentai"), None, now),
+        Freshness::Fresh
+    );
+    assert_eq!(entry.freshness(None, None, now), Freshness::Fresh);
+}
+
+#[test]
+fn cache_entries_are_outdated_by_a_new_plugin_version() {
+    let now = Local::now();
+    let entry = CacheEntry::new("term", "gallery-dl", Some("1.27.1".into()), Book::default());
+    assert_eq!(entry.freshness(None, Some("1.27.1"), now), Freshness::Fresh);
+    assert_eq!(
+        entry.freshness(None, Some("1.28.0"), now),
+        Freshness::Outdated
+    );
+    // Unknown current version
+    assert_eq!(entry.freshness(None, None, now), Freshness::Fresh);
+
+    let unversioned = CacheEntry::new("term", "gallery-dl", None, Book::default());
+    assert_eq!(
+        unversioned.freshness(None, Some("1.28.0"), now),
+        Freshness::Fresh
+    );
+}
+
+#[test]
+fn read_stamped_and_legacy_cache_files() {
+    let folder = ScratchDir::new("cache");
+    let book = Book {
+        title: "Title".to_string(),
+        ..Default::default()
+    };
+
+    let stamped = folder.join("stamped.json");
+    let entry = CacheEntry::new("term", "plugin", Some("v1".into()), book.clone());
+    files::write_entry(&stamped, &entry).unwrap();
+    let read = files::read_entry(&stamped).unwrap();
+    assert_eq!(read.term, "term");
+    assert_eq!(read.plugin_version.as_deref(), Some("v1"));
+    assert_eq!(read.fetched_at.timestamp(), entry.fetched_at.timestamp());
+
+    // Written before entries were stamped
+    let legacy = folder.join("legacy.json");
+    std::fs::write(&legacy, serde_json::to_string(&book).unwrap()).unwrap();
+    let read = files::read_entry(&legacy).unwrap();
+    assert_eq!(read.book.title, "Title");
+    assert!(read.term.is_empty() && read.plugin_version.is_none());
+    assert!(read.age(Local::now()) < Duration::from_secs(60));
+
+    std::fs::write(folder.join("broken.json"), "{").unwrap();
+    std::fs::write(folder.join("notes.txt"), "").unwrap();
+    let entries = FileCacheStorage::new(folder.path().to_path_buf())
+        .entries()
+        .unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(
+        entries.iter().filter(|(_, entry)| entry.is_err()).count(),
+        1
+    );
+}
+
+#[test]
+fn plugin_sources_digest() {
+    let folder = ScratchDir::new("digest");
+    std::fs::create_dir_all(folder.join("sub")).unwrap();
+    assert_eq!(utils::digest_folder(folder.path(), &["py"]), None);
+
+    std::fs::write(folder.join("__init__.py"), "x = 1").unwrap();
+    std::fs::write(folder.join("sub/helper.py"), "y = 2").unwrap();
+    std::fs::write(folder.join("ignored.txt"), "z").unwrap();
+    let first = utils::digest_folder(folder.path(), &["py"]).unwrap();
+    assert_eq!(first.len(), 12);
+
+    std::fs::write(folder.join("ignored.txt"), "changed").unwrap();
+    assert_eq!(utils::digest_folder(folder.path(), &["py"]).unwrap(), first);
+    std::fs::write(folder.join("sub/helper.py"), "y = 3").unwrap();
+    assert_ne!(utils::digest_folder(folder.path(), &["py"]).unwrap(), first);
+}
+
+#[test]
+fn sqlite_cache_storage_indexes_tags_and_imports_json_files() {
+    let folder = ScratchDir::new("sqlite");
+    let tagged = |title: &str, tags: &[&str]| Book {
+        title: title.to_string(),
+        tags: tags
+            .iter()
+            .map(|name| Tag {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let files = FileCacheStorage::new(folder.path().to_path_buf());
+    let entry = |title, tags| CacheEntry::new(title, "plugin", None, tagged(title, tags));
+    files
+        .put("mx_a", &entry("A", &["Action", "Drama"]))
+        .unwrap();
+    files.put("mx_b", &entry("B", &["drama"])).unwrap();
+    std::fs::write(folder.join("mx_broken.json"), "{").unwrap();
+
+    let database = SqliteCacheStorage::new(folder.join("cache.sqlite"));
+    let migration = database.migrate_from(&files, true).unwrap();
+    assert_eq!(migration.imported, 2);
+    assert_eq!(migration.skipped.len(), 1);
+    // Unreadable files are left in place
+    assert_eq!(files.entries().unwrap().len(), 1);
+
+    let titles = |entries: Vec<(String, CacheEntry)>| {
+        entries
+            .into_iter()
+            .map(|(_, entry)| entry.book.title)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles(database.find_by_tag("DRAMA").unwrap()), ["A", "B"]);
+    assert_eq!(titles(database.find_by_tag("action").unwrap()), ["A"]);
+
+    // Replacing an entry replaces its tags
+    database.put("mx_a", &entry("A", &["Comedy"])).unwrap();
+    assert_eq!(titles(database.find_by_tag("drama").unwrap()), ["B"]);
+    assert_eq!(
+        database.get("mx_a").unwrap().unwrap().book.tags[0].name,
+        "Comedy"
+    );
+
+    assert!(database.remove("mx_b").unwrap());
+    assert!(!database.remove("mx_b").unwrap());
+    assert!(database.get("mx_b").unwrap().is_none());
+    assert!(database.find_by_tag("drama").unwrap().is_empty());
+    assert_eq!(database.entries().unwrap().len(), 1);
+}
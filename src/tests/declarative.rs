@@ -0,0 +1,155 @@
+use insta::assert_debug_snapshot;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use url::Url;
+
+use crate::{
+    core::http::FetchContext,
+    plugins::{
+        declarative::{self, site::SiteSpec},
+        PluginReporter,
+    },
+    schemas::book::LazyChapter,
+};
+
+fn fixture(file: &str) -> String {
+    std::fs::read_to_string(Path::new("./src/tests/declarative").join(file)).unwrap()
+}
+
+/// Html of the fake site, by url
+fn fake_site(url: &Url) -> anyhow::Result<String> {
+    let file = match (url.path(), url.query()) {
+        ("/title/42", None | Some("page=1")) => "book.html",
+        ("/title/42", Some("page=2")) => "book_page_2.html",
+        (path, _) if path.ends_with("/p2") => "chapter_page_2.html",
+        (path, _) if path.starts_with("/title/42/chapter/") => "chapter.html",
+        _ => anyhow::bail!("404 {url}"),
+    };
+    Ok(fixture(file))
+}
+
+#[tokio::test]
+async fn crawl_declarative_site() {
+    let spec = SiteSpec::load(Path::new("./src/tests/declarative/site.yaml")).unwrap();
+    let term = "https://manga.example/title/42";
+    assert!(spec.matches(term));
+    assert!(!spec.matches("https://manga.example/author/1"));
+    // Compiled once by `load`
+    assert!(spec.patterns.get().is_some());
+    assert!(spec.pages.compiled.get().is_some());
+
+    let fetched = Arc::new(Mutex::new(vec![]));
+    let book = declarative::crawl_book(
+        &spec,
+        term,
+        Some(FetchContext::default()),
+        &PluginReporter::new(term.to_string(), None),
+        |url| {
+            fetched.lock().unwrap().push(url.to_string());
+            let html = fake_site(&url);
+            async move { html }
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(book.source_id, "42");
+    assert_eq!(
+        book.chapters
+            .iter()
+            .map(|chapter| chapter.title.as_str())
+            .collect::<Vec<_>>(),
+        ["Chapter 1", "Chapter 2", "Chapter 3"]
+    );
+    // Pagination loops back to the first page, which is only fetched once more
+    assert_eq!(
+        fetched
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|url| url.contains("page="))
+            .count(),
+        2
+    );
+
+    assert_debug_snapshot!(book);
+}
+
+#[tokio::test]
+async fn crawl_declarative_site_lazily() {
+    let mut spec = SiteSpec::load(Path::new("./src/tests/declarative/site.yaml")).unwrap();
+    spec.chapters.as_mut().unwrap().lazy = true;
+    let term = "https://manga.example/title/42";
+
+    let fetched = Arc::new(Mutex::new(vec![]));
+    let fetch = |url: Url| {
+        fetched.lock().unwrap().push(url.to_string());
+        let html = fake_site(&url);
+        async move { html }
+    };
+    let book = declarative::crawl_book(
+        &spec,
+        term,
+        None,
+        &PluginReporter::new(term.to_string(), None),
+        fetch,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(book.chapters.len(), 3);
+    assert!(book.chapters.iter().all(
+        |chapter| chapter.pages.is_empty() && matches!(chapter.lazy, Some(LazyChapter::Plugin))
+    ));
+    assert!(!fetched
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|url| url.contains("/chapter/")));
+
+    let pages = declarative::crawl_chapter(&spec, &book.chapters[1], None, fetch)
+        .await
+        .unwrap();
+    assert_eq!(
+        pages
+            .iter()
+            .map(|page| (page.url.as_str(), page.filename.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            ("https://cdn.manga.example/42/1/001.jpg", "001.jpg"),
+            ("https://cdn.manga.example/42/1/002.jpg", "002.jpg"),
+            ("https://manga.example/title/42/chapter/003.jpg", "003.jpg"),
+        ]
+    );
+    assert!(fetched
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|url| url == &book.chapters[1].url));
+}
+
+#[test]
+fn declarative_site_is_validated() {
+    let spec: SiteSpec = serde_yaml::from_str(
+        r#"
+        urls: ^https://a\.b/
+        pages:
+          selector: img[
+        "#,
+    )
+    .unwrap();
+    assert!(spec.validate().is_err());
+
+    let spec: SiteSpec = serde_yaml::from_str(
+        r#"
+        urls: (
+        pages:
+          selector: img
+        "#,
+    )
+    .unwrap();
+    assert!(spec.validate().is_err());
+}
@@ -0,0 +1,169 @@
+use std::{path::PathBuf, time::Duration};
+
+use lazy_static::lazy_static;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::core::utils;
+use crate::plugins::{python::PythonPlugin, MXPlugin, PluginImpl, PluginManager, PluginReporter};
+use crate::schemas::config::Route;
+use crate::tests::ScratchDir;
+use crate::GLOBAL_CONFIG;
+
+const TERM: &str = "https://some-sauce/a/b/c";
+
+lazy_static! {
+    static ref CACHE: Mutex<()> = Mutex::new(());
+}
+
+/// Query cache of a test, fields are dropped in order so the folder is removed before the next
+/// test takes the lock
+struct TempCache {
+    _folder: ScratchDir,
+    _guard: MutexGuard<'static, ()>,
+}
+
+/// Keep the query cache out of the working directory, the folder is shared by the whole config
+/// so tests writing to it run one at a time
+async fn temp_cache() -> TempCache {
+    let guard = CACHE.lock().await;
+    let folder = ScratchDir::new("dispatch");
+    GLOBAL_CONFIG.write().unwrap().cache.folder = folder.path().to_path_buf();
+    TempCache {
+        _folder: folder,
+        _guard: guard,
+    }
+}
+
+async fn python_plugins(names: &[&str]) -> Vec<PluginImpl> {
+    let mut plugins = vec![];
+    for name in names {
+        let mut plugin = PythonPlugin {
+            name: name.to_string(),
+            workdir: Some(PathBuf::from("src/tests/plugins")),
+            version: None,
+        };
+        plugin.init().await.unwrap();
+        plugins.push(PluginImpl::Python(plugin));
+    }
+    plugins
+}
+
+async fn claim_order(manager: &PluginManager) -> Vec<String> {
+    manager
+        .explain(TERM)
+        .await
+        .into_iter()
+        .map(|claim| claim.plugin_name)
+        .collect()
+}
+
+#[tokio::test]
+async fn plugins_are_ordered_by_priority() {
+    let names = ["example", "example_stream", "failing"];
+
+    let manager = PluginManager::with_plugins(python_plugins(&names).await, &[]);
+    assert_eq!(claim_order(&manager).await, names);
+
+    // Listed plugins come first, the others keep their relative order
+    let priority = ["failing".to_string(), "unknown".to_string()];
+    let manager = PluginManager::with_plugins(python_plugins(&names).await, &priority);
+    assert_eq!(
+        claim_order(&manager).await,
+        ["failing", "example", "example_stream"]
+    );
+}
+
+#[tokio::test]
+async fn fallthrough_to_the_next_supporting_plugin() {
+    let _cache = temp_cache().await;
+    let priority = ["failing".to_string()];
+    let plugins = python_plugins(&["example", "failing"]).await;
+    let manager = PluginManager::with_plugins(plugins, &priority);
+
+    let claims = manager.explain(TERM).await;
+    assert!(claims.iter().all(|claim| *claim.verdict.as_ref().unwrap()));
+
+    // `failing` is asked first, claims the term then raises
+    let result = manager
+        .auto_fetch(
+            TERM.to_string(),
+            PluginReporter::new(TERM.to_string(), None),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result.plugin_name, "example");
+    assert!(!result.book.chapters.is_empty());
+
+    let err = manager
+        .fetch(
+            TERM.to_string(),
+            "failing".to_string(),
+            PluginReporter::new(TERM.to_string(), None),
+        )
+        .await
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("Cannot fetch"), "{err:#}");
+
+    // e.g. a batch of `example` failed, `failing` is the only one left
+    let err = manager
+        .fallthrough_fetch(
+            TERM.to_string(),
+            PluginReporter::new(TERM.to_string(), None),
+            vec![("example".to_string(), anyhow::anyhow!("Batch failed"))],
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Plugin example: Batch failed"), "{err}");
+    assert!(err.contains("Plugin failing:"), "{err}");
+}
+
+#[tokio::test]
+async fn stop_streaming_once_the_consumer_is_gone() {
+    let _cache = temp_cache().await;
+    let manager = PluginManager::with_plugins(python_plugins(&["example_stream"]).await, &[]);
+
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+    let stream = manager.stream(
+        "stream".to_string(),
+        "example_stream".to_string(),
+        PluginReporter::default(),
+        sender,
+    );
+    // e.g. the download failed after the first chapter
+    let consume = async move {
+        receiver.recv().await.unwrap();
+        receiver.recv().await.unwrap();
+    };
+    let (res, _) = tokio::time::timeout(Duration::from_secs(10), async {
+        tokio::join!(stream, consume)
+    })
+    .await
+    .expect("The plugin kept waiting for the consumer");
+    let err = res.unwrap_err();
+    assert!(
+        format!("{err:#}").contains("closed by the receiver"),
+        "{err:#}"
+    );
+
+    // The partial book is not cached
+    let storage = GLOBAL_CONFIG.read().unwrap().get_cache_storage();
+    let signature = utils::compute_query_signature("stream", "example_stream");
+    assert!(storage.get(&signature).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn reject_routes_to_missing_plugins() {
+    let manager = PluginManager::with_plugins(python_plugins(&["example"]).await, &[]);
+    let route = |plugin: &str| Route {
+        plugin: plugin.to_string(),
+        prefix: Some("ex:".to_string()),
+        ..Default::default()
+    };
+
+    assert!(manager.check_routes(&[route("example")]).is_ok());
+    let err = manager
+        .check_routes(&[route("example"), route("exemple")])
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("Invalid route #2"), "{err:#}");
+}
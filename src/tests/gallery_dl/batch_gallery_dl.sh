@@ -0,0 +1,23 @@
+#!/bin/sh
+# Mimics `gallery-dl --dump-json --input-file <file>`, one array per url
+while [ "$#" -gt 0 ]; do
+    if [ "$1" = "--input-file" ]; then
+        input="$2"
+    fi
+    shift
+done
+
+status=0
+while read -r url; do
+    case "$url" in
+        *broken*)
+            echo '[["HttpError", "404 Not Found"]]'
+            echo "[twitter][error] HttpError: 404 Not Found" >&2
+            status=4
+            ;;
+        *)
+            echo "[[2, {\"title\": \"$url\"}], [3, \"$url/1.jpg\", {\"filename\": \"1\", \"extension\": \"jpg\"}]]"
+            ;;
+    esac
+done < "$input"
+exit $status
@@ -0,0 +1,6 @@
+#!/bin/sh
+# Mimics `gallery-dl --directory <dest> --filename <format> <url>`
+[ "$4" = "{filename}.{extension}" ] || exit 1
+echo "$2/1.jpg"
+echo "# $2/2.jpg"
+echo "[downloader.http][error] 404 Not Found for $5" >&2
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Mimics an extractor that never answers
+echo "[twitter][warning] rate limited, waiting" >&2
+sleep 30
@@ -0,0 +1,185 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{Local, TimeDelta};
+use url::Url;
+
+use crate::{
+    core::http::{
+        cache::{CacheControl, HttpCache},
+        FetchContext, HttpResponse,
+    },
+    schemas::config::{AuthKind, HttpCacheOptions},
+    tests::ScratchDir,
+};
+
+fn http_cache(name: &str, dev_mode: bool) -> (HttpCache, ScratchDir) {
+    let folder = ScratchDir::new(&format!("http-{name}"));
+    let cache = HttpCache::new(HttpCacheOptions {
+        enable: true,
+        folder: folder.path().to_path_buf(),
+        dev_mode,
+        ..Default::default()
+    });
+    (cache, folder)
+}
+
+fn response(status: u16, headers: &[(&str, &str)], body: &str) -> HttpResponse {
+    HttpResponse {
+        status,
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        body: body.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn parse_cache_control_directives() {
+    let parse = |header: &str| CacheControl::parse(Some(&header.to_string()));
+    assert_eq!(
+        parse("public, max-age=600"),
+        CacheControl {
+            max_age: Some(Duration::from_secs(600)),
+            ..Default::default()
+        }
+    );
+    assert!(parse("No-Store").no_store);
+    assert!(parse("no-cache, max-age=\"60\"").no_cache);
+    assert_eq!(parse("max-age=60").max_age, Some(Duration::from_secs(60)));
+    assert_eq!(parse("max-age=soon").max_age, None);
+    assert_eq!(CacheControl::parse(None), CacheControl::default());
+}
+
+#[test]
+fn http_cache_serves_fresh_responses_and_revalidates_stale_ones() {
+    let (cache, _folder) = http_cache("revalidate", false);
+    let url = Url::parse("https://a.b/title/1").unwrap();
+    let context = FetchContext::default();
+    let now = Local::now();
+
+    let request = cache.lookup_at(&url, &context, now);
+    assert!(cache.fresh_body(&request).is_none());
+    assert!(request.conditional(context.clone()).headers.is_empty());
+    let headers = [("etag", "\"v1\""), ("cache-control", "max-age=60")];
+    let body = cache
+        .complete_at(request, response(200, &headers, "page"), now)
+        .unwrap();
+    assert_eq!(body, b"page");
+
+    let request = cache.lookup_at(&url, &context, now + TimeDelta::seconds(30));
+    assert_eq!(cache.fresh_body(&request).unwrap(), b"page");
+
+    // Stale, the next request carries the validator
+    let later = now + TimeDelta::seconds(90);
+    let request = cache.lookup_at(&url, &context, later);
+    assert!(cache.fresh_body(&request).is_none());
+    let conditional = request.conditional(context.clone());
+    assert_eq!(conditional.headers["If-None-Match"], "\"v1\"");
+    let body = cache
+        .complete_at(request, response(304, &[], ""), later)
+        .unwrap();
+    assert_eq!(body, b"page");
+
+    // Fresh again from the revalidation
+    let request = cache.lookup_at(&url, &context, later + TimeDelta::seconds(30));
+    assert_eq!(cache.fresh_body(&request).unwrap(), b"page");
+}
+
+#[test]
+fn http_cache_only_keeps_cacheable_responses() {
+    let (cache, _folder) = http_cache("storable", false);
+    let context = FetchContext::default();
+    let now = Local::now();
+    let stored = |path: &str, headers: &[(&str, &str)]| {
+        let url = Url::parse(&format!("https://a.b/{path}")).unwrap();
+        let request = cache.lookup_at(&url, &context, now);
+        cache
+            .complete_at(request, response(200, headers, path), now)
+            .unwrap();
+        cache.lookup_at(&url, &context, now)
+    };
+
+    let no_store = stored(
+        "no-store",
+        &[("cache-control", "no-store"), ("etag", "\"x\"")],
+    );
+    assert!(no_store
+        .conditional(FetchContext::default())
+        .headers
+        .is_empty());
+    let plain = stored("plain", &[("content-type", "text/html")]);
+    assert!(cache.fresh_body(&plain).is_none());
+
+    // Validators only, always revalidated
+    let validated = stored(
+        "validated",
+        &[("last-modified", "Sun, 06 Nov 1994 08:49:37 GMT")],
+    );
+    assert!(cache.fresh_body(&validated).is_none());
+    assert_eq!(
+        validated.conditional(FetchContext::default()).headers["If-Modified-Since"],
+        "Sun, 06 Nov 1994 08:49:37 GMT"
+    );
+
+    let expires = (now + TimeDelta::hours(1)).to_rfc2822();
+    let expiring = stored("expires", &[("expires", &expires)]);
+    assert_eq!(cache.fresh_body(&expiring).unwrap(), b"expires");
+}
+
+#[test]
+fn http_cache_dev_mode_keeps_everything() {
+    let (cache, _folder) = http_cache("dev", true);
+    let url = Url::parse("https://a.b/title/1").unwrap();
+    let context = FetchContext::default();
+    let now = Local::now();
+
+    let request = cache.lookup_at(&url, &context, now);
+    cache
+        .complete_at(
+            request,
+            response(200, &[("cache-control", "no-store")], "page"),
+            now,
+        )
+        .unwrap();
+    let request = cache.lookup_at(&url, &context, now + TimeDelta::days(30));
+    assert_eq!(cache.fresh_body(&request).unwrap(), b"page");
+}
+
+#[test]
+fn http_cache_key_depends_on_vary_headers() {
+    let (cache, _) = http_cache("key", false);
+    let url = Url::parse("https://a.b/title/1").unwrap();
+    let with_headers = |headers: &[(&str, &str)]| FetchContext {
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>(),
+        ..Default::default()
+    };
+
+    let plain = cache.key(&url, &FetchContext::default());
+    assert_eq!(cache.key(&url, &with_headers(&[("Referer", "x")])), plain);
+    assert_ne!(
+        cache.key(&url, &with_headers(&[("Accept", "text/html")])),
+        plain
+    );
+    assert_eq!(
+        cache.key(&url, &with_headers(&[("ACCEPT", "text/html")])),
+        cache.key(&url, &with_headers(&[("accept", "text/html")]))
+    );
+    let authenticated = FetchContext {
+        auth: Some(AuthKind::Bearer {
+            token: "secret".to_string(),
+        }),
+        ..Default::default()
+    };
+    assert_ne!(cache.key(&url, &authenticated), plain);
+    assert_ne!(
+        cache.key(
+            &Url::parse("https://a.b/title/2").unwrap(),
+            &FetchContext::default()
+        ),
+        plain
+    );
+}
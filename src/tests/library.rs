@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use crate::{
+    core::library,
+    schemas::{
+        book::CacheFile,
+        config::SidecarFormat,
+        library::{DownloadState, LibraryIndex, LibraryQuery},
+    },
+    tests::{BookBuilder, ScratchDir},
+};
+
+fn save(root: &Path, plugin: &str, saved: &CacheFile) {
+    let folder = root.join(plugin).join(&saved.book.title);
+    std::fs::create_dir_all(&folder).unwrap();
+    let file = folder.join(format!("{}.json", saved.book.source_id));
+    std::fs::write(file, serde_json::to_string(saved).unwrap()).unwrap();
+}
+
+fn download(root: &Path, plugin: &str, book: &str, chapter: &str, pages: usize) {
+    let folder = root.join(plugin).join(book).join(chapter);
+    std::fs::create_dir_all(&folder).unwrap();
+    for p in 1..=pages {
+        std::fs::write(folder.join(format!("{p:03}.jpg")), b"").unwrap();
+    }
+}
+
+#[test]
+fn index_saved_books_by_download_state() {
+    let root = ScratchDir::new("library");
+    let (metadata, downloads) = (root.join("metadata"), root.join("download"));
+
+    let complete = BookBuilder::new("Complete")
+        .author("Alice")
+        .tag("Drama")
+        .chapter("One", 2)
+        .chapter("Two", 1)
+        .chapter("Lazy", 0)
+        .saved();
+    let partial = BookBuilder::new("Partial")
+        .author("Bob")
+        .tag("drama")
+        .tag("comedy")
+        .chapter("One", 2)
+        .chapter("Two", 2)
+        .saved();
+    let missing = BookBuilder::new("Missing")
+        .author("Alice Bob")
+        .tag("comedy")
+        .chapter("One", 1)
+        .saved();
+    // Moved along with the chapters once downloaded
+    save(&downloads, "nh", &complete);
+    download(&downloads, "nh", "Complete", "One", 2);
+    download(&downloads, "nh", "Complete", "Two", 1);
+    // Sidecars are not pages, downloaded text files are
+    download(&downloads, "nh", "Complete", "Lazy", 2);
+    let lazy = downloads.join("nh").join("Complete").join("Lazy");
+    std::fs::write(lazy.join("ComicInfo.xml"), "<ComicInfo />").unwrap();
+    std::fs::write(lazy.join("notes.txt"), "Afterword").unwrap();
+    save(&metadata, "nh", &partial);
+    download(&downloads, "nh", "Partial", "One", 2);
+    download(&downloads, "nh", "Partial", "Two", 1);
+    save(&metadata, "other", &missing);
+    std::fs::write(metadata.join("other").join("Missing").join("x.json"), "{").unwrap();
+
+    let sidecars = [SidecarFormat::ComicInfo, SidecarFormat::Hydrus];
+    let (index, errors) = library::scan(&metadata, &downloads, &sidecars);
+    assert_eq!(errors.len(), 1);
+    let summary = index
+        .books
+        .iter()
+        .map(|b| {
+            (
+                b.title.as_str(),
+                b.state,
+                b.downloaded_chapters,
+                b.downloaded_pages,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("Complete", DownloadState::Complete, 3, 6),
+            ("Partial", DownloadState::Partial, 1, 3),
+            ("Missing", DownloadState::Missing, 0, 0),
+        ]
+    );
+    assert_eq!(index.books[1].pages, 4);
+    assert_eq!(index.books[1].folder, downloads.join("nh").join("Partial"));
+
+    let file = root.join("library.json");
+    index.save(&file).unwrap();
+    let index = LibraryIndex::load(&file).unwrap().unwrap();
+    assert_eq!(index.books.len(), 3);
+    assert!(LibraryIndex::load(&root.join("none.json"))
+        .unwrap()
+        .is_none());
+
+    let titles = |query: LibraryQuery| {
+        index
+            .search(&query)
+            .map(|b| b.title.clone())
+            .collect::<Vec<_>>()
+    };
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect();
+    assert_eq!(
+        titles(LibraryQuery {
+            tags: tags(&["DRAMA"]),
+            ..Default::default()
+        }),
+        ["Complete", "Partial"]
+    );
+    assert_eq!(
+        titles(LibraryQuery {
+            tags: tags(&["drama", "comedy"]),
+            ..Default::default()
+        }),
+        ["Partial"]
+    );
+    assert_eq!(
+        titles(LibraryQuery {
+            author: Some("alice".into()),
+            plugin: Some("other".into()),
+            ..Default::default()
+        }),
+        ["Missing"]
+    );
+    assert_eq!(
+        titles(LibraryQuery {
+            title: Some("~^(Complete|Missing)$".parse().unwrap()),
+            state: Some("complete".parse().unwrap()),
+            ..Default::default()
+        }),
+        ["Complete"]
+    );
+    assert_eq!(
+        titles(LibraryQuery {
+            title: Some("art".parse().unwrap()),
+            ..Default::default()
+        }),
+        ["Partial"]
+    );
+    assert!("~("
+        .parse::<crate::schemas::library::TitleFilter>()
+        .is_err());
+}
@@ -1,9 +1,197 @@
+#[cfg(test)]
+mod declarative;
+
 #[cfg(test)]
 mod gallery_dl;
 
+#[cfg(test)]
+mod yt_dlp;
+
 #[cfg(test)]
 mod parser;
 
+#[cfg(test)]
+mod selection;
+
+#[cfg(test)]
+mod sync;
+
+#[cfg(test)]
+mod watch;
+
+#[cfg(test)]
+mod cache;
+
+#[cfg(test)]
+mod http_cache;
+
+#[cfg(test)]
+mod library;
+
+#[cfg(test)]
+mod verify;
+
+#[cfg(test)]
+mod postprocess;
+
+#[cfg(test)]
+mod template;
+
+#[cfg(test)]
+mod sidecar;
+
+#[cfg(test)]
+mod dispatch;
+
+#[cfg(test)]
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+use crate::schemas::book::{Author, Book, CacheFile, Chapter, Metadata, Page, Tag};
+
+/// Folder of a test under the system temp folder, emptied when created and removed once dropped,
+/// even when the test panics
+#[cfg(test)]
+pub struct ScratchDir(PathBuf);
+
+#[cfg(test)]
+impl ScratchDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("mx-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+#[cfg(test)]
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Chapter `number` with a page per filename, page urls are below the chapter url
+#[cfg(test)]
+pub fn chapter(number: u32, url: &str, filenames: &[&str]) -> Chapter {
+    Chapter {
+        title: format!("Chapter {number}"),
+        url: url.to_string(),
+        number,
+        pages: filenames
+            .iter()
+            .enumerate()
+            .map(|(p, filename)| Page {
+                number: p as u32 + 1,
+                url: match url.is_empty() {
+                    true => String::new(),
+                    false => format!("{url}/{filename}"),
+                },
+                filename: filename.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Book of the tests, chapters are numbered in the order they are added
+#[cfg(test)]
+pub struct BookBuilder(Book);
+
+#[cfg(test)]
+impl BookBuilder {
+    /// The source id is the lowercased title
+    pub fn new(title: &str) -> Self {
+        Self(Book {
+            title: title.to_string(),
+            source_id: title.to_lowercase(),
+            ..Default::default()
+        })
+    }
+
+    pub fn source_id(mut self, source_id: &str) -> Self {
+        self.0.source_id = source_id.to_string();
+        self
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.0.url = url.to_string();
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.0.description = description.to_string();
+        self
+    }
+
+    pub fn author(mut self, name: &str) -> Self {
+        self.0.authors.push(Author {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn tag(mut self, name: &str) -> Self {
+        self.0.tags.push(Tag {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn metadata(mut self, label: &str, content: serde_json::Value) -> Self {
+        self.0.metadata.push(Metadata {
+            label: label.to_string(),
+            content,
+        });
+        self
+    }
+
+    /// Chapter of `pages` pages named `001.jpg`, `002.jpg`..
+    pub fn chapter(self, title: &str, pages: usize) -> Self {
+        let filenames = (1..=pages)
+            .map(|p| format!("{p:03}.jpg"))
+            .collect::<Vec<_>>();
+        let filenames = filenames.iter().map(String::as_str).collect::<Vec<_>>();
+        self.chapter_of(title, &filenames)
+    }
+
+    /// Chapter with a page per filename
+    pub fn chapter_of(mut self, title: &str, filenames: &[&str]) -> Self {
+        let number = self.0.chapters.len() as u32 + 1;
+        self.0.chapters.push(Chapter {
+            title: title.to_string(),
+            ..chapter(number, "", filenames)
+        });
+        self
+    }
+
+    pub fn build(self) -> Book {
+        self.0
+    }
+
+    /// Metadata file of the book
+    pub fn saved(self) -> CacheFile {
+        CacheFile {
+            engine: "mx-scraper".to_string(),
+            date: "2026-10-18".to_string(),
+            selection: None,
+            plugin: None,
+            book: self.0,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
@@ -15,9 +203,13 @@ mod test {
     use crate::core::http::{basic::BasicRequestResolver, ContextProvider, MxScraperHttpClient};
     use crate::core::utils;
     use crate::plugins::python::PythonPlugin;
-    use crate::plugins::MXPlugin;
-    use crate::schemas::book::Book;
+    use crate::plugins::{MXPlugin, PluginReporter};
+    use crate::schemas::book::{
+        Book, BookAssembler, BookChunk, Chapter, LazyChapter, Page, ParseLinkHint,
+    };
+    use crate::schemas::config::{Config, Route};
     use crate::schemas::cookies::NetscapeCookie;
+    use crate::tests::ScratchDir;
 
     #[test]
     fn should_work_with_old_books() {
@@ -30,16 +222,110 @@ mod test {
         let mut plugin = PythonPlugin {
             name: "example".to_string(),
             workdir: Some(PathBuf::from("src/tests/plugins")),
+            version: None,
         };
 
         let term = "https://some-sauce/a/b/c".to_string();
 
         plugin.init().await.unwrap();
+        // Computed once when loading the plugin
+        let sources = PathBuf::from("src/tests/plugins/example");
+        assert_eq!(plugin.version, utils::digest_folder(&sources, &["py"]));
+        assert!(plugin.version.is_some());
         if !plugin.is_supported(term.clone()).await.unwrap() {
             panic!("Sauce {term:?} not supported?");
         }
 
-        plugin.get_book(term).await.unwrap();
+        plugin
+            .get_book(term, PluginReporter::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn python_foreign_function_mx_get_book_as_generator() {
+        let mut plugin = PythonPlugin {
+            name: "example_stream".to_string(),
+            workdir: Some(PathBuf::from("src/tests/plugins")),
+            version: None,
+        };
+
+        plugin.init().await.unwrap();
+        let book = plugin
+            .get_book("stream".to_string(), PluginReporter::default())
+            .await
+            .unwrap();
+
+        assert_eq!(book.title, "Streamed");
+        assert_eq!(book.chapters.len(), 2);
+        assert_eq!(
+            book.chapters[1]
+                .pages
+                .iter()
+                .map(|p| (p.number, p.filename.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "1.jpg"), (2, "2.jpg"), (3, "3.jpg")]
+        );
+    }
+
+    #[tokio::test]
+    async fn python_foreign_function_mx_get_book_with_context() {
+        let mut plugin = PythonPlugin {
+            name: "example_context".to_string(),
+            workdir: Some(PathBuf::from("src/tests/plugins")),
+            version: None,
+        };
+
+        plugin.init().await.unwrap();
+        let pb = indicatif::ProgressBar::hidden();
+        let book = plugin
+            .get_book(
+                "context".to_string(),
+                PluginReporter::new("context".to_string(), Some(pb.clone())),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(book.title, "Book of context");
+        assert_eq!(pb.message(), "[1/1] book loaded");
+    }
+
+    #[test]
+    fn assemble_book_from_chunks() {
+        let mut assembler = BookAssembler::new("some term".to_string());
+        let page = |url: &str| Page {
+            url: url.to_string(),
+            ..Default::default()
+        };
+
+        // Pages before any header land in a default chapter
+        let added = assembler.push(BookChunk::Page(page("https://a.b/1.png")));
+        assert_eq!(added.len(), 1);
+        assembler.push(BookChunk::Book(Book {
+            title: "Title".to_string(),
+            ..Default::default()
+        }));
+        assembler.push(BookChunk::Chapter(Chapter {
+            title: "Second".to_string(),
+            pages: vec![page("https://a.b/x"), page("https://a.b/y")],
+            ..Default::default()
+        }));
+        let added = assembler.push(BookChunk::Page(page("https://a.b/z.gif")));
+        assert_eq!(added[0].0, 1);
+
+        let book = assembler.finish();
+        assert_eq!(book.title, "Title");
+        assert_eq!(book.chapters.len(), 2);
+        assert_eq!(book.chapters[0].pages[0].filename, "1.png");
+        assert_eq!(book.chapters[1].number, 2);
+        assert_eq!(
+            book.chapters[1]
+                .pages
+                .iter()
+                .map(|p| (p.number, p.filename.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "x"), (2, "y"), (3, "z.gif")]
+        );
     }
 
     #[test]
@@ -61,6 +347,69 @@ mod test {
         assert_eq!(bytes_a, bytes_b)
     }
 
+    #[test]
+    fn route_terms_by_prefix_host_or_regex() {
+        let routes: Vec<Route> = serde_yaml::from_str(
+            r#"
+            - plugin: gallery-dl
+              regex: '^tw:(?<id>\d+)$'
+              rewrite: 'https://x.com/i/status/${id}'
+            - plugin: images
+              prefix: "pic:"
+              rewrite: "img:$1"
+            - plugin: batoto
+              hosts: [mto.to, bato.to]
+            "#,
+        )
+        .unwrap();
+        let config = Config {
+            routing: routes,
+            ..Config::new()
+        };
+        config.validate().unwrap();
+        // Compiled once when loading, not for every term
+        assert!(config.routing.iter().all(|r| r.patterns.get().is_some()));
+
+        let route = |term: &str| {
+            config
+                .route(term)
+                .unwrap()
+                .map(|(route, rewritten)| (route.plugin, rewritten))
+        };
+        assert_eq!(
+            route("tw:123"),
+            Some((
+                "gallery-dl".to_string(),
+                "https://x.com/i/status/123".to_string()
+            ))
+        );
+        assert_eq!(
+            route("pic:https://a.b"),
+            Some(("images".to_string(), "img:https://a.b".to_string()))
+        );
+        assert_eq!(
+            route("https://www.bato.to/series/1"),
+            Some((
+                "batoto".to_string(),
+                "https://www.bato.to/series/1".to_string()
+            ))
+        );
+        assert_eq!(route("https://notbato.to/series/1"), None);
+        assert_eq!(route("tw:abc"), None);
+    }
+
+    #[test]
+    fn reject_invalid_routes() {
+        let config = Config {
+            routing: vec![Route {
+                plugin: "images".to_string(),
+                ..Default::default()
+            }],
+            ..Config::new()
+        };
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn parse_netscape_cookies_formatted_in_json() {
         let json = std::fs::read_to_string("src/tests/cookies/netscape.json").unwrap();
@@ -85,6 +434,21 @@ mod test {
         assert_eq!(sub_sizes, vec![3, 3, 3, 1]);
     }
 
+    #[test]
+    fn utils_verdict_key() {
+        assert_eq!(
+            utils::verdict_key("https://x.com/a/status/1"),
+            Some("https://x.com".to_string())
+        );
+        assert_eq!(utils::verdict_key("nh:177013"), Some("nh:".to_string()));
+        assert_eq!(
+            utils::verdict_key("img:https://www.google.com"),
+            Some("img:".to_string())
+        );
+        assert_eq!(utils::verdict_key("example"), None);
+        assert_eq!(utils::verdict_key("some title: part 2"), None);
+    }
+
     #[test]
     fn utils_resume_text() {
         let abc = "aaaaaaaaaa^^^^^^^bbbbbbbbb";
@@ -141,4 +505,148 @@ mod test {
             "https_a_b_c_posts_tags_ayumu_z_5_jpg_one_234whatever_-_- (mx_1234)"
         );
     }
+
+    #[test]
+    fn utils_fix_extensions() {
+        let webp = b"RIFF\x1a\x00\x00\x00WEBPVP8L";
+        assert_eq!(
+            utils::detect_extension(webp, Some("image/jpeg")),
+            Some("webp".into())
+        );
+        assert_eq!(
+            utils::detect_extension(b"????", Some("image/PNG; charset=binary")),
+            Some("png".into())
+        );
+        assert_eq!(
+            utils::detect_extension(b"????", Some("application/octet-stream")),
+            None
+        );
+        assert_eq!(utils::detect_extension(b"????", None), None);
+
+        assert_eq!(utils::fix_extension("1.jpg", "webp"), Some("1.webp".into()));
+        assert_eq!(utils::fix_extension("1.JPEG", "jpg"), None);
+        assert_eq!(utils::fix_extension("view", "png"), Some("view.png".into()));
+        assert_eq!(
+            utils::fix_extension("a.b.jpg", "mp4"),
+            Some("a.b.mp4".into())
+        );
+
+        let dir = ScratchDir::new("renamed");
+        std::fs::write(dir.join("1.webp"), b"").unwrap();
+        std::fs::write(dir.join("view.png"), b"").unwrap();
+        let filenames = utils::list_filenames(dir.path());
+        assert_eq!(filenames.len(), 2);
+        assert_eq!(
+            utils::find_renamed(&filenames, "1.jpg"),
+            Some("1.webp".into())
+        );
+        assert_eq!(
+            utils::find_renamed(&filenames, "view"),
+            Some("view.png".into())
+        );
+        assert_eq!(utils::find_renamed(&filenames, "2.jpg"), None);
+    }
+
+    #[test]
+    fn evaluate_intermediate_link_hints() {
+        let page: Page = serde_json::from_str(
+            r##"{"title": "", "url": "https://host.example/view/1", "number": 1, "filename": "1.jpg",
+                "intermediate_link_hint": {"selector": "#img", "attribute": "src"}}"##,
+        )
+        .unwrap();
+        assert_eq!(page.intermediate_link_hint.len(), 1);
+
+        let base = Url::parse("https://host.example/view/1").unwrap();
+        let html = r#"<img id="img"><img id="img" src="../full/1.png?a=1&amp;b=2">"#;
+        let url = page.intermediate_link_hint[0]
+            .evaluate(html, &base)
+            .unwrap();
+        assert_eq!(url.as_str(), "https://host.example/full/1.png?a=1&b=2");
+
+        let hint = ParseLinkHint {
+            selector: Some("a.download".to_string()),
+            regex: Some(r"id=(\d+)".to_string()),
+            ..Default::default()
+        };
+        let html = r#"<a class="download">no id</a><a class="download">/get?id=42</a>"#;
+        assert_eq!(
+            hint.evaluate(html, &base).unwrap().as_str(),
+            "https://host.example/view/42"
+        );
+
+        let hint = ParseLinkHint {
+            json_pointer: Some("/data/0/url".to_string()),
+            ..Default::default()
+        };
+        let json = r#"{"data": [{"url": "https://cdn.example/1.png"}]}"#;
+        assert_eq!(
+            hint.evaluate(json, &base).unwrap().as_str(),
+            "https://cdn.example/1.png"
+        );
+
+        let hint = ParseLinkHint {
+            regex: Some(r#"var src = "(?<value>[^"]+)""#.to_string()),
+            ..Default::default()
+        };
+        let script = r#"<script>var src = "https:\/\/cdn.example\/2.png";</script>"#;
+        assert_eq!(
+            hint.evaluate(script, &base).unwrap().as_str(),
+            "https://cdn.example/2.png"
+        );
+
+        let hint = ParseLinkHint {
+            selector: Some("#missing".to_string()),
+            ..Default::default()
+        };
+        assert!(hint.evaluate(html, &base).is_err());
+    }
+
+    #[test]
+    fn lazy_chapter_hints() {
+        let chapter: Chapter = serde_json::from_str(
+            r##"{"title": "Chapter 1", "description": "", "url": "https://host.example/c/1", "number": 1, "pages": [],
+                "lazy": {"hint": {"selector": ".reader img", "attribute": "data-src"}}}"##,
+        )
+        .unwrap();
+        let Some(LazyChapter::Hint(hint)) = &chapter.lazy else {
+            panic!("Expected a hint, got {:?}", chapter.lazy);
+        };
+
+        let base = Url::parse(&chapter.url).unwrap();
+        let html =
+            r#"<div class="reader"><img data-src="1.png"><img><img data-src="/2.webp"></div>"#;
+        let urls = hint.evaluate_all(html, &base).unwrap();
+        assert_eq!(
+            urls.iter().map(Url::as_str).collect::<Vec<_>>(),
+            [
+                "https://host.example/c/1.png",
+                "https://host.example/2.webp"
+            ]
+        );
+        let pages = urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| Page::from_url(i as u32 + 1, url).filename)
+            .collect::<Vec<_>>();
+        assert_eq!(pages, ["001.png", "002.webp"]);
+
+        let hint = ParseLinkHint {
+            json_pointer: Some("/images".to_string()),
+            ..Default::default()
+        };
+        let json = r#"{"images": ["https://cdn.example/1.png", "https://cdn.example/2.png"]}"#;
+        assert_eq!(hint.evaluate_all(json, &base).unwrap().len(), 2);
+
+        let chapter: Chapter = serde_json::from_str(
+            r#"{"title": "", "description": "", "url": "", "number": 1, "pages": [], "lazy": "plugin"}"#,
+        )
+        .unwrap();
+        assert!(matches!(chapter.lazy, Some(LazyChapter::Plugin)));
+        let json = serde_json::to_value(&chapter).unwrap();
+        assert_eq!(json["lazy"], "plugin");
+
+        let chapter = Chapter::default();
+        let json = serde_json::to_value(&chapter).unwrap();
+        assert!(json.get("lazy").is_none());
+    }
 }
@@ -0,0 +1,22 @@
+from typing import Dict, Any
+
+
+def mx_is_supported(term) -> bool:
+    return term == "context"
+
+
+# Functions taking a third argument are given the context of the term
+def mx_get_book(term, req, ctx) -> Dict[str, Any]:
+    ctx.log("debug", f"Reading book for {ctx.term}")
+    ctx.progress(1, 1, "book loaded")
+    return {
+        "title": f"Book of {ctx.term}",
+        "title_aliases": [],
+        "source_id": "context",
+        "description": "",
+        "authors": [],
+        "chapters": [],
+        "tags": [],
+        "metadata": [],
+        "url": term,
+    }
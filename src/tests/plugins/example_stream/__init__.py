@@ -0,0 +1,43 @@
+from typing import Dict, Any
+
+
+def mx_is_supported(term) -> bool:
+    return term == "stream"
+
+
+# The context is still given to functions taking it through *args
+def mx_get_book(term, *args):
+    ctx = args[1]
+    yield {
+        "book": {
+            "title": "Streamed",
+            "title_aliases": [],
+            "source_id": "streamed",
+            "description": "",
+            "authors": [],
+            "chapters": [],
+            "tags": [],
+            "metadata": [],
+            "url": term,
+        }
+    }
+    for c in range(2):
+        ctx.progress(c + 1, 2, "chapters")
+        yield {
+            "chapter": {
+                "title": f"Chapter {c + 1}",
+                "description": "",
+                "url": f"https://some-sauce/{c + 1}",
+                "number": c + 1,
+                "pages": [],
+            }
+        }
+        for p in range(3):
+            yield {
+                "page": {
+                    "title": "",
+                    "url": f"https://some-sauce/{c + 1}/{p + 1}.jpg",
+                    "number": 0,
+                    "filename": "",
+                }
+            }
@@ -0,0 +1,10 @@
+from typing import Dict, Any
+
+
+# Claims the same terms as the example plugin but never returns a book
+def mx_is_supported(term) -> bool:
+    return term.startswith("https://some-sauce/")
+
+
+def mx_get_book(term, req, ctx) -> Dict[str, Any]:
+    raise Exception(f"Cannot fetch {term}")
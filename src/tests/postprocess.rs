@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+use crate::{
+    core::postprocess,
+    schemas::config::{Config, PageFormat, PostProcess},
+    tests::ScratchDir,
+};
+
+fn save(dir: &Path, filename: &str, width: u32, height: u32, format: ImageFormat) {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        width,
+        height,
+        image::Rgba([200, 10, 10, 128]),
+    ));
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image,
+    };
+    image.save_with_format(dir.join(filename), format).unwrap();
+}
+
+#[test]
+fn convert_downsize_and_strip_pages() {
+    let dir = ScratchDir::new("postprocess");
+
+    save(dir.path(), "1.webp", 40, 20, ImageFormat::WebP);
+    save(dir.path(), "2.png", 300, 150, ImageFormat::Png);
+    save(dir.path(), "3.jpg", 10, 10, ImageFormat::Jpeg);
+    std::fs::write(dir.join("4.txt"), "not an image").unwrap();
+
+    let options = PostProcess {
+        format: Some(PageFormat::Jpg),
+        from: vec!["webp".to_string()],
+        ..Default::default()
+    };
+    let renamed = postprocess::process_page(dir.path(), "1.webp", &options).unwrap();
+    assert_eq!(renamed.as_deref(), Some("1.jpg"));
+    assert!(!dir.join("1.webp").exists());
+    assert_eq!(
+        infer::get_from_path(dir.join("1.jpg"))
+            .unwrap()
+            .unwrap()
+            .extension(),
+        "jpg"
+    );
+    // Not listed in `from`
+    assert_eq!(
+        postprocess::process_page(dir.path(), "2.png", &options).unwrap(),
+        None
+    );
+    assert_eq!(
+        postprocess::process_page(dir.path(), "4.txt", &options).unwrap(),
+        None
+    );
+
+    let options = PostProcess {
+        max_dimension: Some(100),
+        ..Default::default()
+    };
+    assert_eq!(
+        postprocess::process_page(dir.path(), "2.png", &options).unwrap(),
+        None
+    );
+    assert_eq!(
+        image::image_dimensions(dir.join("2.png")).unwrap(),
+        (100, 50)
+    );
+
+    // APP1 segment right after the start of image
+    let jpg = std::fs::read(dir.join("3.jpg")).unwrap();
+    let payload = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0";
+    let mut with_exif = jpg[..2].to_vec();
+    with_exif.extend_from_slice(&[0xFF, 0xE1]);
+    with_exif.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    with_exif.extend_from_slice(payload);
+    with_exif.extend_from_slice(&jpg[2..]);
+    std::fs::write(dir.join("3.jpg"), &with_exif).unwrap();
+
+    let options = PostProcess {
+        strip_exif: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        postprocess::process_page(dir.path(), "3.jpg", &options).unwrap(),
+        None
+    );
+    assert_eq!(std::fs::read(dir.join("3.jpg")).unwrap(), jpg);
+
+    // Extended WebP with an EXIF chunk, stripped without being encoded again
+    save(dir.path(), "5.webp", 10, 10, ImageFormat::WebP);
+    let webp = std::fs::read(dir.join("5.webp")).unwrap();
+    let riff = |chunks: &[u8]| {
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend_from_slice(chunks);
+        bytes
+    };
+    let vp8x = |flags: u8| {
+        let mut chunk = b"VP8X\x0a\0\0\0".to_vec();
+        chunk.extend_from_slice(&[flags, 0, 0, 0, 9, 0, 0, 9, 0, 0]);
+        chunk
+    };
+    let exif = b"EXIF\x06\0\0\0MM\0\x2a\0\0";
+    let image = &webp[12..];
+    std::fs::write(
+        dir.join("5.webp"),
+        riff(&[&vp8x(0x18)[..], image, exif].concat()),
+    )
+    .unwrap();
+    assert_eq!(
+        postprocess::process_page(dir.path(), "5.webp", &options).unwrap(),
+        None
+    );
+    assert_eq!(
+        std::fs::read(dir.join("5.webp")).unwrap(),
+        riff(&[&vp8x(0x10)[..], image].concat())
+    );
+    assert_eq!(
+        image::image_dimensions(dir.join("5.webp")).unwrap(),
+        (10, 10)
+    );
+}
+
+#[test]
+fn reject_formats_that_cannot_be_decoded() {
+    let mut config = Config::default();
+    config.postprocess.insert(
+        "_all".to_string(),
+        PostProcess {
+            from: vec!["jpeg".to_string(), "gif".to_string()],
+            ..Default::default()
+        },
+    );
+    assert!(config.validate().is_ok());
+
+    config.postprocess.insert(
+        "_all".to_string(),
+        PostProcess {
+            format: Some(PageFormat::Jpg),
+            from: vec!["webp".to_string(), "avif".to_string()],
+            ..Default::default()
+        },
+    );
+    let err = config.validate().unwrap_err();
+    assert!(format!("{err:#}").contains("\"avif\" pages cannot be decoded"));
+}
@@ -0,0 +1,72 @@
+use crate::schemas::{
+    book::{Book, Chapter, Page},
+    selection::{NumberSelection, Selection},
+};
+
+fn select(spec: &str, numbers: &[u32]) -> Vec<u32> {
+    let selection: NumberSelection = spec.parse().unwrap();
+    let mut numbers = numbers.to_vec();
+    selection.retain(&mut numbers, |n| *n);
+    numbers
+}
+
+#[test]
+fn parse_number_selections() {
+    let numbers = (1..=20).collect::<Vec<_>>();
+    assert_eq!(select("1-3,15,latest:2", &numbers), [1, 2, 3, 15, 19, 20]);
+    assert_eq!(select("18-", &numbers), [18, 19, 20]);
+    assert_eq!(select(" 4 , 2 ", &numbers), [2, 4]);
+    assert_eq!(select("latest:2", &[5, 1, 9, 7]), [9, 7]);
+    assert_eq!(select("latest:3", &[5, 1, 9, 7, 5]), [5, 9, 7, 5]);
+    assert!(select("30-40", &numbers).is_empty());
+
+    for invalid in ["", ",", "a", "3-1", "latest:x", "1-2-3", "-5"] {
+        assert!(
+            invalid.parse::<NumberSelection>().is_err(),
+            "{invalid:?} should be rejected"
+        );
+    }
+
+    let selection: NumberSelection = "1-10, 15,latest:3,7-".parse().unwrap();
+    assert_eq!(selection.to_string(), "1-10,15,latest:3,7-");
+}
+
+#[test]
+fn select_chapters_and_pages_of_a_book() {
+    let chapter = |number: u32, pages: u32| Chapter {
+        number,
+        title: format!("Chapter {number}"),
+        pages: (1..=pages)
+            .map(|number| Page {
+                number,
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let mut book = Book {
+        chapters: vec![chapter(1, 3), chapter(2, 3), chapter(3, 1), chapter(4, 5)],
+        ..Default::default()
+    };
+
+    let selection: Selection =
+        serde_json::from_str(r#"{"chapters": "1,latest:2", "pages": "2-"}"#).unwrap();
+    selection.apply(&mut book);
+    assert_eq!(
+        book.chapters
+            .iter()
+            .map(|c| (
+                c.number,
+                c.pages.iter().map(|p| p.number).collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        [(1, vec![2, 3]), (3, vec![]), (4, vec![2, 3, 4, 5])]
+    );
+    assert_eq!(
+        serde_json::to_string(&selection).unwrap(),
+        r#"{"chapters":"1,latest:2","pages":"2-"}"#
+    );
+
+    assert!(Selection::default().is_empty());
+    assert_eq!(serde_json::to_string(&Selection::default()).unwrap(), "{}");
+}
@@ -0,0 +1,84 @@
+use crate::{
+    core::sidecar,
+    schemas::{book::Book, config::SidecarFormat},
+    tests::{BookBuilder, ScratchDir},
+};
+
+fn book() -> Book {
+    BookBuilder::new("Tom & Jerry <Deluxe>")
+        .source_id("42")
+        .url("https://a.b/book/42")
+        .description("Cat\nand mouse")
+        .author("Someone")
+        .tag("comedy")
+        .tag("slap  stick")
+        .metadata("Language", serde_json::json!("en"))
+        .metadata("Year", serde_json::json!(1940))
+        .metadata("Raw", serde_json::json!({ "nested": true }))
+        .chapter("One", 2)
+        .chapter("Two", 2)
+        .build()
+}
+
+#[test]
+fn write_sidecars_of_downloaded_files() {
+    let folder = ScratchDir::new("sidecar");
+    // Only the first page of the first chapter is on disk
+    std::fs::create_dir_all(folder.join("One")).unwrap();
+    std::fs::write(folder.join("One/001.jpg"), b"").unwrap();
+
+    let formats = [
+        SidecarFormat::ComicInfo,
+        SidecarFormat::Opf,
+        SidecarFormat::Hydrus,
+        SidecarFormat::JsonLd,
+    ];
+    sidecar::write_sidecars(&formats, "nh", &book(), folder.path()).unwrap();
+    let read = |path: &str| std::fs::read_to_string(folder.join(path)).unwrap();
+
+    let comic_info = read("One/ComicInfo.xml");
+    assert!(comic_info.contains("<Series>Tom &amp; Jerry &lt;Deluxe&gt;</Series>"));
+    assert!(comic_info.contains("<Number>1</Number>"));
+    assert!(comic_info.contains("<Year>1940</Year>"));
+    assert!(comic_info.contains("<LanguageISO>en</LanguageISO>"));
+    assert!(comic_info.contains("<Page Image=\"0\" Type=\"FrontCover\" ImageFile=\"001.jpg\" />"));
+    assert!(!folder.join("Two").exists());
+
+    let opf = read("metadata.opf");
+    assert!(opf.contains("<dc:identifier id=\"uid\" opf:scheme=\"nh\">42</dc:identifier>"));
+    assert!(opf.contains("<dc:creator opf:role=\"aut\">Someone</dc:creator>"));
+    assert!(opf.contains("<dc:subject>comedy</dc:subject>"));
+
+    assert_eq!(
+        read("One/001.jpg.txt").lines().collect::<Vec<_>>(),
+        [
+            "title:Tom & Jerry <Deluxe>",
+            "chapter:1",
+            "page:1",
+            "site:nh",
+            "creator:Someone",
+            "comedy",
+            "slap stick",
+            "language:en",
+            "year:1940",
+        ]
+    );
+    assert!(!folder.join("One/002.jpg.txt").exists());
+
+    let json_ld = serde_json::from_str::<serde_json::Value>(&read("metadata.jsonld")).unwrap();
+    assert_eq!(json_ld["@type"], "Book");
+    assert_eq!(json_ld["author"][0]["name"], "Someone");
+    assert_eq!(json_ld["inLanguage"], "en");
+    assert_eq!(json_ld["hasPart"][1]["position"], 2);
+
+    let chapter = &book().chapters[0];
+    assert!(sidecar::is_sidecar(&formats, chapter, "ComicInfo.xml"));
+    assert!(sidecar::is_sidecar(&formats, chapter, "001.jpg.txt"));
+    assert!(!sidecar::is_sidecar(&formats, chapter, "001.jpg"));
+    assert!(!sidecar::is_sidecar(&formats, chapter, "notes.txt"));
+    assert!(!sidecar::is_sidecar(
+        &[SidecarFormat::ComicInfo],
+        chapter,
+        "001.jpg.txt"
+    ));
+}
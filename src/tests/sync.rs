@@ -0,0 +1,106 @@
+use crate::{
+    core::sync::{BookDiff, ChapterState},
+    schemas::book::{Book, Chapter, LazyChapter, Page},
+    tests::chapter,
+};
+
+#[test]
+fn diff_chapters_against_saved_metadata() {
+    let previous = vec![
+        chapter(1, "https://a.b/c/1", &["001.jpg", "002.jpg"]),
+        chapter(2, "https://a.b/c/2", &["001.jpg"]),
+        chapter(3, "https://a.b/c/3", &["001.jpg"]),
+        chapter(4, "https://a.b/c/4", &["001.jpg"]),
+    ];
+    let mut lazy = chapter(4, "https://a.b/c/4", &[]);
+    lazy.lazy = Some(LazyChapter::Plugin);
+    let current = vec![
+        // Page urls differ only by a renewed token
+        Chapter {
+            pages: chapter(1, "https://a.b/c/1", &["001.jpg", "002.jpg"])
+                .pages
+                .into_iter()
+                .map(|page| Page {
+                    url: format!("{}-renewed", page.url),
+                    ..page
+                })
+                .collect(),
+            ..chapter(1, "https://a.b/c/1", &[])
+        },
+        chapter(2, "https://a.b/c/2", &["001.jpg", "002.jpg"]),
+        chapter(3, "https://a.b/c/3-reupload", &["001.jpg"]),
+        lazy,
+        chapter(5, "https://a.b/c/5", &["001.jpg"]),
+    ];
+
+    let diff = BookDiff::new(&previous, &current);
+    assert_eq!(
+        diff.chapters
+            .iter()
+            .map(|diff| (diff.state, diff.previous))
+            .collect::<Vec<_>>(),
+        [
+            (ChapterState::Unchanged, Some(0)),
+            (ChapterState::Changed, Some(1)),
+            (ChapterState::Changed, Some(2)),
+            (ChapterState::Unchanged, Some(3)),
+            (ChapterState::New, None),
+        ]
+    );
+    assert!(diff.removed.is_empty());
+    assert!(!diff.is_up_to_date());
+    assert_eq!(diff.count(ChapterState::Changed), 2);
+
+    let book = Book {
+        chapters: current.clone(),
+        ..Default::default()
+    };
+    let pending = diff.pending(&book);
+    assert_eq!(
+        pending
+            .chapters
+            .iter()
+            .map(|c| c.number)
+            .collect::<Vec<_>>(),
+        [2, 3, 5]
+    );
+
+    assert!(BookDiff::new(&previous, &previous).is_up_to_date());
+    assert!(BookDiff::new(&[], &[]).is_up_to_date());
+}
+
+#[test]
+fn merge_synced_chapters() {
+    let previous = vec![
+        chapter(1, "https://a.b/c/1", &["001.jpg"]),
+        chapter(2, "https://a.b/c/2", &["001.jpg"]),
+        chapter(9, "https://a.b/c/gone", &["001.jpg"]),
+    ];
+    let current = vec![
+        chapter(1, "https://a.b/c/1", &["001.jpg"]),
+        chapter(2, "https://a.b/c/2", &["001.jpg", "002.jpg"]),
+        chapter(3, "https://a.b/c/3", &[]),
+        chapter(4, "https://a.b/c/4", &["001.jpg"]),
+    ];
+    let diff = BookDiff::new(&previous, &current);
+    assert_eq!(diff.removed, [2]);
+
+    // Chapter 3 was resolved while downloading, chapter 4 was not selected
+    let saved = vec![
+        chapter(2, "https://a.b/c/2", &["001.jpg", "002.jpg"]),
+        chapter(3, "https://a.b/c/3", &["001.png"]),
+    ];
+    let merged = diff.merge(&previous, &current, &saved);
+    assert_eq!(
+        merged
+            .iter()
+            .map(|c| (c.url.as_str(), c.pages.len()))
+            .collect::<Vec<_>>(),
+        [
+            ("https://a.b/c/1", 1),
+            ("https://a.b/c/2", 2),
+            ("https://a.b/c/3", 1),
+            ("https://a.b/c/gone", 1),
+        ]
+    );
+}
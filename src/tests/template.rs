@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use crate::schemas::{
+    book::{Author, Book, Chapter, Metadata, Page},
+    config::PathTemplates,
+    template::{PathTemplate, TemplateContext, TemplateScope},
+};
+
+fn template(s: &str) -> PathTemplate {
+    s.parse().unwrap()
+}
+
+#[test]
+fn parse_and_validate_templates() {
+    for valid in [
+        "{plugin}/{author}/{title} ({id})",
+        "{chapter.number:03} - {chapter.title}",
+        "{page.number:04}.{ext}",
+        "{meta.Language}/{chapter.meta.group}",
+        "static",
+    ] {
+        assert_eq!(template(valid).to_string(), valid);
+    }
+
+    for invalid in [
+        "",
+        "/{title}",
+        "{title}/",
+        "{plugin}//{title}",
+        "../{title}",
+        "{plugin}\\{title}",
+        "{title",
+        "title}",
+        "{unknown}",
+        "{meta.}",
+        "{title:03}",
+        "{page.number:x}",
+    ] {
+        assert!(invalid.parse::<PathTemplate>().is_err(), "{invalid:?}");
+    }
+
+    assert!(template("{plugin}/{title} ({id})")
+        .validate(TemplateScope::Book, false)
+        .is_ok());
+    assert!(template("{title}/{chapter.number}")
+        .validate(TemplateScope::Book, false)
+        .is_err());
+    assert!(template("{chapter.title}/{page.number}")
+        .validate(TemplateScope::Chapter, false)
+        .is_err());
+    assert!(template("{id}")
+        .validate(TemplateScope::Page, true)
+        .is_err());
+    assert!(template("{chapter.number}/{page.number}.{ext}")
+        .validate(TemplateScope::Page, true)
+        .is_err());
+
+    let paths = serde_yaml::from_str::<PathTemplates>(
+        "book: '{plugin}/{author}/{title} [{source_id}]'\npage: '{page.number:04}.{ext}'",
+    )
+    .unwrap();
+    assert!(paths.validate().is_ok());
+    // Two books with the same title would share a folder
+    let paths = serde_yaml::from_str::<PathTemplates>("book: '{plugin}/{author}/{title}'").unwrap();
+    assert!(paths.validate().is_err());
+    assert!(serde_yaml::from_str::<PathTemplates>("page: '{nope}'").is_err());
+    let paths = serde_yaml::from_str::<PathTemplates>("page: '{chapter.title}/{ext}'").unwrap();
+    assert!(paths.validate().is_err());
+    let paths = serde_yaml::from_str::<PathTemplates>("metadata: '{source_id}.txt'").unwrap();
+    assert!(paths.validate().is_err());
+}
+
+#[test]
+fn render_templates() {
+    let book = Book {
+        title: "A/B: the. \"Book\"".to_string(),
+        source_id: "123".to_string(),
+        authors: vec![Author {
+            name: "Some/One".to_string(),
+            ..Default::default()
+        }],
+        metadata: vec![Metadata {
+            label: "Languages".to_string(),
+            content: serde_json::json!(["en", "ja"]),
+        }],
+        ..Default::default()
+    };
+    let chapter = Chapter {
+        title: "Ch. 7: End?".to_string(),
+        number: 7,
+        ..Default::default()
+    };
+    let page = Page {
+        filename: "scan_12.webp".to_string(),
+        number: 12,
+        ..Default::default()
+    };
+    let context = TemplateContext {
+        plugin: "nh",
+        id: Some("0123456789"),
+        book: &book,
+        chapter: Some(&chapter),
+        page: Some(&page),
+    };
+
+    assert_eq!(
+        template("{plugin}/{author}/{title} ({id})").render(&context),
+        PathBuf::from("nh/Some_One/A_B_ the_ _Book_ (0123456789)")
+    );
+    assert_eq!(
+        template("{chapter.number:03} - {chapter.title}").render(&context),
+        PathBuf::from("007 - Ch. 7_ End_")
+    );
+    assert_eq!(
+        template("{page.number:04}.{ext}").render(&context),
+        PathBuf::from("0012.webp")
+    );
+    assert_eq!(
+        template("{meta.languages}/{page.filename}").render(&context),
+        PathBuf::from("en, ja/scan_12.webp")
+    );
+    // Empty values never collapse a folder
+    assert_eq!(
+        template("{meta.missing}/{chapter.meta.missing}.").render(&context),
+        PathBuf::from("_/_")
+    );
+}
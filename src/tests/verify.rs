@@ -0,0 +1,97 @@
+use crate::{
+    core::verify::{self, Problem},
+    schemas::{config::PathTemplates, template::PathTemplate},
+    tests::{BookBuilder, ScratchDir},
+};
+
+const JPG: &[u8] = &[
+    0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F', 0, 0xFF, 0xD9,
+];
+const PNG: &[u8] = &[
+    0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42,
+    0x60, 0x82,
+];
+
+#[test]
+fn verify_pages_against_the_metadata() {
+    let root = ScratchDir::new("verify");
+    let folder = root.join("download").join("nh").join("Book");
+    let write = |path: &str, bytes: &[u8]| {
+        let path = folder.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, bytes).unwrap();
+    };
+
+    write("One/001.jpg", JPG);
+    write("One/002.jpg", b"");
+    write("One/003.jpg", &JPG[..JPG.len() - 2]);
+    write("One/004.jpg", PNG);
+    write("One/005.png", PNG);
+    write("One/006.txt", b"not an image");
+    // Pages are only known on disk
+    write("Two/a.gif", b"GIF89a\x01\x00");
+    write("Two/b.png", PNG);
+
+    let saved = BookBuilder::new("Book")
+        .chapter_of(
+            "One",
+            &[
+                "001.jpg", "002.jpg", "003.jpg", "004.jpg", "005.png", "006.txt", "007.jpg",
+            ],
+        )
+        .chapter_of("Two", &[])
+        .chapter("Three", 1)
+        .saved();
+    let broken = verify::verify_book(&saved, "test", &folder)
+        .into_iter()
+        .map(|file| {
+            (
+                file.chapter,
+                file.page,
+                file.path.file_name().unwrap().to_string_lossy().to_string(),
+                file.problem,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mismatch = Problem::Mismatch {
+        detected: "png".to_string(),
+    };
+    assert_eq!(
+        broken,
+        vec![
+            (0, Some(1), "002.jpg".to_string(), Problem::Empty),
+            (0, Some(2), "003.jpg".to_string(), Problem::Truncated),
+            (0, Some(3), "004.jpg".to_string(), mismatch),
+            (0, Some(6), "007.jpg".to_string(), Problem::Missing),
+            (1, None, "a.gif".to_string(), Problem::Truncated),
+            (2, Some(0), "001.jpg".to_string(), Problem::Missing),
+        ]
+    );
+
+    let temp = root.join("temp");
+    std::fs::create_dir_all(temp.join("nh").join("Stale").join("One")).unwrap();
+    std::fs::write(temp.join("nh").join("Stale").join("One").join("1.jpg"), JPG).unwrap();
+    std::fs::write(temp.join("nh").join("Stale").join("book.json"), "{}").unwrap();
+    let leftovers = verify::temp_leftovers(&temp, PathTemplates::default().book_depth());
+    assert_eq!(leftovers, vec![(temp.join("nh").join("Stale"), 2)]);
+    assert!(verify::temp_leftovers(&root.join("none"), 2).is_empty());
+
+    // Author folders are not book folders
+    let template = "{plugin}/{author}/{title} {id}"
+        .parse::<PathTemplate>()
+        .unwrap();
+    let authored = root.join("authored");
+    for title in ["Stale", "Other"] {
+        let book_folder = authored.join("nh").join("Alice").join(title);
+        std::fs::create_dir_all(book_folder.join("One")).unwrap();
+        std::fs::write(book_folder.join("One").join("1.jpg"), JPG).unwrap();
+    }
+    let leftovers = verify::temp_leftovers(&authored, template.depth());
+    assert_eq!(
+        leftovers,
+        vec![
+            (authored.join("nh").join("Alice").join("Other"), 1),
+            (authored.join("nh").join("Alice").join("Stale"), 1),
+        ]
+    );
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use chrono::{Local, TimeDelta};
+
+use crate::schemas::{
+    book::{Book, Chapter},
+    watch::{Interval, Watchlist},
+};
+
+#[test]
+fn parse_watch_intervals() {
+    let parse = |s: &str| s.parse::<Interval>().map(|interval| interval.0.as_secs());
+    assert_eq!(parse("90").unwrap(), 90);
+    assert_eq!(parse("30m").unwrap(), 30 * 60);
+    assert_eq!(parse("6h").unwrap(), 6 * 60 * 60);
+    assert_eq!(parse(" 2w ").unwrap(), 14 * 24 * 60 * 60);
+    for invalid in ["", "0h", "h", "1y", "-1d", "99999999999999w"] {
+        assert!(parse(invalid).is_err(), "{invalid:?} should be rejected");
+    }
+
+    assert_eq!("1440m".parse::<Interval>().unwrap().to_string(), "1d");
+    assert_eq!("90m".parse::<Interval>().unwrap().to_string(), "90m");
+    assert_eq!(Interval(Duration::from_secs(61)).to_string(), "61s");
+}
+
+#[test]
+fn watchlist_entries_are_due_after_their_interval() {
+    let now = Local::now();
+    let mut watchlist: Watchlist = serde_yaml::from_str(
+        r#"
+        entries:
+          - term: https://a.b/title/1
+            interval: 6h
+          - term: https://a.b/title/2
+            plugin: batoto
+            interval: 3600
+        "#,
+    )
+    .unwrap();
+    assert!(watchlist.entries.iter().all(|entry| entry.is_due(now)));
+    assert_eq!(watchlist.next_due_in(now), Some(Duration::ZERO));
+
+    let book = Book {
+        title: "Title 1".to_string(),
+        chapters: (1..=2)
+            .map(|number| Chapter {
+                number,
+                url: format!("https://a.b/chapter/{number}"),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let entry = watchlist.find_mut("https://a.b/title/1").unwrap();
+    entry.record(&book, now - TimeDelta::hours(1));
+    assert!(!entry.is_due(now));
+    assert!(entry.is_due(now + TimeDelta::hours(5)));
+
+    // New chapter, and a known one moved to another url
+    let mut book = book;
+    book.chapters[1].url = "https://a.b/chapter/2-fixed".to_string();
+    book.chapters.push(Chapter {
+        number: 3,
+        url: "https://a.b/chapter/3".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(entry.unknown_chapters(&book), [1, 2]);
+    entry.record(&book, now);
+    assert_eq!(entry.chapters.len(), 4);
+    assert!(entry.unknown_chapters(&book).is_empty());
+
+    // Numbered by position, newest first: the new chapter takes an existing number
+    let mut renumbered = book.clone();
+    renumbered.chapters.insert(
+        0,
+        Chapter {
+            number: 1,
+            url: "https://a.b/chapter/4".to_string(),
+            ..Default::default()
+        },
+    );
+    assert_eq!(entry.unknown_chapters(&renumbered), [0]);
+    // Without an url, only the number is left
+    renumbered.chapters[0].url.clear();
+    assert!(entry.unknown_chapters(&renumbered).is_empty());
+
+    entry.record_error(&anyhow::anyhow!("timed out"), now);
+    assert_eq!(entry.last_error.as_deref(), Some("timed out"));
+
+    let interval = "1d".parse().unwrap();
+    let entry = watchlist.upsert("https://a.b/title/2", None, interval);
+    assert_eq!(entry.plugin.as_deref(), Some("batoto"));
+    assert_eq!(entry.interval, interval);
+    watchlist.upsert("https://a.b/title/3", None, interval);
+    assert_eq!(watchlist.entries.len(), 3);
+    assert!(watchlist.remove("https://a.b/title/3"));
+    assert!(!watchlist.remove("https://a.b/title/3"));
+
+    let yaml = serde_yaml::to_string(&watchlist).unwrap();
+    let reloaded: Watchlist = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(reloaded.entries[0].chapters, watchlist.entries[0].chapters);
+    assert_eq!(
+        reloaded.entries[0].last_check,
+        watchlist.entries[0].last_check
+    );
+    assert_eq!(reloaded.entries[1].interval.to_string(), "1d");
+}
@@ -0,0 +1,205 @@
+use insta::assert_debug_snapshot;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    core::http::FetchContext,
+    plugins::{
+        context::{ContextArgs, HeaderArgs},
+        process,
+        yt_dlp::{self, schema::VideoInfo, ExtraConfig, YtDlpPlugin},
+        DownloadEvent, MXPlugin,
+    },
+    schemas::{
+        book::{Chapter, Metadata, Page},
+        config::AuthKind,
+    },
+    tests::ScratchDir,
+};
+
+fn read_info(file: &str) -> VideoInfo {
+    let file = Path::new("./src/tests/yt_dlp").join(file);
+    let content = std::fs::read_to_string(file).unwrap();
+    serde_json::from_str(&content).unwrap()
+}
+
+#[test]
+fn map_yt_dlp_playlist() {
+    let info = read_info("playlist.json");
+    let book = yt_dlp::generate_book("https://www.youtube.com/playlist?list=PLx", &info);
+
+    assert_eq!(book.chapters.len(), 2);
+    let merged = &book.chapters[0];
+    assert_eq!(
+        merged
+            .pages
+            .iter()
+            .map(|page| page.filename.as_str())
+            .collect::<Vec<_>>(),
+        [
+            "Big Buck Bunny 60fps 4K [aqz-KE-bpKQ]_f137.mp4",
+            "Big Buck Bunny 60fps 4K [aqz-KE-bpKQ]_f140.m4a"
+        ]
+    );
+    let context = merged.pages[0].fetch_context.as_ref().unwrap();
+    assert_eq!(context.user_agent.as_deref(), Some("Mozilla/5.0"));
+    assert_eq!(context.headers.get("Accept").unwrap(), "*/*");
+
+    assert_debug_snapshot!(book);
+}
+
+#[test]
+fn yt_dlp_extra_config_formats() {
+    let cfg = HashMap::from([
+        ("format".to_string(), "bv*+ba/b".to_string()),
+        (
+            "format.youtube.com".to_string(),
+            "bv*[height<=720]+ba".to_string(),
+        ),
+        ("format.Vimeo.com".to_string(), "best".to_string()),
+        ("timeout".to_string(), "0".to_string()),
+    ]);
+    let config = ExtraConfig::from_map(&cfg).unwrap();
+    assert_eq!(config.timeout, None);
+    assert_eq!(config.probe_timeout, Some(Duration::from_secs(30)));
+    // Unknown hosts are only probed on demand
+    assert!(!config.probe);
+
+    let format_for = |term: &str| config.format_for(term).map(String::as_str);
+    assert_eq!(
+        format_for("https://www.youtube.com/watch?v=a"),
+        Some("bv*[height<=720]+ba")
+    );
+    assert_eq!(format_for("https://vimeo.com/1"), Some("best"));
+    assert_eq!(format_for("https://example.com/a.mp4"), Some("bv*+ba/b"));
+    assert_eq!(format_for("not an url"), Some("bv*+ba/b"));
+
+    let cfg = HashMap::from([("forward_context".to_string(), "maybe".to_string())]);
+    assert!(ExtraConfig::from_map(&cfg).is_err());
+}
+
+#[test]
+fn yt_dlp_args_from_fetch_context() {
+    let context = FetchContext {
+        user_agent: Some("mx-scraper/test".to_string()),
+        headers: HashMap::from([("Accept".to_string(), "*/*".to_string())]),
+        auth: Some(AuthKind::Bearer {
+            token: "token".to_string(),
+        }),
+        ..Default::default()
+    };
+    let ctx_args = ContextArgs::new(
+        &context,
+        Some("socks5://127.0.0.1"),
+        &["https://x.com/a"],
+        HeaderArgs::AddHeader,
+    )
+    .unwrap();
+    assert_eq!(
+        ctx_args.args,
+        [
+            "--user-agent",
+            "mx-scraper/test",
+            "--add-header",
+            "Accept:*/*",
+            "--add-header",
+            "Authorization:Bearer token",
+            "--proxy",
+            "socks5://127.0.0.1",
+        ]
+    );
+
+    let mut command = tokio::process::Command::new("yt-dlp");
+    command.args(&ctx_args.args).args(["-p", "secret"]);
+    assert_eq!(
+        process::print_command_invocation(&command),
+        "yt-dlp --user-agent mx-scraper/test --add-header Accept:*/* \
+        --add-header Authorization:*** --proxy socks5://127.0.0.1 -p ***"
+    );
+}
+
+#[tokio::test]
+async fn yt_dlp_download_chapter_reports_progress_events() {
+    let plugin = YtDlpPlugin {
+        name: "yt-dlp".to_string(),
+        extra_config: ExtraConfig {
+            bin: PathBuf::from("./src/tests/yt_dlp/fake_yt_dlp.sh"),
+            forward_context: false,
+            ..Default::default()
+        },
+    };
+    let page = |filename: &str, format_id: &str| Page {
+        filename: filename.to_string(),
+        metadata: vec![Metadata {
+            label: filename.to_string(),
+            content: serde_json::json!({ "format_id": format_id }),
+        }],
+        ..Default::default()
+    };
+    let chapter = Chapter {
+        url: "https://www.youtube.com/watch?v=abc".to_string(),
+        pages: vec![
+            page("Video [abc].f137.mp4", "137"),
+            page("Video [abc].f140.m4a", "140"),
+        ],
+        ..Default::default()
+    };
+    let dest = ScratchDir::new("yt-dlp");
+
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    plugin
+        .download_chapter(&chapter, dest.path(), sender)
+        .await
+        .unwrap();
+
+    // Saved under the filenames of the metadata, one format each
+    let read = |filename: &str| std::fs::read_to_string(dest.join(filename)).unwrap();
+    assert_eq!(read("Video [abc].f137.mp4"), "137\n");
+    assert_eq!(read("Video [abc].f140.m4a"), "140\n");
+
+    let mut saved = vec![];
+    let mut failed = vec![];
+    let mut messages = vec![];
+    while let Some(event) = receiver.recv().await {
+        match event {
+            DownloadEvent::Progress { filename } => saved.push(filename),
+            DownloadEvent::Failed { error, .. } => failed.push(error),
+            DownloadEvent::Message(message) => messages.push(message),
+        }
+    }
+    assert_eq!(saved, vec!["Video [abc].f137.mp4", "Video [abc].f140.m4a"]);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(messages.len(), 2);
+}
+
+#[tokio::test]
+async fn yt_dlp_claims_known_hosts_without_probing() {
+    let cfg = HashMap::from([
+        ("bin".to_string(), "./does-not-exist".to_string()),
+        ("hosts".to_string(), "Example.tv, ".to_string()),
+    ]);
+    let mut plugin = YtDlpPlugin {
+        name: "yt-dlp".to_string(),
+        extra_config: ExtraConfig::from_map(&cfg).unwrap(),
+    };
+    assert_eq!(plugin.extra_config.hosts, vec!["example.tv"]);
+
+    // The binary is never spawned for these
+    let supported = |term: &'static str| plugin.is_supported(term.to_string());
+    assert!(supported("https://m.youtube.com/watch?v=abc")
+        .await
+        .unwrap());
+    assert!(supported("https://live.example.tv/1").await.unwrap());
+    assert!(!supported("ftp://youtube.com/a").await.unwrap());
+    assert!(!supported("not an url").await.unwrap());
+    assert!(!supported("https://example.com/a").await.unwrap());
+
+    plugin.extra_config.probe = true;
+    assert!(plugin
+        .is_supported("https://example.com/a".to_string())
+        .await
+        .is_err());
+}
@@ -0,0 +1,15 @@
+#!/bin/sh
+# Mimics `yt-dlp [--format <id>] --output <file> ... -- <url>`, the file holds the format
+format="best"
+while [ "$#" -gt 0 ]; do
+    case "$1" in
+        --format) format="$2" ;;
+        --output) output="$2" ;;
+    esac
+    shift
+done
+echo "$format" > "$output"
+echo "WARNING: [youtube] abc: Some formats are missing" >&2
+if [ "$format" = "140" ]; then
+    echo "ERROR: [youtube] abc: Audio unavailable" >&2
+fi